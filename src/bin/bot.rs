@@ -2,10 +2,12 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use clap::Parser;
 use ctrlc::set_handler;
 use eventsource_client as es;
 use futures::TryStreamExt;
-use ircc_ai::constants::ORACLE_QUERY_URL_DEFAULT;
+use ircc_ai::config::{Binary, BotConfig, Config, ConfigArgs};
+use ircc_ai::constants::API_KEY_HEADER;
 use serde::Serialize;
 use teloxide::prelude::*;
 use tokio::sync::mpsc;
@@ -14,6 +16,13 @@ extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
 
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+	#[command(flatten)]
+	config: ConfigArgs
+}
+
 #[derive(Serialize)]
 pub struct Query {
 	pub query: String
@@ -30,6 +39,10 @@ enum EventMessage {
 #[tokio::main]
 async fn main() {
 	pretty_env_logger::init();
+
+	let args = Args::parse();
+	let config = Arc::new(Config::init(&args.config, Binary::Bot).bot);
+
 	info!("Starting IRCC bot...");
 
 	// Initialize Ctrl+C signal handling.
@@ -45,7 +58,7 @@ async fn main() {
 	let (tx, mut rx) = mpsc::channel(32);
 
 	let repl_handle = tokio::spawn(async move {
-		run().await;
+		run(config).await;
 		tx.send(()).await.expect("Error sending signal to main thread");
 	});
 
@@ -66,31 +79,38 @@ async fn main() {
 	repl_handle.await.expect("Error waiting for REPL thread");
 }
 
-async fn run() {
-	let bot = Bot::from_env();
+async fn run(config: Arc<BotConfig>) {
+	// Validated at startup
+	let token = config.telegram_token.as_ref().map(|token| token.expose().to_string()).unwrap_or_default();
+	let bot = Bot::new(token);
 	debug!("Bot started...");
 
-	teloxide::repl(bot, |bot: Bot, msg: Message| async move {
-		debug!("Received {:?} from @{:?}", msg.text(), msg.from());
+	teloxide::repl(bot, move |bot: Bot, msg: Message| {
+		let config = config.clone();
+		async move {
+			debug!("Received {:?} from @{:?}", msg.text(), msg.from());
 
-		if let Some(q) = msg.text() {
-			if q != "/start" {
-				let json_query = Query { query: q.to_string() };
-				let json_string = serde_json::to_string(&json_query).unwrap();
+			if let Some(q) = msg.text() {
+				if q != "/start" {
+					let json_query = Query { query: q.to_string() };
+					let json_string = serde_json::to_string(&json_query).unwrap();
 
-				process_query(bot, msg.chat.id, &json_string).await.log_on_error().await;
-			}
-		};
-		Ok(())
+					process_query(bot, msg.chat.id, &config, &json_string).await.log_on_error().await;
+				}
+			};
+			Ok(())
+		}
 	})
 	.await;
 }
 
-async fn process_query(bot: Bot, chat_id: ChatId, query: &str) -> Result<(), es::Error> {
-	let url = std::env::var("ORACLE_QUERY_URL").unwrap_or(ORACLE_QUERY_URL_DEFAULT.into());
+async fn process_query(bot: Bot, chat_id: ChatId, config: &BotConfig, query: &str) -> Result<(), es::Error> {
+	let mut builder = es::ClientBuilder::for_url(&config.oracle_query_url)?.header("Content-Type", "application/json")?;
+	if let Some(api_key) = &config.oracle_api_key {
+		builder = builder.header(API_KEY_HEADER, api_key.expose())?;
+	}
 
-	let client = es::ClientBuilder::for_url(&url)?
-		.header("Content-Type", "application/json")?
+	let client = builder
 		.method(String::from("POST"))
 		.body(query.to_string())
 		.reconnect(
@@ -1,20 +1,103 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
-use futures::stream::StreamExt;
+use clap::{Parser, Subcommand};
+use futures::future::try_join_all;
+use futures::stream::{self, StreamExt};
+use ircc_ai::config::{Binary, Config, ConfigArgs, CrawlConfig, ProfileConfig};
+use ircc_ai::constants::{INDEX_PROGRESS_INTERVAL_SECS, UPSERT_BATCH_SIZE, UPSERT_CONCURRENCY, VERIFY_REPORTED_PATHS};
+use ircc_ai::crawl::Crawler;
 use ircc_ai::db::qdrant::QdrantDB;
-use ircc_ai::db::RepositoryEmbeddingsDB;
+use ircc_ai::db::{RepositoryEmbeddingsDB, SearchFilter};
 use ircc_ai::embeddings::*;
-use ircc_ai::fs::embed_path;
+use ircc_ai::fs::checkpoint::Checkpoint;
+use ircc_ai::fs::progress::IndexProgress;
+use ircc_ai::fs::report::{EmbedReport, FailureCategory, FailureThresholds, FileFailure, ProfileReport};
+use ircc_ai::fs::rules::{IndexRules, ListedFile};
+use ircc_ai::fs::watch::{Changes, DocumentsWatcher};
+use ircc_ai::fs::{embed_files, embed_path, EmbedOutcome, SkipReason};
 use ircc_ai::prelude::*;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-	#[arg(short, long)]
-	path: String
+	#[command(flatten)]
+	config: ConfigArgs,
+
+	/// Index only this profile, every profile is indexed by default
+	#[arg(long)]
+	profile: Option<String>,
+
+	/// List the files that would be indexed, and why the others would be skipped, without indexing anything
+	#[arg(long)]
+	dry_run: bool,
+
+	/// Continue the interrupted run recorded in the checkpoint of each profile instead of indexing from scratch
+	#[arg(long)]
+	resume: bool,
+
+	/// Exit with an error if any file fails, whatever the configured thresholds
+	#[arg(long)]
+	strict: bool,
+
+	#[command(subcommand)]
+	command: Option<Command>
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Keep the collections of the profiles indexed by a previous run in sync with their documents, applying the files
+	/// created, modified, renamed or deleted since that run and then as they change
+	Watch,
+	/// Mirror a site as Markdown documents in the documents directory of the profile, to be indexed by a later run
+	Crawl(CrawlArgs)
+}
+
+/// Flags of `crawl`, taking precedence over the `crawl` settings
+#[derive(clap::Args, Debug)]
+struct CrawlArgs {
+	/// Page the crawl starts from, overrides `CRAWL_START_URL`
+	#[arg(long)]
+	start_url: Option<String>,
+
+	/// URL prefix of the pages to follow, may be repeated, overrides `CRAWL_ALLOWED_PREFIXES`
+	#[arg(long = "allow")]
+	allowed_prefixes: Vec<String>,
+
+	/// Pause between two requests in milliseconds, overrides `CRAWL_DELAY_MS`
+	#[arg(long)]
+	delay_ms: Option<u64>,
+
+	/// Fetch the pages robots.txt disallows
+	#[arg(long)]
+	ignore_robots_txt: bool,
+
+	/// Overrides `CRAWL_MAX_PAGES`
+	#[arg(long)]
+	max_pages: Option<usize>
+}
+
+impl CrawlArgs {
+	fn apply(&self, config: &mut CrawlConfig) {
+		if let Some(start_url) = &self.start_url {
+			config.start_url = start_url.clone();
+		}
+		if !self.allowed_prefixes.is_empty() {
+			config.allowed_prefixes = self.allowed_prefixes.clone();
+		}
+		if let Some(delay_ms) = self.delay_ms {
+			config.delay_ms = delay_ms;
+		}
+		if self.ignore_robots_txt {
+			config.respect_robots_txt = false;
+		}
+		if let Some(max_pages) = self.max_pages {
+			config.max_pages = Some(max_pages);
+		}
+	}
 }
 
 #[cfg(feature = "embed")]
@@ -24,66 +107,417 @@ async fn main() {
 
 	dotenv::dotenv().ok();
 
-	// The model is copied in the container at build time
-	let model: Arc<Onnx> = Arc::new(Onnx::new(Path::new("/model")).unwrap());
-	let db: QdrantDB = QdrantDB::initialize().unwrap();
-
 	let args = Args::parse();
+	let binary = if matches!(args.command, Some(Command::Crawl(_))) { Binary::Crawl } else { Binary::Embed };
+	let mut config = Config::init(&args.config, binary);
+
+	let profiles: Vec<ProfileConfig> = config
+		.profiles()
+		.into_iter()
+		.filter(|profile| args.profile.as_ref().map_or(true, |name| &profile.name == name))
+		.collect();
+	if profiles.is_empty() {
+		log::error!("Unknown profile {}", args.profile.unwrap_or_default());
+		exit(2);
+	}
 
-	let dir = PathBuf::from(args.path);
+	if let Some(Command::Crawl(crawl_args)) = &args.command {
+		crawl_args.apply(&mut config.crawl);
+		let [profile] = profiles.as_slice() else {
+			log::error!("Choose the profile to crawl for with --profile");
+			exit(2);
+		};
+		match crawl(&config.crawl, profile).await {
+			Ok(_) => exit(0),
+			Err(err) => {
+				log::error!("Crawling for profile {} failed: {:#}", profile.name, err);
+				exit(1);
+			}
+		}
+	}
 
-	log::info!("Calculating embeddings for {}", dir.display());
+	for profile in &profiles {
+		if !profile.documents_path.is_dir() {
+			log::error!("Documents of profile {} not found at {}", profile.name, profile.documents_path.display());
+			exit(2);
+		}
+	}
 
-	let result = embed_and_insert_embeddings(&model, &db, &dir).await;
+	let rules = IndexRules::from_config(&config.documents).unwrap();
 
-	match result {
-		Ok(_) => {
-			log::info!("Process completed successfully.");
-			exit(0);
+	if args.dry_run {
+		for profile in &profiles {
+			let checkpoint_path = checkpoint_path(&config, profile);
+			let rules = match Checkpoint::resume(&checkpoint_path, &profile.collection).await {
+				Ok(Some(checkpoint)) if args.resume => rules.skip_completed(checkpoint.completed().clone()),
+				Err(err) if args.resume => {
+					log::error!("Reading the checkpoint of profile {} failed: {}", profile.name, err);
+					exit(1);
+				}
+				_ => rules.clone()
+			};
+			if let Err(err) = list_files(&rules, profile).await {
+				log::error!("Listing the documents of profile {} failed: {}", profile.name, err);
+				exit(1);
+			}
 		}
-		Err(err) => {
-			log::error!("Process failed: {}", err);
+		exit(0);
+	}
+
+	let model: Arc<Onnx> = Arc::new(Onnx::new(&config.model.path).unwrap());
+
+	if let Some(Command::Watch) = args.command {
+		let watches = profiles.iter().map(|profile| watch_profile(&model, &config, profile, &rules));
+		if let Err(err) = try_join_all(watches).await {
+			log::error!("Watching the documents failed: {:#}", err);
 			exit(1);
 		}
+		exit(0);
+	}
+
+	let mut reports = Vec::new();
+	for profile in &profiles {
+		let db: QdrantDB = QdrantDB::initialize(&config.qdrant, &profile.collection).unwrap();
+
+		log::info!("Calculating embeddings of profile {} for {}", profile.name, profile.documents_path.display());
+
+		let job = EmbedJob {
+			profile,
+			rules: &rules,
+			checkpoint_path: checkpoint_path(&config, profile),
+			resume: args.resume
+		};
+		match embed_and_insert_embeddings(&model, &db, job).await {
+			Ok(report) => reports.push(report),
+			Err(err) => {
+				log::error!("Indexing profile {} failed: {:#}", profile.name, err);
+				reports.push(ProfileReport::aborted(&profile.name, &profile.collection, &err));
+				break;
+			}
+		}
+	}
+
+	let thresholds = FailureThresholds {
+		max_failed_percent: config.embed.max_failed_percent,
+		max_failed_files: if args.strict { Some(0) } else { config.embed.max_failed_files }
+	};
+	let report = EmbedReport::new(reports, &thresholds);
+	match report.write(&config.embed.report_path).await {
+		Ok(_) => log::info!("Report written to {}", config.embed.report_path.display()),
+		Err(err) => log::error!("Writing the report to {} failed: {}", config.embed.report_path.display(), err)
 	}
+
+	if report.passed {
+		log::info!("Process completed successfully.");
+		exit(0);
+	}
+	for violation in &report.violations {
+		log::error!("Process failed: {}", violation);
+	}
+	exit(1);
 }
 
-async fn embed_and_insert_embeddings(model: &Arc<Onnx>, db: &QdrantDB, dir: &Path) -> Result<()> {
-	let collection_exists = db.is_indexed().await?;
+async fn list_files(rules: &IndexRules, profile: &ProfileConfig) -> Result<()> {
+	let mut files = rules.walk(&profile.documents_path);
+	let mut included = 0;
+	let mut skipped = 0;
 
-	if collection_exists {
-		db.delete_collection().await?;
+	println!("Profile {} ({})", profile.name, profile.documents_path.display());
+	while let Some(file) = files.next().await {
+		match file? {
+			ListedFile::Included(path) => {
+				included += 1;
+				println!("index  {}", path.display());
+			}
+			ListedFile::Skipped(path, reason) => {
+				skipped += 1;
+				println!("skip   {}: {}", path.display(), reason);
+			}
+		}
 	}
+	println!("{} files would be indexed, {} skipped", included, skipped);
 
-	let embeddings_stream = embed_path(Arc::clone(model), dir.to_path_buf()).await;
-	let mut chunks_stream = embeddings_stream.chunks(10);
-	let mut successfully_inserted = 0;
+	Ok(())
+}
 
-	while let Some(chunk) = chunks_stream.next().await {
-		// Another alternative could be using chunk.into_iter().collect(); to convert Vec<Result<_>> to Result<Vec<_>>
-		// But if there's even a single Err(e) value in the Vec, the collection will yield that error, and any subsequent items
-		// will be ignored.
+async fn crawl(config: &CrawlConfig, profile: &ProfileConfig) -> Result<()> {
+	log::info!("Crawling {} into {}", config.start_url, profile.documents_path.display());
+	let summary = Crawler::new(config, &profile.documents_path)?.run().await?;
+	log::info!(
+		"Crawl finished: {} pages saved, {} skipped, {} failed",
+		summary.written,
+		summary.skipped,
+		summary.failed
+	);
 
-		let mut embeddings_chunk = Vec::new();
+	if summary.written == 0 {
+		return Err(anyhow::anyhow!("No page was saved"));
+	}
+	Ok(())
+}
+
+fn checkpoint_path(config: &Config, profile: &ProfileConfig) -> PathBuf {
+	config.embed.checkpoint_dir.join(format!("{}.jsonl", profile.name))
+}
+
+struct EmbedJob<'a> {
+	profile: &'a ProfileConfig,
+	rules: &'a IndexRules,
+	checkpoint_path: PathBuf,
+	resume: bool
+}
+
+async fn embed_and_insert_embeddings(model: &Arc<Onnx>, db: &QdrantDB, job: EmbedJob<'_>) -> Result<ProfileReport> {
+	let resumed = if job.resume { Checkpoint::resume(&job.checkpoint_path, db.collection()).await? } else { None };
+	let mut checkpoint = match resumed {
+		Some(checkpoint) => {
+			log::info!(
+				"Resuming from {} after {} batches, {} documents already indexed",
+				checkpoint.path().display(),
+				checkpoint.batches(),
+				checkpoint.completed().len()
+			);
+			checkpoint
+		}
+		None => {
+			if job.resume {
+				log::warn!("No checkpoint at {}, indexing from scratch", job.checkpoint_path.display());
+			}
+			if db.is_indexed().await? {
+				db.delete_collection().await?;
+			}
+			Checkpoint::create(&job.checkpoint_path, db.collection()).await?
+		}
+	};
+	// Created before the upserts, which run concurrently
+	db.create_collection().await?;
+
+	let rules = job.rules.skip_completed(checkpoint.completed().clone());
+	let progress = Arc::new(IndexProgress::default());
+	let reporter = progress.report_every(Duration::from_secs(INDEX_PROGRESS_INTERVAL_SECS));
+
+	// Batches are upserted while the next ones are embedded
+	let mut batches = embed_path(Arc::clone(model), job.profile.documents_path.clone(), &rules, Arc::clone(&progress))
+		.chunks(UPSERT_BATCH_SIZE)
+		.map(|chunk| {
+			let mut batch = Batch::default();
+			let mut embeddings_chunk = Vec::new();
+			for result in chunk {
+				match result {
+					Ok(EmbedOutcome::Embedded(embedding)) => embeddings_chunk.push(embedding),
+					Ok(EmbedOutcome::Skipped { path, reason }) => {
+						batch.skipped += 1;
+						// Listed by the walk but not indexed, unless indexed by the run being resumed
+						if reason != SkipReason::AlreadyIndexed {
+							batch.not_indexed.push(path);
+						}
+					}
+					Ok(EmbedOutcome::Failed(failure)) => batch.failures.push(failure),
+					// The directory could not be walked any further
+					Err(e) => batch.error = Some(e)
+				}
+			}
 
-		for result in chunk {
-			match result {
-				Ok(embedding) => {
-					embeddings_chunk.push(embedding);
+			let progress = Arc::clone(&progress);
+			async move {
+				let paths: Vec<String> = embeddings_chunk.iter().map(|embedding| embedding.path.clone()).collect();
+				if paths.is_empty() {
+					return batch;
 				}
-				Err(e) => {
-					log::error!("Error processing embedding: {:?}", e);
+				match db.insert_embeddings(embeddings_chunk).await {
+					Ok(_) => batch.upserted = paths,
+					Err(e) => {
+						log::error!("Upserting {} documents failed: {:#}", paths.len(), e);
+						for path in &paths {
+							progress.failed();
+							batch.failures.push(FileFailure::new(Path::new(path), FailureCategory::Upsert, &e));
+						}
+					}
 				}
+				progress.upsert.leave(paths.len());
+				batch
+			}
+		})
+		.buffered(UPSERT_CONCURRENCY);
+
+	let mut skipped = 0;
+	let mut not_indexed = HashSet::new();
+	let mut failures = Vec::new();
+	let mut result = Ok(());
+	while let Some(batch) = batches.next().await {
+		skipped += batch.skipped;
+		not_indexed.extend(batch.not_indexed);
+		failures.extend(batch.failures);
+		if let Some(e) = batch.error {
+			result = Err(e);
+			break;
+		}
+		if !batch.upserted.is_empty() {
+			if let Err(e) = checkpoint.record(batch.upserted).await {
+				result = Err(e);
+				break;
 			}
 		}
+	}
+	reporter.abort();
+	log::info!("{}", progress.report());
+	result?;
+	// Answers cached by the oracle for the previous version are no longer served
+	db.write_index_version().await?;
 
-		if !embeddings_chunk.is_empty() {
-			db.insert_embeddings(embeddings_chunk.clone()).await?;
-			successfully_inserted += embeddings_chunk.len();
+	not_indexed.extend(failures.iter().map(|failure| failure.path.clone()));
+	let mut report = ProfileReport::new(&job.profile.name, db.collection(), checkpoint.completed().len(), skipped, failures);
+	if let Err(e) = verify(db, job.rules, &job.profile.documents_path, &not_indexed).await {
+		log::error!("{:#}", e);
+		report.error = Some(format!("{:#}", e));
+	}
+	Ok(report)
+}
+
+// Outcomes of one batch of files
+#[derive(Default)]
+struct Batch {
+	upserted: Vec<String>,
+	skipped: usize,
+	// Skipped after being listed, e.g. by their loader
+	not_indexed: Vec<String>,
+	failures: Vec<FileFailure>,
+	error: Option<anyhow::Error>
+}
+
+// Every file the rules select has a point and every point a selected file, but for the files that failed or that were
+// skipped after being listed
+async fn verify(db: &QdrantDB, rules: &IndexRules, documents_path: &Path, not_indexed: &HashSet<String>) -> Result<()> {
+	let mut eligible = HashSet::new();
+	let mut files = rules.walk(documents_path);
+	while let Some(file) = files.next().await {
+		if let ListedFile::Included(path) = file? {
+			let path = path.to_string_lossy().to_string();
+			if !not_indexed.contains(&path) {
+				eligible.insert(path);
+			}
 		}
 	}
+	let indexed: HashSet<String> = db.get_file_paths(&SearchFilter::default()).await?.into_iter().collect();
+
+	let mut missing: Vec<&String> = eligible.difference(&indexed).collect();
+	let mut unexpected: Vec<&String> = indexed.difference(&eligible).collect();
+	if missing.is_empty() && unexpected.is_empty() {
+		log::info!("Verified collection {}: {} points for {} eligible documents", db.collection(), indexed.len(), eligible.len());
+		return Ok(());
+	}
 
-	log::info!("Successfully inserted {} embeddings", successfully_inserted);
+	missing.sort_unstable();
+	unexpected.sort_unstable();
+	for path in &missing {
+		log::error!("No point for {}", path);
+	}
+	for path in &unexpected {
+		log::error!("Point for {}, which is not an eligible document", path);
+	}
+	Err(anyhow::anyhow!(
+		"Collection {} does not match its documents: {} without a point{}, {} points of documents not eligible{}",
+		db.collection(),
+		missing.len(),
+		sample(&missing),
+		unexpected.len(),
+		sample(&unexpected)
+	))
+}
+
+// The first paths of a list, for the report
+fn sample(paths: &[&String]) -> String {
+	if paths.is_empty() {
+		return String::new();
+	}
+	let shown: Vec<&str> = paths.iter().take(VERIFY_REPORTED_PATHS).map(|path| path.as_str()).collect();
+	let more = paths.len().saturating_sub(VERIFY_REPORTED_PATHS);
+	if more > 0 {
+		format!(" ({} and {} more)", shown.join(", "), more)
+	} else {
+		format!(" ({})", shown.join(", "))
+	}
+}
+
+// Applies the changes to the documents of `profile` to its collection as they happen, until an error stops it
+async fn watch_profile(model: &Arc<Onnx>, config: &Config, profile: &ProfileConfig, rules: &IndexRules) -> Result<()> {
+	let db = QdrantDB::initialize(&config.qdrant, &profile.collection)?;
+	let checkpoint_path = checkpoint_path(config, profile);
+	// Documents modified after the last write of the checkpoint are embedded again
+	let since = tokio::fs::metadata(&checkpoint_path).await.and_then(|metadata| metadata.modified()).ok();
+	let mut checkpoint = Checkpoint::resume(&checkpoint_path, db.collection()).await?.ok_or_else(|| {
+		anyhow::anyhow!(
+			"No checkpoint at {}, run embed for profile {} before watching it",
+			checkpoint_path.display(),
+			profile.name
+		)
+	})?;
+	db.create_collection().await?;
+
+	// Watching starts before catching up so that no change is missed in between
+	let debounce = Duration::from_millis(config.embed.watch_debounce_ms);
+	let mut watcher = DocumentsWatcher::new(&profile.documents_path, rules.clone(), debounce)?;
+	let changes = watcher.catch_up(checkpoint.completed(), since).await?;
+	log::info!(
+		"Profile {}: catching up with {} new or modified and {} deleted documents",
+		profile.name,
+		changes.upsert.len(),
+		changes.delete.len()
+	);
+	apply_changes(model, &db, profile, &mut checkpoint, changes).await?;
+	// Once caught up, as a restart catches up from the last write of the checkpoint
+	checkpoint.compact().await?;
+
+	log::info!("Watching {} for profile {}", profile.documents_path.display(), profile.name);
+	while let Some(changes) = watcher.next_changes(checkpoint.completed()).await? {
+		apply_changes(model, &db, profile, &mut checkpoint, changes).await?;
+	}
+	Ok(())
+}
+
+// Embeds and upserts, then deletes, recording both in the checkpoint. Files failing to embed keep their previous point
+// and are retried when they change again or the watch restarts.
+async fn apply_changes(model: &Arc<Onnx>, db: &QdrantDB, profile: &ProfileConfig, checkpoint: &mut Checkpoint, changes: Changes) -> Result<()> {
+	if changes.is_empty() {
+		return Ok(());
+	}
+
+	let Changes { upsert, mut delete } = changes;
+	let files = stream::iter(upsert.into_iter().map(|path| Ok(ListedFile::Included(path)))).boxed();
+	let mut outcomes = embed_files(Arc::clone(model), profile.documents_path.clone(), files, Arc::default()).chunks(UPSERT_BATCH_SIZE);
+
+	let mut upserted = 0;
+	let mut failed = 0;
+	while let Some(chunk) = outcomes.next().await {
+		let mut embeddings = Vec::new();
+		for outcome in chunk {
+			match outcome? {
+				EmbedOutcome::Embedded(embedding) => embeddings.push(embedding),
+				// E.g. a PDF replaced by a scan, its previous text is no longer there
+				EmbedOutcome::Skipped { path, .. } if checkpoint.completed().contains(&path) => delete.push(path),
+				EmbedOutcome::Skipped { .. } => {}
+				EmbedOutcome::Failed(_) => failed += 1
+			}
+		}
+		if embeddings.is_empty() {
+			continue;
+		}
+
+		let paths: Vec<String> = embeddings.iter().map(|embedding| embedding.path.clone()).collect();
+		db.insert_embeddings(embeddings).await?;
+		upserted += paths.len();
+		checkpoint.record(paths).await?;
+	}
+
+	let deleted = delete.len();
+	if deleted > 0 {
+		db.delete_embeddings(&delete).await?;
+		checkpoint.record_deleted(delete).await?;
+	}
+	if upserted + deleted > 0 {
+		db.write_index_version().await?;
+	}
 
+	log::info!("Profile {}: {} documents upserted, {} deleted, {} failed", profile.name, upserted, deleted, failed);
 	Ok(())
 }
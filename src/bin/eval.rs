@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::Arc;
+
+use chrono::Utc;
+use clap::{Parser, ValueEnum};
+use ircc_ai::config::{Binary, Config, ConfigArgs, ProfileConfig};
+use ircc_ai::convrsation::settings::{ChatSettings, SearchSettings};
+use ircc_ai::convrsation::templates::{PromptStore, PromptVariables};
+use ircc_ai::convrsation::Assistant;
+use ircc_ai::db::qdrant::QdrantDB;
+use ircc_ai::db::RepositoryEmbeddingsDB;
+use ircc_ai::embeddings::Onnx;
+use ircc_ai::eval::answers::{answer_question, AnswerConfigurationResult, AnswerEvalRun, AnswerMetrics};
+use ircc_ai::eval::judge::{default_rubric, load_rubric, Judge};
+use ircc_ai::eval::{
+	diff, evaluate, load_configurations, load_golden_set, write_json, ConfigurationResult, EvalConfiguration, EvalRun, GoldenQuestion, NamedMetrics
+};
+use ircc_ai::llm::{self, ChatClient, ScriptedClient};
+use ircc_ai::prelude::*;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+	#[command(flatten)]
+	config: ConfigArgs,
+
+	/// Golden set, a JSONL file of `{"question", "expected_paths", "path_query", "responses", "reference_answer"}` objects
+	#[arg(long)]
+	questions: PathBuf,
+
+	/// TOML file of the `[[configurations]]` to compare, only the configured search settings by default
+	#[arg(long)]
+	configurations: Option<PathBuf>,
+
+	/// What is evaluated: the documents the search functions retrieve, or the answers of whole conversations
+	#[arg(long, value_enum, default_value_t = Mode::Retrieval)]
+	mode: Mode,
+
+	/// Cut-offs of recall and nDCG, comma separated
+	#[arg(long, value_delimiter = ',', default_value = "1,3,5,10")]
+	k: Vec<usize>,
+
+	/// Answer with the OpenAI API rather than the scripted responses of the questions
+	#[arg(long)]
+	live: bool,
+
+	/// Have the answers scored by a model with the OpenAI API
+	#[arg(long)]
+	judge: bool,
+
+	/// The model of the judge, the answer model by default
+	#[arg(long)]
+	judge_model: Option<String>,
+
+	/// TOML file of the `[[criteria]]` the judge scores, with a `name` and a `description`
+	#[arg(long)]
+	rubric: Option<PathBuf>,
+
+	/// Where the results are written, to be passed as the baseline of later runs
+	#[arg(long, default_value = "eval-results.json")]
+	output: PathBuf,
+
+	/// Results of a previous run of the same mode to compare with
+	#[arg(long)]
+	baseline: Option<PathBuf>,
+
+	/// Where the comparison with the baseline is written
+	#[arg(long, default_value = "eval-diff.json")]
+	diff_output: PathBuf,
+
+	/// Exit with status 1 when a metric is lower than in the baseline by more than this
+	#[arg(long)]
+	max_regression: Option<f64>
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Mode {
+	Retrieval,
+	Answers
+}
+
+#[cfg(feature = "oracle")]
+#[tokio::main]
+async fn main() {
+	pretty_env_logger::init();
+
+	dotenv::dotenv().ok();
+
+	let args = Args::parse();
+	let config = Config::init(&args.config, Binary::Eval);
+
+	let result = match args.mode {
+		Mode::Retrieval => run_retrieval(&args, &config).await,
+		Mode::Answers => run_answers(&args, &config).await
+	};
+	match result {
+		Ok(true) => exit(0),
+		Ok(false) => exit(1),
+		Err(err) => {
+			log::error!("Evaluation failed: {:#}", err);
+			exit(1);
+		}
+	}
+}
+
+// Whether no metric regressed by more than `--max-regression`
+async fn run_retrieval(args: &Args, config: &Config) -> Result<bool> {
+	let questions = load_golden_set(&args.questions).await?;
+	let configurations = configurations(args).await?;
+	// Read first, so that a wrong path fails before the evaluation rather than after
+	let baseline = match &args.baseline {
+		Some(path) => Some(EvalRun::read(path).await?),
+		None => None
+	};
+	let mut k = args.k.clone();
+	k.sort_unstable();
+	k.dedup();
+
+	let mut models = HashMap::new();
+	let mut results = Vec::new();
+	for configuration in &configurations {
+		results.push(evaluate_configuration(config, configuration, &questions, &k, &mut models).await?);
+	}
+
+	let run = EvalRun {
+		finished_at: Utc::now(),
+		golden_set: args.questions.clone(),
+		k,
+		configurations: results
+	};
+	run.write(&args.output).await?;
+	for configuration in &run.configurations {
+		for (function, metrics) in &configuration.metrics {
+			let named: Vec<String> = metrics.named().iter().map(|(name, value)| format!("{} {:.3}", name, value)).collect();
+			println!("{} {} ({} questions): {}", configuration.name, function, metrics.questions, named.join(", "));
+		}
+	}
+	log::info!("Results written to {}", args.output.display());
+
+	match baseline {
+		Some(baseline) => compare(args, &baseline.named_metrics(), &run.named_metrics()).await,
+		None => Ok(true)
+	}
+}
+
+// Whether no metric regressed by more than `--max-regression`
+async fn run_answers(args: &Args, config: &Config) -> Result<bool> {
+	let questions = load_golden_set(&args.questions).await?;
+	let configurations = configurations(args).await?;
+	let baseline = match &args.baseline {
+		Some(path) => Some(AnswerEvalRun::read(path).await?),
+		None => None
+	};
+	if !args.live {
+		if let Some(question) = questions.iter().find(|question| question.responses.is_empty()) {
+			return Err(anyhow::anyhow!("Question {} has no scripted responses, add them or pass --live", question.id));
+		}
+	}
+
+	let openai: Option<Arc<dyn ChatClient>> = if args.live || args.judge {
+		Some(llm::from_config(&config.openai)?)
+	} else {
+		None
+	};
+	let judge = match &openai {
+		Some(llm) if args.judge => {
+			let rubric = match &args.rubric {
+				Some(path) => load_rubric(path).await?,
+				None => default_rubric()
+			};
+			let model = args.judge_model.clone().unwrap_or_else(|| config.chat.answer.model.clone());
+			Some(Judge::new(Arc::clone(llm), model, rubric))
+		}
+		_ => None
+	};
+	let live = openai.filter(|_| args.live);
+
+	let mut models = HashMap::new();
+	let mut results = Vec::new();
+	for configuration in &configurations {
+		results.push(answer_configuration(config, configuration, &questions, &mut models, live.as_ref(), judge.as_ref()).await?);
+	}
+
+	let run = AnswerEvalRun {
+		finished_at: Utc::now(),
+		golden_set: args.questions.clone(),
+		live: args.live,
+		configurations: results
+	};
+	run.write(&args.output).await?;
+	for configuration in &run.configurations {
+		let metrics = &configuration.metrics;
+		let named: Vec<String> = metrics.named().iter().map(|(name, value)| format!("{} {:.3}", name, value)).collect();
+		println!(
+			"{} answers ({} questions, {} uncited): {}",
+			configuration.name,
+			metrics.questions,
+			metrics.uncited,
+			named.join(", ")
+		);
+	}
+	log::info!("Results written to {}", args.output.display());
+
+	match baseline {
+		Some(baseline) => compare(args, &baseline.named_metrics(), &run.named_metrics()).await,
+		None => Ok(true)
+	}
+}
+
+async fn configurations(args: &Args) -> Result<Vec<EvalConfiguration>> {
+	match &args.configurations {
+		Some(path) => load_configurations(path).await,
+		None => Ok(vec![EvalConfiguration {
+			name: "default".into(),
+			..Default::default()
+		}])
+	}
+}
+
+// Writes the comparison with the baseline, returning whether no metric regressed by more than `--max-regression`
+async fn compare(args: &Args, baseline: &NamedMetrics, current: &NamedMetrics) -> Result<bool> {
+	let diffs = diff(baseline, current);
+	write_json(&args.diff_output, &diffs).await?;
+	log::info!("Comparison with the baseline written to {}", args.diff_output.display());
+
+	let mut passed = true;
+	for diff in &diffs {
+		let Some(delta) = diff.delta else {
+			continue;
+		};
+		if args.max_regression.is_some_and(|max_regression| delta < -max_regression) {
+			log::error!("{} {} {} regressed by {:.3}", diff.configuration, diff.function, diff.metric, -delta);
+			passed = false;
+		}
+	}
+	Ok(passed)
+}
+
+async fn evaluate_configuration(
+	config: &Config,
+	configuration: &EvalConfiguration,
+	questions: &[GoldenQuestion],
+	k: &[usize],
+	models: &mut HashMap<PathBuf, Arc<Onnx>>
+) -> Result<ConfigurationResult> {
+	let profile = profile(config, configuration)?;
+	let search = search_settings(config, configuration, &profile);
+	let (model_path, model) = model(config, configuration, models)?;
+	let (collection, db) = open_collection(config, configuration, &profile).await?;
+
+	log::info!("Evaluating configuration {} on collection {}", configuration.name, collection);
+	let (metrics, questions) = evaluate(questions, model.as_ref(), &db, &search, k).await?;
+
+	Ok(ConfigurationResult {
+		name: configuration.name.clone(),
+		collection,
+		model_path,
+		relevant_files_limit: search.files_limit,
+		relevant_chunks_limit: search.chunks_limit,
+		linked_files_limit: search.linked_files_limit,
+		metrics,
+		questions
+	})
+}
+
+// Answers with `live` when set, with the scripted responses of each question otherwise
+async fn answer_configuration(
+	config: &Config,
+	configuration: &EvalConfiguration,
+	questions: &[GoldenQuestion],
+	models: &mut HashMap<PathBuf, Arc<Onnx>>,
+	live: Option<&Arc<dyn ChatClient>>,
+	judge: Option<&Judge>
+) -> Result<AnswerConfigurationResult> {
+	let profile = profile(config, configuration)?;
+	let search = search_settings(config, configuration, &profile);
+	let (_, model) = model(config, configuration, models)?;
+	let (collection, db) = open_collection(config, configuration, &profile).await?;
+	let db = Arc::new(db);
+
+	let prompt_version = match (&configuration.prompt_version, config.prompts.versions.first()) {
+		(Some(version), _) | (None, Some(version)) => version.clone(),
+		(None, None) => return Err(anyhow::anyhow!("No prompt version is configured"))
+	};
+	let store = PromptStore::open(config.prompts.path.clone(), vec![prompt_version.clone()], PromptVariables::from(&profile))?;
+	let prompts = store.pick();
+	let settings = ChatSettings::from_config(&config.chat);
+
+	log::info!("Answering with configuration {} on collection {}", configuration.name, collection);
+	let mut answers = Vec::new();
+	for question in questions {
+		let llm: Arc<dyn ChatClient> = match live {
+			Some(llm) => Arc::clone(llm),
+			None => Arc::new(ScriptedClient::new(question.responses.clone()))
+		};
+		let assistant = Assistant {
+			llm,
+			settings: settings.clone(),
+			prompts: Arc::clone(&prompts)
+		};
+		answers.push(answer_question(question, Arc::clone(&db), Arc::clone(&model), assistant, &search, judge).await);
+	}
+
+	Ok(AnswerConfigurationResult {
+		name: configuration.name.clone(),
+		collection,
+		prompt_version,
+		metrics: AnswerMetrics::mean(&answers),
+		answers
+	})
+}
+
+fn profile(config: &Config, configuration: &EvalConfiguration) -> Result<ProfileConfig> {
+	let mut profiles = config.profiles();
+	match &configuration.profile {
+		Some(name) => profiles
+			.into_iter()
+			.find(|profile| &profile.name == name)
+			.ok_or_else(|| anyhow::anyhow!("Unknown profile {} in configuration {}", name, configuration.name)),
+		None => Ok(profiles.remove(0))
+	}
+}
+
+fn search_settings(config: &Config, configuration: &EvalConfiguration, profile: &ProfileConfig) -> SearchSettings {
+	let mut search = SearchSettings::from_config(profile, &config.search);
+	search.files_limit = configuration.relevant_files_limit.unwrap_or(search.files_limit);
+	search.chunks_limit = configuration.relevant_chunks_limit.unwrap_or(search.chunks_limit);
+	search.linked_files_limit = configuration.linked_files_limit.unwrap_or(search.linked_files_limit);
+	search
+}
+
+// Configurations sharing a model load it once
+fn model(config: &Config, configuration: &EvalConfiguration, models: &mut HashMap<PathBuf, Arc<Onnx>>) -> Result<(PathBuf, Arc<Onnx>)> {
+	let model_path = configuration.model_path.clone().unwrap_or_else(|| config.model.path.clone());
+	let model = match models.get(&model_path) {
+		Some(model) => Arc::clone(model),
+		None => {
+			let model = Arc::new(Onnx::new(&model_path)?);
+			models.insert(model_path.clone(), Arc::clone(&model));
+			model
+		}
+	};
+	Ok((model_path, model))
+}
+
+async fn open_collection(config: &Config, configuration: &EvalConfiguration, profile: &ProfileConfig) -> Result<(String, QdrantDB)> {
+	let collection = configuration.collection.clone().unwrap_or_else(|| profile.collection.clone());
+	let db = QdrantDB::initialize(&config.qdrant, &collection)?;
+	if !db.is_indexed().await? {
+		return Err(anyhow::anyhow!("Collection {} of configuration {} does not exist", collection, configuration.name));
+	}
+	Ok((collection, db))
+}
@@ -1,44 +1,156 @@
-use std::{path::Path, sync::Arc};
+use std::io::Write;
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc
+};
 
 use actix_cors::Cors;
-use actix_web::{web, App, HttpServer};
+use actix_web::{http::header, web, App, HttpServer};
+use actix_web_lab::middleware::from_fn;
+use clap::{Parser, Subcommand};
 use ircc_ai::{
-	constants::{HOME_ROUTE_REDIRECT_URL, WEBSERVER_PORT_DEFAULT},
-	db::qdrant::QdrantDB,
-	embeddings::Onnx
+	config::{Binary, Config, ConfigArgs, TelemetryConfig},
+	constants::{ANSWER_ID_HEADER, API_KEY_HEADER},
+	convrsation::{cache::AnswerCache, profiles::Profiles, settings::ChatSettings},
+	embeddings::Onnx,
+	feedback::FeedbackStore,
+	llm::{self, ChatClient},
+	prelude::*,
+	routes::{
+		auth::{self, AccessGuard},
+		pipeline::{InFlightQueries, QueryPipeline}
+	},
+	telemetry
 };
 use log::info;
 use tracing_actix_web::TracingLogger;
 
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+	#[command(flatten)]
+	config: ConfigArgs,
+
+	#[command(subcommand)]
+	command: Option<Command>
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Write every recorded answer joined with its feedback as JSONL
+	ExportFeedback {
+		/// Output file, defaults to stdout
+		#[arg(short, long)]
+		output: Option<PathBuf>,
+
+		/// Leave out answers that received no feedback
+		#[arg(long)]
+		rated_only: bool
+	}
+}
+
 #[cfg(feature = "oracle")]
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-	pretty_env_logger::init();
-
 	dotenv::dotenv().ok();
-	let host = "0.0.0.0";
 
-	let model: Arc<Onnx> = Arc::new(Onnx::new(Path::new("/model")).unwrap());
-	let db: Arc<QdrantDB> = Arc::new(QdrantDB::initialize().unwrap());
+	let args = Args::parse();
+
+	if let Some(Command::ExportFeedback { output, rated_only }) = args.command {
+		// Only logs, an export has no spans worth sending anywhere
+		let _telemetry = telemetry::init(&TelemetryConfig::default()).unwrap();
+		let result = match Config::load(&args.config) {
+			Ok(config) => export_feedback(&config.feedback.path, output, rated_only).await,
+			Err(e) => Err(e)
+		};
+		if let Err(e) = result {
+			log::error!("Feedback export failed: {}", e);
+			std::process::exit(1);
+		}
+		return Ok(());
+	}
+
+	let config = Config::init(&args.config, Binary::Oracle);
+	// Flushes the spans not exported yet once the server stops
+	let _telemetry = telemetry::init(&config.telemetry).unwrap();
 
-	let mut port = std::env::var("WEBSERVER_PORT").unwrap_or(WEBSERVER_PORT_DEFAULT.into());
-	if port.is_empty() {
-		port = WEBSERVER_PORT_DEFAULT.to_string();
+	let model: Arc<Onnx> = Arc::new(Onnx::new(&config.model.path).unwrap());
+	let feedback_store: Arc<FeedbackStore> = Arc::new(FeedbackStore::open(&config.feedback.path).await.unwrap());
+	let access_guard: Arc<AccessGuard> = Arc::new(AccessGuard::new(&config.auth).unwrap());
+	let answer_cache: Arc<AnswerCache> = Arc::new(AnswerCache::from_config(&config.answer_cache));
+	let llm: Arc<dyn ChatClient> = llm::from_config(&config.openai).unwrap();
+	let chat_settings: Arc<ChatSettings> = Arc::new(ChatSettings::from_config(&config.chat));
+	let profiles: Arc<Profiles> = Arc::new(Profiles::from_config(&config).unwrap());
+	for profile in profiles.iter() {
+		actix_rt::spawn(profile.prompts.clone().watch());
 	}
-	let port = port.parse::<u16>().expect("Invalid WEBSERVER_PORT");
+	let pipeline = QueryPipeline {
+		profiles,
+		model,
+		llm,
+		chat_settings,
+		feedback_store,
+		answer_cache,
+		in_flight: Arc::new(InFlightQueries::default())
+	};
+	let allowed_origins = config.server.cors_allowed_origins.clone();
+	let redirect_url = config.server.redirect_url.clone();
 
 	let server = HttpServer::new(move || {
 		App::new()
-			.wrap(Cors::permissive())
+			.wrap(from_fn(auth::guard))
+			.wrap(cors(&allowed_origins))
 			.wrap(TracingLogger::default())
-			.service(web::redirect("/", HOME_ROUTE_REDIRECT_URL))
+			.service(web::redirect("/", redirect_url.clone()))
 			.service(ircc_ai::routes::query)
-			.app_data(web::Data::new(model.clone()))
-			.app_data(web::Data::new(db.clone()))
+			.service(ircc_ai::routes::cancel)
+			.service(ircc_ai::routes::feedback)
+			.service(ircc_ai::routes::profiles)
+			.service(ircc_ai::routes::ws::chat)
+			.service(ircc_ai::routes::metrics)
+			.service(ircc_ai::routes::health::liveness)
+			.service(ircc_ai::routes::health::readiness)
+			.service(ircc_ai::routes::openapi::openapi_spec)
+			.service(ircc_ai::routes::openapi::event_catalogue)
+			.app_data(web::Data::new(pipeline.clone()))
+			.app_data(web::Data::new(access_guard.clone()))
 	})
-	.bind((host, port))?;
+	.bind((config.server.host.as_str(), config.server.port))?;
 
-	info!("Server running on {}:{}", host, port);
+	info!("Server running on {}:{}", config.server.host, config.server.port);
 
 	server.run().await
 }
+
+async fn export_feedback(store_path: &Path, output: Option<PathBuf>, rated_only: bool) -> Result<()> {
+	let store = FeedbackStore::open(store_path).await?;
+	let items = store.export(rated_only).await?;
+
+	let mut writer: Box<dyn Write> = match &output {
+		Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+		None => Box::new(std::io::stdout().lock())
+	};
+	for item in &items {
+		writeln!(writer, "{}", serde_json::to_string(item)?)?;
+	}
+	writer.flush()?;
+
+	log::info!("Exported {} answers", items.len());
+	Ok(())
+}
+
+// Origins come from `server.cors_allowed_origins`, `*` allows any origin
+fn cors(allowed_origins: &[String]) -> Cors {
+	let cors = Cors::default()
+		.allowed_methods(vec!["GET", "POST"])
+		.allowed_headers(vec![header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static(API_KEY_HEADER)])
+		// Browsers hide other response headers from scripts, and clients need the answer ID for feedback and cancellation
+		.expose_headers([ANSWER_ID_HEADER])
+		.max_age(3600);
+
+	if allowed_origins.iter().any(|origin| origin == "*") {
+		return cors.allow_any_origin();
+	}
+
+	allowed_origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use super::Config;
+
+/// Flags shared by every binary, taking precedence over the configuration file and the environment
+#[derive(clap::Args, Debug, Default)]
+pub struct ConfigArgs {
+	/// TOML configuration file, overrides `CONFIG_FILE`
+	#[arg(short, long, global = true)]
+	pub config: Option<PathBuf>,
+
+	/// Directory holding the documents, overrides `DOCUMENTS_BASE_PATH`
+	#[arg(short, long, global = true)]
+	pub path: Option<PathBuf>,
+
+	/// Overrides `QDRANT_URL`
+	#[arg(long, global = true)]
+	pub qdrant_url: Option<String>,
+
+	/// Directory holding the ONNX embeddings model, overrides `MODEL_PATH`
+	#[arg(long, global = true)]
+	pub model_path: Option<PathBuf>,
+
+	/// Overrides `WEBSERVER_PORT`
+	#[arg(long, global = true)]
+	pub port: Option<u16>,
+
+	/// Print the effective configuration, with secrets redacted, and exit
+	#[arg(long, global = true)]
+	pub print_config: bool
+}
+
+impl ConfigArgs {
+	pub(super) fn apply(&self, config: &mut Config) {
+		if let Some(path) = &self.path {
+			config.documents.path = path.clone();
+		}
+		if let Some(url) = &self.qdrant_url {
+			config.qdrant.url = url.clone();
+		}
+		if let Some(path) = &self.model_path {
+			config.model.path = path.clone();
+		}
+		if let Some(port) = self.port {
+			config.server.port = port;
+		}
+	}
+}
@@ -0,0 +1,799 @@
+mod args;
+mod secret;
+
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub use args::ConfigArgs;
+pub use secret::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::*;
+use crate::prelude::*;
+
+/// The binary a configuration is loaded for, which decides the settings that must be present
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binary {
+	Embed,
+	/// The `crawl` subcommand of `embed`, which needs neither the model nor the database
+	Crawl,
+	Oracle,
+	/// The retrieval evaluation, which searches like the oracle without calling the LLM
+	Eval,
+	Bot
+}
+
+/// Settings of every binary. Values come from the defaults, then the TOML file given by `--config` or `CONFIG_FILE`,
+/// then the environment variables documented on each field, then the command line flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub qdrant: QdrantConfig,
+	pub model: ModelConfig,
+	pub documents: DocumentsConfig,
+	pub embed: EmbedConfig,
+	pub crawl: CrawlConfig,
+	pub search: SearchConfig,
+	pub server: ServerConfig,
+	pub auth: AuthConfig,
+	pub openai: OpenAIConfig,
+	pub chat: ChatConfig,
+	pub prompts: PromptsConfig,
+	pub answer_cache: AnswerCacheConfig,
+	pub feedback: FeedbackConfig,
+	pub telemetry: TelemetryConfig,
+	pub bot: BotConfig,
+	/// The document corpora served, see `Config::profiles`
+	pub profiles: Vec<ProfileConfig>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QdrantConfig {
+	/// `QDRANT_URL`
+	pub url: String,
+	/// `QDRANT_COLLECTION`, collection of the default profile
+	pub collection: String
+}
+
+impl Default for QdrantConfig {
+	fn default() -> Self {
+		Self {
+			url: QDRANT_URL_DEFAULT.into(),
+			collection: QDRANT_COLLECTION_NAME.into()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelConfig {
+	/// `MODEL_PATH`, the directory holding the ONNX embeddings model
+	pub path: PathBuf
+}
+
+impl Default for ModelConfig {
+	fn default() -> Self {
+		Self {
+			path: MODEL_PATH_DEFAULT.into()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DocumentsConfig {
+	/// `DOCUMENTS_BASE_PATH`, documents of the default profile
+	pub path: PathBuf,
+	/// `DOCUMENTS_INCLUDE` (comma separated), globs relative to the documents directory of the files to index, every
+	/// file when empty
+	pub include: Vec<String>,
+	/// `DOCUMENTS_EXCLUDE` (comma separated), globs of the files and directories never to index
+	pub exclude: Vec<String>,
+	/// `DOCUMENTS_IGNORE_FILES` (comma separated), names of the `.gitignore` style files honoured in every directory
+	pub ignore_files: Vec<String>,
+	/// `DOCUMENTS_MAX_FILE_SIZE_BYTES`, larger files are not indexed
+	pub max_file_size_bytes: u64
+}
+
+impl Default for DocumentsConfig {
+	fn default() -> Self {
+		Self {
+			path: DOCUMENTS_PATH_DEFAULT.into(),
+			include: Vec::new(),
+			exclude: Vec::new(),
+			ignore_files: DOCUMENTS_IGNORE_FILES_DEFAULT.iter().map(|name| name.to_string()).collect(),
+			max_file_size_bytes: DOCUMENTS_MAX_FILE_SIZE_BYTES_DEFAULT
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmbedConfig {
+	/// `EMBED_CHECKPOINT_DIR`, where the checkpoint of each profile is written for `embed --resume`
+	pub checkpoint_dir: PathBuf,
+	/// `EMBED_REPORT_PATH`, JSON report of the failures of the last run
+	pub report_path: PathBuf,
+	/// `EMBED_MAX_FAILED_PERCENT`, share of the files of a profile that may fail before the run exits with an error
+	pub max_failed_percent: f64,
+	/// `EMBED_MAX_FAILED_FILES`, files of a profile that may fail before the run exits with an error
+	pub max_failed_files: Option<usize>,
+	/// `EMBED_WATCH_DEBOUNCE_MS`, how long `embed watch` waits for a file to stop changing before indexing it
+	pub watch_debounce_ms: u64
+}
+
+impl Default for EmbedConfig {
+	fn default() -> Self {
+		Self {
+			checkpoint_dir: EMBED_CHECKPOINT_DIR_DEFAULT.into(),
+			report_path: EMBED_REPORT_PATH_DEFAULT.into(),
+			max_failed_percent: EMBED_MAX_FAILED_PERCENT_DEFAULT,
+			max_failed_files: None,
+			watch_debounce_ms: EMBED_WATCH_DEBOUNCE_MS_DEFAULT
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+	/// `CRAWL_START_URL`, the page the crawl starts from
+	pub start_url: String,
+	/// `CRAWL_ALLOWED_PREFIXES` (comma separated), URL prefixes of the pages followed, the start URL without its `.html`
+	/// extension when empty
+	pub allowed_prefixes: Vec<String>,
+	/// `CRAWL_DELAY_MS`, pause between two requests, longer when robots.txt sets a `Crawl-delay`
+	pub delay_ms: u64,
+	/// `CRAWL_RESPECT_ROBOTS_TXT`, skip the pages the robots.txt of their site disallows
+	pub respect_robots_txt: bool,
+	/// `CRAWL_USER_AGENT`, also the name looked up in robots.txt
+	pub user_agent: String,
+	/// `CRAWL_MAX_PAGES`, pages written before the crawl stops, no limit when unset
+	pub max_pages: Option<usize>,
+	/// `CRAWL_REQUEST_TIMEOUT_SECS`
+	pub request_timeout_secs: u64
+}
+
+impl Default for CrawlConfig {
+	fn default() -> Self {
+		Self {
+			start_url: CRAWL_START_URL_DEFAULT.into(),
+			allowed_prefixes: Vec::new(),
+			delay_ms: CRAWL_DELAY_MS_DEFAULT,
+			respect_robots_txt: true,
+			user_agent: CRAWL_USER_AGENT_DEFAULT.into(),
+			max_pages: None,
+			request_timeout_secs: CRAWL_REQUEST_TIMEOUT_SECS_DEFAULT
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+	/// `RELEVANT_FILES_LIMIT`, files returned by a semantic search
+	pub relevant_files_limit: usize,
+	/// `RELEVANT_CHUNKS_LIMIT`, chunks kept from each file
+	pub relevant_chunks_limit: usize,
+	/// `LINKED_FILES_LIMIT`, documents linked from the files found that are searched as well, 0 disables it
+	pub linked_files_limit: usize
+}
+
+impl Default for SearchConfig {
+	fn default() -> Self {
+		Self {
+			relevant_files_limit: RELEVANT_FILES_LIMIT,
+			relevant_chunks_limit: RELEVANT_CHUNKS_LIMIT,
+			linked_files_limit: LINKED_FILES_LIMIT_DEFAULT
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+	/// `WEBSERVER_HOST`
+	pub host: String,
+	/// `WEBSERVER_PORT`
+	pub port: u16,
+	/// `HOME_ROUTE_REDIRECT_URL`, where `/` redirects to
+	pub redirect_url: String,
+	/// `CORS_ALLOWED_ORIGINS` (comma separated), `*` allows any origin
+	pub cors_allowed_origins: Vec<String>
+}
+
+impl Default for ServerConfig {
+	fn default() -> Self {
+		Self {
+			host: WEBSERVER_HOST_DEFAULT.into(),
+			port: WEBSERVER_PORT_DEFAULT,
+			redirect_url: HOME_ROUTE_REDIRECT_URL.into(),
+			cors_allowed_origins: Vec::new()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+	/// `AUTH_DISABLED`, serves every route without API keys. The oracle refuses to start without keys otherwise.
+	pub disabled: bool,
+	/// `API_KEYS` (comma separated)
+	pub api_keys: Vec<Secret>,
+	/// `API_KEYS_FILE`, one key per line optionally followed by a client name
+	pub api_keys_file: PathBuf,
+	/// `RATE_LIMIT_PER_KEY_PER_MINUTE`
+	pub rate_limit_per_key_per_minute: u32,
+	/// `RATE_LIMIT_PER_IP_PER_MINUTE`
+	pub rate_limit_per_ip_per_minute: u32,
+	/// `DAILY_QUOTA_PER_KEY`
+	pub daily_quota_per_key: u32,
+	/// `TRUSTED_PROXIES` (comma separated), addresses of the reverse proxies whose `Forwarded` and `X-Forwarded-For`
+	/// headers give the client IP. The peer address is used for any other connection.
+	pub trusted_proxies: Vec<IpAddr>
+}
+
+impl Default for AuthConfig {
+	fn default() -> Self {
+		Self {
+			disabled: false,
+			api_keys: Vec::new(),
+			api_keys_file: API_KEYS_FILE_DEFAULT.into(),
+			rate_limit_per_key_per_minute: RATE_LIMIT_PER_KEY_PER_MINUTE_DEFAULT,
+			rate_limit_per_ip_per_minute: RATE_LIMIT_PER_IP_PER_MINUTE_DEFAULT,
+			daily_quota_per_key: DAILY_QUOTA_PER_KEY_DEFAULT,
+			trusted_proxies: Vec::new()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenAIConfig {
+	/// `OPENAI_API_KEY`
+	pub api_key: Option<Secret>,
+	/// `OPENAI_API_BASE`
+	pub api_base: String,
+	/// `LLM_REQUEST_TIMEOUT_SECS`
+	pub request_timeout_secs: u32,
+	/// `LLM_MAX_RETRIES`
+	pub max_retries: u32,
+	/// `LLM_FIXTURES_MODE`
+	pub fixtures_mode: FixturesMode,
+	/// `LLM_FIXTURES_PATH`, directory of the recorded exchanges
+	pub fixtures_path: PathBuf
+}
+
+impl Default for OpenAIConfig {
+	fn default() -> Self {
+		Self {
+			api_key: None,
+			api_base: OPENAI_API_BASE_DEFAULT.into(),
+			request_timeout_secs: LLM_REQUEST_TIMEOUT_SECS_DEFAULT,
+			max_retries: LLM_MAX_RETRIES_DEFAULT,
+			fixtures_mode: FixturesMode::Off,
+			fixtures_path: LLM_FIXTURES_PATH_DEFAULT.into()
+		}
+	}
+}
+
+/// Whether chat completions are recorded to, or replayed from, fixture files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FixturesMode {
+	/// Requests go to the API and nothing is recorded
+	#[default]
+	Off,
+	/// Requests go to the API and every exchange is saved
+	Record,
+	/// Requests are answered from the saved exchanges only, the API is never called
+	Replay
+}
+
+impl FromStr for FixturesMode {
+	type Err = anyhow::Error;
+
+	fn from_str(value: &str) -> Result<Self> {
+		match value {
+			"off" => Ok(FixturesMode::Off),
+			"record" => Ok(FixturesMode::Record),
+			"replay" => Ok(FixturesMode::Replay),
+			_ => Err(anyhow::anyhow!("Expected off, record or replay"))
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+	/// `PLANNING_MODEL`, `PLANNING_TEMPERATURE` and `PLANNING_MAX_TOKENS`
+	pub planning: ChatModelConfig,
+	/// `ANSWER_MODEL`, `ANSWER_TEMPERATURE` and `ANSWER_MAX_TOKENS`
+	pub answer: ChatModelConfig,
+	/// `ALLOWED_MODELS` (comma separated), models a request may switch to besides the planning and answer models
+	pub allowed_models: Vec<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatModelConfig {
+	pub model: String,
+	pub temperature: f64,
+	pub max_tokens: Option<u32>
+}
+
+impl Default for ChatModelConfig {
+	fn default() -> Self {
+		Self {
+			model: CHAT_COMPLETION_MODEL.into(),
+			temperature: CHAT_COMPLETION_TEMPERATURE,
+			max_tokens: None
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptsConfig {
+	/// `PROMPTS_PATH`
+	pub path: PathBuf,
+	/// `PROMPTS_VERSIONS` (comma separated), directories under `path`
+	pub versions: Vec<String>,
+	/// `SITE_BASE_URL`, citation links prefix of the default profile
+	pub site_base_url: String,
+	/// `PROMPT_LANGUAGE`, language of the default profile
+	pub language: String,
+	/// `AGENCY_NAME`, publisher of the documents of the default profile
+	pub agency_name: String,
+	/// `PROMPT_DOMAIN`, subject of the documents of the default profile
+	pub domain: String
+}
+
+impl Default for PromptsConfig {
+	fn default() -> Self {
+		Self {
+			path: PROMPTS_PATH_DEFAULT.into(),
+			versions: vec![PROMPTS_VERSION_DEFAULT.into()],
+			site_base_url: SITE_BASE_URL_DEFAULT.into(),
+			language: PROMPT_LANGUAGE_DEFAULT.into(),
+			agency_name: AGENCY_NAME_DEFAULT.into(),
+			domain: DOMAIN_DEFAULT.into()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnswerCacheConfig {
+	/// `ANSWER_CACHE_TTL_SECS`, 0 disables the cache
+	pub ttl_secs: u64,
+	/// `ANSWER_CACHE_MAX_ENTRIES`
+	pub max_entries: usize,
+	/// `ANSWER_CACHE_SIMILARITY_THRESHOLD`, only exact matches hit when unset
+	pub similarity_threshold: Option<f32>
+}
+
+impl Default for AnswerCacheConfig {
+	fn default() -> Self {
+		Self {
+			ttl_secs: ANSWER_CACHE_TTL_SECS_DEFAULT,
+			max_entries: ANSWER_CACHE_MAX_ENTRIES_DEFAULT,
+			similarity_threshold: None
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedbackConfig {
+	/// `FEEDBACK_STORE_PATH`
+	pub path: PathBuf
+}
+
+impl Default for FeedbackConfig {
+	fn default() -> Self {
+		Self {
+			path: FEEDBACK_STORE_PATH_DEFAULT.into()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+	/// `OTEL_EXPORTER_OTLP_ENDPOINT`, gRPC endpoint of the collector spans are exported to, e.g. `http://localhost:4317`.
+	/// Spans are only logged when unset.
+	pub otlp_endpoint: Option<String>,
+	/// `OTEL_SERVICE_NAME`
+	pub service_name: String
+}
+
+impl Default for TelemetryConfig {
+	fn default() -> Self {
+		Self {
+			otlp_endpoint: None,
+			service_name: TELEMETRY_SERVICE_NAME_DEFAULT.into()
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+	/// `ORACLE_QUERY_URL`
+	pub oracle_query_url: String,
+	/// `ORACLE_API_KEY`
+	pub oracle_api_key: Option<Secret>,
+	/// `TELOXIDE_TOKEN`
+	pub telegram_token: Option<Secret>
+}
+
+impl Default for BotConfig {
+	fn default() -> Self {
+		Self {
+			oracle_query_url: ORACLE_QUERY_URL_DEFAULT.into(),
+			oracle_api_key: None,
+			telegram_token: None
+		}
+	}
+}
+
+/// A document corpus: where its documents and embeddings are, how the prompts describe it and how its documents are
+/// cited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+	/// Selects the profile in queries
+	pub name: String,
+	/// Qdrant collection holding the embeddings of the documents
+	pub collection: String,
+	pub documents_path: PathBuf,
+	/// Subject of the documents, e.g. "immigration, refugees and citizenship of Canada"
+	pub domain: String,
+	/// Publisher of the documents
+	pub agency_name: String,
+	/// Prepended to the path of a document, relative to `documents_path`, to build its citation link
+	pub site_base_url: String,
+	/// Removed from the end of document paths when building citation links, e.g. ".md"
+	pub strip_suffix: Option<String>,
+	/// Languages the documents are published in, answers default to the first one
+	pub languages: Vec<String>
+}
+
+impl Config {
+	/// Loads and validates the configuration of `binary`. Prints it and exits if `--print-config` was given, and exits
+	/// with the list of problems if it is invalid.
+	pub fn init(args: &ConfigArgs, binary: Binary) -> Self {
+		let config = match Self::load(args) {
+			Ok(config) => config,
+			Err(e) => {
+				eprintln!("Failed to load the configuration: {:#}", e);
+				std::process::exit(2);
+			}
+		};
+
+		if args.print_config {
+			match config.to_redacted_toml() {
+				Ok(toml) => print!("{}", toml),
+				Err(e) => eprintln!("Failed to print the configuration: {}", e)
+			}
+		}
+
+		if let Err(errors) = config.validate(binary) {
+			eprintln!("Invalid configuration:");
+			for error in errors {
+				eprintln!("  - {}", error);
+			}
+			std::process::exit(2);
+		}
+
+		if args.print_config {
+			std::process::exit(0);
+		}
+		config
+	}
+
+	pub fn load(args: &ConfigArgs) -> Result<Self> {
+		let file = args.config.clone().or_else(|| std::env::var("CONFIG_FILE").ok().filter(|path| !path.is_empty()).map(PathBuf::from));
+		let mut config = match &file {
+			Some(path) => Self::from_file(path)?,
+			None => Self::default()
+		};
+		config.apply_env()?;
+		args.apply(&mut config);
+		Ok(config)
+	}
+
+	pub fn from_file(path: &Path) -> Result<Self> {
+		let content = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+		toml::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+	}
+
+	/// The configured profiles, or when there are none a single profile named `default` described by `qdrant.collection`,
+	/// `documents.path` and the prompt variables
+	pub fn profiles(&self) -> Vec<ProfileConfig> {
+		if !self.profiles.is_empty() {
+			return self.profiles.clone();
+		}
+
+		vec![ProfileConfig {
+			name: PROFILE_NAME_DEFAULT.into(),
+			collection: self.qdrant.collection.clone(),
+			documents_path: self.documents.path.clone(),
+			domain: self.prompts.domain.clone(),
+			agency_name: self.prompts.agency_name.clone(),
+			site_base_url: self.prompts.site_base_url.clone(),
+			strip_suffix: None,
+			languages: vec![self.prompts.language.clone()]
+		}]
+	}
+
+	/// The configuration as TOML, with every secret replaced by a placeholder
+	pub fn to_redacted_toml(&self) -> Result<String> {
+		Ok(toml::to_string_pretty(self)?)
+	}
+
+	fn apply_env(&mut self) -> Result<()> {
+		env("QDRANT_URL", &mut self.qdrant.url)?;
+		env("QDRANT_COLLECTION", &mut self.qdrant.collection)?;
+		env("MODEL_PATH", &mut self.model.path)?;
+		env("DOCUMENTS_BASE_PATH", &mut self.documents.path)?;
+		env_list("DOCUMENTS_INCLUDE", &mut self.documents.include)?;
+		env_list("DOCUMENTS_EXCLUDE", &mut self.documents.exclude)?;
+		env_list("DOCUMENTS_IGNORE_FILES", &mut self.documents.ignore_files)?;
+		env("DOCUMENTS_MAX_FILE_SIZE_BYTES", &mut self.documents.max_file_size_bytes)?;
+		env("EMBED_CHECKPOINT_DIR", &mut self.embed.checkpoint_dir)?;
+		env("EMBED_REPORT_PATH", &mut self.embed.report_path)?;
+		env("EMBED_MAX_FAILED_PERCENT", &mut self.embed.max_failed_percent)?;
+		env_opt("EMBED_MAX_FAILED_FILES", &mut self.embed.max_failed_files)?;
+		env("EMBED_WATCH_DEBOUNCE_MS", &mut self.embed.watch_debounce_ms)?;
+
+		env("CRAWL_START_URL", &mut self.crawl.start_url)?;
+		env_list("CRAWL_ALLOWED_PREFIXES", &mut self.crawl.allowed_prefixes)?;
+		env("CRAWL_DELAY_MS", &mut self.crawl.delay_ms)?;
+		env("CRAWL_RESPECT_ROBOTS_TXT", &mut self.crawl.respect_robots_txt)?;
+		env("CRAWL_USER_AGENT", &mut self.crawl.user_agent)?;
+		env_opt("CRAWL_MAX_PAGES", &mut self.crawl.max_pages)?;
+		env("CRAWL_REQUEST_TIMEOUT_SECS", &mut self.crawl.request_timeout_secs)?;
+
+		env("RELEVANT_FILES_LIMIT", &mut self.search.relevant_files_limit)?;
+		env("RELEVANT_CHUNKS_LIMIT", &mut self.search.relevant_chunks_limit)?;
+		env("LINKED_FILES_LIMIT", &mut self.search.linked_files_limit)?;
+
+		env("WEBSERVER_HOST", &mut self.server.host)?;
+		env("WEBSERVER_PORT", &mut self.server.port)?;
+		env("HOME_ROUTE_REDIRECT_URL", &mut self.server.redirect_url)?;
+		env_list("CORS_ALLOWED_ORIGINS", &mut self.server.cors_allowed_origins)?;
+
+		env("AUTH_DISABLED", &mut self.auth.disabled)?;
+		env_list("API_KEYS", &mut self.auth.api_keys)?;
+		env("API_KEYS_FILE", &mut self.auth.api_keys_file)?;
+		env("RATE_LIMIT_PER_KEY_PER_MINUTE", &mut self.auth.rate_limit_per_key_per_minute)?;
+		env("RATE_LIMIT_PER_IP_PER_MINUTE", &mut self.auth.rate_limit_per_ip_per_minute)?;
+		env("DAILY_QUOTA_PER_KEY", &mut self.auth.daily_quota_per_key)?;
+		env_list("TRUSTED_PROXIES", &mut self.auth.trusted_proxies)?;
+
+		env_opt("OPENAI_API_KEY", &mut self.openai.api_key)?;
+		env("OPENAI_API_BASE", &mut self.openai.api_base)?;
+		env("LLM_REQUEST_TIMEOUT_SECS", &mut self.openai.request_timeout_secs)?;
+		env("LLM_MAX_RETRIES", &mut self.openai.max_retries)?;
+		env("LLM_FIXTURES_MODE", &mut self.openai.fixtures_mode)?;
+		env("LLM_FIXTURES_PATH", &mut self.openai.fixtures_path)?;
+
+		for (prefix, settings) in [("PLANNING", &mut self.chat.planning), ("ANSWER", &mut self.chat.answer)] {
+			env(&format!("{}_MODEL", prefix), &mut settings.model)?;
+			env(&format!("{}_TEMPERATURE", prefix), &mut settings.temperature)?;
+			env_opt(&format!("{}_MAX_TOKENS", prefix), &mut settings.max_tokens)?;
+		}
+		env_list("ALLOWED_MODELS", &mut self.chat.allowed_models)?;
+
+		env("PROMPTS_PATH", &mut self.prompts.path)?;
+		env_list("PROMPTS_VERSIONS", &mut self.prompts.versions)?;
+		env("SITE_BASE_URL", &mut self.prompts.site_base_url)?;
+		env("PROMPT_LANGUAGE", &mut self.prompts.language)?;
+		env("AGENCY_NAME", &mut self.prompts.agency_name)?;
+		env("PROMPT_DOMAIN", &mut self.prompts.domain)?;
+
+		env("ANSWER_CACHE_TTL_SECS", &mut self.answer_cache.ttl_secs)?;
+		env("ANSWER_CACHE_MAX_ENTRIES", &mut self.answer_cache.max_entries)?;
+		env_opt("ANSWER_CACHE_SIMILARITY_THRESHOLD", &mut self.answer_cache.similarity_threshold)?;
+
+		env("FEEDBACK_STORE_PATH", &mut self.feedback.path)?;
+
+		env_opt("OTEL_EXPORTER_OTLP_ENDPOINT", &mut self.telemetry.otlp_endpoint)?;
+		env("OTEL_SERVICE_NAME", &mut self.telemetry.service_name)?;
+
+		env("ORACLE_QUERY_URL", &mut self.bot.oracle_query_url)?;
+		env_opt("ORACLE_API_KEY", &mut self.bot.oracle_api_key)?;
+		env_opt("TELOXIDE_TOKEN", &mut self.bot.telegram_token)?;
+
+		Ok(())
+	}
+
+	/// Every problem found, so that they can all be fixed at once
+	pub fn validate(&self, binary: Binary) -> std::result::Result<(), Vec<String>> {
+		let mut errors = Vec::new();
+
+		check_url(&mut errors, "qdrant.url", &self.qdrant.url);
+
+		let profiles = self.profiles();
+		if binary != Binary::Bot {
+			check_profiles(&mut errors, &profiles);
+		}
+
+		match binary {
+			// The documents are checked once the profiles to index are known
+			Binary::Embed => {
+				check_dir(&mut errors, "model.path", &self.model.path);
+				check_globs(&mut errors, "documents.include", &self.documents.include);
+				check_globs(&mut errors, "documents.exclude", &self.documents.exclude);
+				if self.documents.max_file_size_bytes == 0 {
+					errors.push("documents.max_file_size_bytes must be at least 1".into());
+				}
+				if !(0.0..=100.0).contains(&self.embed.max_failed_percent) {
+					errors.push("embed.max_failed_percent must be between 0 and 100".into());
+				}
+				if self.embed.watch_debounce_ms == 0 {
+					errors.push("embed.watch_debounce_ms must be at least 1".into());
+				}
+			}
+			// The documents directory is created by the crawl
+			Binary::Crawl => {
+				check_url(&mut errors, "crawl.start_url", &self.crawl.start_url);
+				for prefix in &self.crawl.allowed_prefixes {
+					check_url(&mut errors, "crawl.allowed_prefixes", prefix);
+				}
+				if self.crawl.user_agent.trim().is_empty() {
+					errors.push("crawl.user_agent must not be empty".into());
+				}
+				if self.crawl.request_timeout_secs == 0 {
+					errors.push("crawl.request_timeout_secs must be at least 1".into());
+				}
+			}
+			Binary::Eval => {
+				check_dir(&mut errors, "model.path", &self.model.path);
+				for profile in &profiles {
+					check_dir(&mut errors, &format!("profile {} documents_path", profile.name), &profile.documents_path);
+				}
+			}
+			Binary::Oracle => {
+				check_dir(&mut errors, "model.path", &self.model.path);
+				check_dir(&mut errors, "prompts.path", &self.prompts.path);
+				for profile in &profiles {
+					check_dir(&mut errors, &format!("profile {} documents_path", profile.name), &profile.documents_path);
+					check_url(&mut errors, &format!("profile {} site_base_url", profile.name), &profile.site_base_url);
+				}
+
+				if self.search.relevant_files_limit == 0 || self.search.relevant_chunks_limit == 0 {
+					errors.push("search limits must be at least 1".into());
+				}
+				if self.server.port == 0 {
+					errors.push("server.port must not be 0".into());
+				}
+				check_url(&mut errors, "server.redirect_url", &self.server.redirect_url);
+				if self.auth.rate_limit_per_key_per_minute == 0 || self.auth.rate_limit_per_ip_per_minute == 0 || self.auth.daily_quota_per_key == 0 {
+					errors.push("auth rate limits and quota must be at least 1".into());
+				}
+
+				if self.openai.fixtures_mode == FixturesMode::Replay {
+					check_dir(&mut errors, "openai.fixtures_path", &self.openai.fixtures_path);
+				} else if !self.openai.api_key.as_ref().is_some_and(|key| !key.expose().is_empty()) {
+					errors.push("openai.api_key (OPENAI_API_KEY) is required".into());
+				}
+				check_url(&mut errors, "openai.api_base", &self.openai.api_base);
+				if self.openai.request_timeout_secs == 0 {
+					errors.push("openai.request_timeout_secs must be at least 1".into());
+				}
+
+				for (name, settings) in [("chat.planning", &self.chat.planning), ("chat.answer", &self.chat.answer)] {
+					if settings.model.is_empty() {
+						errors.push(format!("{}.model must not be empty", name));
+					}
+					if !(0.0..=2.0).contains(&settings.temperature) {
+						errors.push(format!("{}.temperature must be between 0 and 2", name));
+					}
+					if settings.max_tokens.is_some_and(|max_tokens| !(1..=CHAT_COMPLETION_MAX_TOKENS_LIMIT).contains(&max_tokens)) {
+						errors.push(format!("{}.max_tokens must be between 1 and {}", name, CHAT_COMPLETION_MAX_TOKENS_LIMIT));
+					}
+				}
+
+				if self.prompts.versions.is_empty() {
+					errors.push("prompts.versions must list at least one version".into());
+				}
+
+				if self.answer_cache.similarity_threshold.is_some_and(|threshold| !(0.0..=1.0).contains(&threshold)) {
+					errors.push("answer_cache.similarity_threshold must be between 0 and 1".into());
+				}
+
+				if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+					check_url(&mut errors, "telemetry.otlp_endpoint", endpoint);
+					if !cfg!(feature = "otlp") {
+						errors.push("telemetry.otlp_endpoint (OTEL_EXPORTER_OTLP_ENDPOINT) requires building with the otlp feature".into());
+					}
+				}
+			}
+			Binary::Bot => {
+				check_url(&mut errors, "bot.oracle_query_url", &self.bot.oracle_query_url);
+				if self.bot.telegram_token.is_none() {
+					errors.push("bot.telegram_token (TELOXIDE_TOKEN) is required".into());
+				}
+			}
+		}
+
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(errors)
+		}
+	}
+}
+
+fn env<T: FromStr>(name: &str, target: &mut T) -> Result<()> {
+	if let Some(value) = env_value(name) {
+		*target = value.parse().map_err(|_| anyhow::anyhow!("Invalid {}: {}", name, value))?;
+	}
+	Ok(())
+}
+
+fn env_opt<T: FromStr>(name: &str, target: &mut Option<T>) -> Result<()> {
+	if let Some(value) = env_value(name) {
+		*target = Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid {}: {}", name, value))?);
+	}
+	Ok(())
+}
+
+fn env_list<T: FromStr>(name: &str, target: &mut Vec<T>) -> Result<()> {
+	if let Some(value) = env_value(name) {
+		*target = value
+			.split(',')
+			.map(str::trim)
+			.filter(|item| !item.is_empty())
+			.map(|item| item.parse().map_err(|_| anyhow::anyhow!("Invalid {}: {}", name, item)))
+			.collect::<Result<_>>()?;
+	}
+	Ok(())
+}
+
+// Empty variables are treated as unset, as docker-compose passes unset variables as empty strings
+fn env_value(name: &str) -> Option<String> {
+	std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn check_profiles(errors: &mut Vec<String>, profiles: &[ProfileConfig]) {
+	for (i, profile) in profiles.iter().enumerate() {
+		if profile.name.is_empty() || profile.collection.is_empty() {
+			errors.push(format!("profiles[{}] must have a name and a collection", i));
+		}
+		if profiles[..i].iter().any(|other| other.name == profile.name) {
+			errors.push(format!("profile name {} is used more than once", profile.name));
+		}
+		if profiles[..i].iter().any(|other| other.collection == profile.collection) {
+			errors.push(format!("profile {} shares collection {} with another profile", profile.name, profile.collection));
+		}
+		if profile.languages.is_empty() {
+			errors.push(format!("profile {} must list at least one language", profile.name));
+		}
+	}
+}
+
+fn check_url(errors: &mut Vec<String>, name: &str, url: &str) {
+	if !url.starts_with("http://") && !url.starts_with("https://") {
+		errors.push(format!("{} must be an http(s) URL, got {:?}", name, url));
+	}
+}
+
+fn check_globs(errors: &mut Vec<String>, name: &str, globs: &[String]) {
+	for glob in globs {
+		if let Err(e) = globset::Glob::new(glob) {
+			errors.push(format!("{} has an invalid glob {:?}: {}", name, glob, e));
+		}
+	}
+}
+
+fn check_dir(errors: &mut Vec<String>, name: &str, path: &Path) {
+	if !path.is_dir() {
+		errors.push(format!("{} must be an existing directory, got {}", name, path.display()));
+	}
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const REDACTED: &str = "<redacted>";
+
+/// A configuration value that never appears in logs or in the printed configuration
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+	pub fn new(value: impl Into<String>) -> Self {
+		Self(value.into())
+	}
+
+	pub fn expose(&self) -> &str {
+		&self.0
+	}
+}
+
+impl std::fmt::Debug for Secret {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", REDACTED)
+	}
+}
+
+impl std::str::FromStr for Secret {
+	type Err = std::convert::Infallible;
+
+	fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+		Ok(Self::new(value))
+	}
+}
+
+impl Serialize for Secret {
+	fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+		serializer.serialize_str(REDACTED)
+	}
+}
+
+impl<'de> Deserialize<'de> for Secret {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+		String::deserialize(deserializer).map(Self)
+	}
+}
@@ -1,28 +1,112 @@
-use std::ops::RangeInclusive;
-
 // Env var defaults
 pub const QDRANT_URL_DEFAULT: &str = "http://qdrant:6334";
-pub const WEBSERVER_PORT_DEFAULT: &str = "3000";
+pub const WEBSERVER_HOST_DEFAULT: &str = "0.0.0.0";
+pub const WEBSERVER_PORT_DEFAULT: u16 = 3000;
+// The model and the documents are copied in the containers at build time
+pub const MODEL_PATH_DEFAULT: &str = "/model";
+pub const DOCUMENTS_PATH_DEFAULT: &str = "/content";
+pub const DOCUMENTS_IGNORE_FILES_DEFAULT: [&str; 2] = [".gitignore", ".embedignore"];
+pub const DOCUMENTS_MAX_FILE_SIZE_BYTES_DEFAULT: u64 = 20 * 1024 * 1024;
+
+// Indexing pipeline
+// Files found by the walk waiting to be read
+pub const WALK_QUEUE_SIZE: usize = 256;
+pub const FILE_READ_CONCURRENCY: usize = 16;
+// Points per upsert request, and upsert requests in flight while the next batch is embedded
+pub const UPSERT_BATCH_SIZE: usize = 64;
+pub const UPSERT_CONCURRENCY: usize = 2;
+pub const INDEX_PROGRESS_INTERVAL_SECS: u64 = 5;
+// Paths listed in the report when the collection does not match the documents, every one is logged
+pub const VERIFY_REPORTED_PATHS: usize = 10;
+pub const EMBED_CHECKPOINT_DIR_DEFAULT: &str = "/data/checkpoints";
+pub const EMBED_REPORT_PATH_DEFAULT: &str = "/data/embed-report.json";
+// Failures never fail the run by default, see `embed --strict`
+pub const EMBED_MAX_FAILED_PERCENT_DEFAULT: f64 = 100.0;
+// The scraper rewrites many files at once, they are indexed together once it is done
+pub const EMBED_WATCH_DEBOUNCE_MS_DEFAULT: u64 = 2000;
+
+// Crawler
+pub const CRAWL_START_URL_DEFAULT: &str = "https://www.canada.ca/en/immigration-refugees-citizenship/services/immigrate-canada.html";
+pub const CRAWL_DELAY_MS_DEFAULT: u64 = 1000;
+pub const CRAWL_USER_AGENT_DEFAULT: &str = "ircc-ai-crawler";
+pub const CRAWL_REQUEST_TIMEOUT_SECS_DEFAULT: u64 = 30;
+// Longer `Crawl-delay`s are capped, a site asking for more is crawled this slowly
+pub const CRAWL_MAX_DELAY_SECS: f64 = 60.0;
 
 pub const ORACLE_QUERY_URL_DEFAULT: &str = "http://oracle:3000/query";
+pub const API_KEYS_FILE_DEFAULT: &str = "/secrets/api-keys";
+pub const FEEDBACK_STORE_PATH_DEFAULT: &str = "/data/feedback";
 
 // Embeddings
 pub const EMBEDDINGS_DIMENSION: usize = 384;
 
 pub const QDRANT_COLLECTION_NAME: &str = "IRCC";
+// Point holding the index version, kept out of every document query. Document points are keyed by a path hash.
+pub const INDEX_VERSION_POINT_ID: u64 = 0;
+
+// Corpus profiles
+// Name of the profile built from the top level settings when no profile is configured
+pub const PROFILE_NAME_DEFAULT: &str = "default";
+pub const DOMAIN_DEFAULT: &str = "immigration, refugees and citizenship of Canada";
 
 // Actix-web
 pub const HOME_ROUTE_REDIRECT_URL: &str = "https://ircc.ai";
 pub const SSE_CHANNEL_BUFFER_SIZE: usize = 1;
+pub const ANSWER_ID_HEADER: &str = "x-answer-id";
+
+// Feedback
+pub const FEEDBACK_COMMENT_MAX_LENGTH: usize = 2000;
+
+// Access control
+pub const API_KEY_HEADER: &str = "x-api-key";
+// Routes reachable without an API key
+pub const PUBLIC_ROUTES: [&str; 6] = ["/", "/openapi.json", "/events", "/metrics", "/health", "/health/ready"];
+pub const RATE_LIMIT_PER_KEY_PER_MINUTE_DEFAULT: u32 = 20;
+pub const RATE_LIMIT_PER_IP_PER_MINUTE_DEFAULT: u32 = 10;
+pub const DAILY_QUOTA_PER_KEY_DEFAULT: u32 = 500;
+// Upper bound on tracked clients before idle rate limit buckets are pruned
+pub const RATE_LIMIT_MAX_TRACKED_CLIENTS: usize = 10_000;
 
 // Semantic search
 pub const MAX_FILES_COUNT: usize = 1000;
-pub const FILE_CHUNKER_CAPACITY_RANGE: RangeInclusive<usize> = 300..=400;
+// Characters of a Markdown chunk, not counting its heading breadcrumb. Kept under the 256 tokens of the embeddings model.
+pub const CHUNK_MAX_CHARS: usize = 800;
 pub const RELEVANT_FILES_LIMIT: usize = 3;
 pub const RELEVANT_CHUNKS_LIMIT: usize = 2;
+// Linked documents added to the results of a semantic search, 0 disables the expansion
+pub const LINKED_FILES_LIMIT_DEFAULT: usize = 0;
+// Documents returned by `get_links` in each direction
+pub const DOCUMENT_LINKS_LIMIT: usize = 20;
+
+// Prompt templates
+pub const PROMPTS_PATH_DEFAULT: &str = "/prompts";
+pub const PROMPTS_VERSION_DEFAULT: &str = "v1";
+pub const PROMPTS_RELOAD_SECS: u64 = 10;
+pub const SITE_BASE_URL_DEFAULT: &str = "https://www.canada.ca";
+pub const PROMPT_LANGUAGE_DEFAULT: &str = "English";
+pub const AGENCY_NAME_DEFAULT: &str = "Immigration, Refugees and Citizenship Canada (IRCC)";
+
+// Answer cache
+pub const ANSWER_CACHE_TTL_SECS_DEFAULT: u64 = 60 * 60;
+pub const ANSWER_CACHE_MAX_ENTRIES_DEFAULT: usize = 1000;
+pub const INDEX_VERSION_REFRESH_SECS: u64 = 60;
 
 // OpenAI
 pub const CHAT_COMPLETION_TEMPERATURE: f64 = 0.7;
 
 // See https://platform.openai.com/docs/models/gpt-4 for more info (tested with gpt-3.5-turbo and gpt-4)
 pub const CHAT_COMPLETION_MODEL: &str = "gpt-3.5-turbo";
+// Upper bound on the max tokens of a completion, whether configured or requested
+pub const CHAT_COMPLETION_MAX_TOKENS_LIMIT: u32 = 4096;
+// Planning requests of a conversation, after which it answers with what its function calls found
+pub const MAX_FUNCTION_CALLS: usize = 10;
+pub const OPENAI_API_BASE_DEFAULT: &str = "https://api.openai.com/v1";
+pub const LLM_REQUEST_TIMEOUT_SECS_DEFAULT: u32 = 60;
+pub const LLM_MAX_RETRIES_DEFAULT: u32 = 3;
+pub const LLM_BACKOFF_BASE_MS: u64 = 500;
+pub const LLM_BACKOFF_MAX_MS: u64 = 8_000;
+// Consecutive failed requests before calls to the API fail fast
+pub const LLM_BREAKER_FAILURE_THRESHOLD: u32 = 5;
+pub const LLM_BREAKER_RESET_SECS: u64 = 30;
+pub const LLM_FIXTURES_PATH_DEFAULT: &str = "fixtures/llm";
+pub const TELEMETRY_SERVICE_NAME_DEFAULT: &str = "ircc-ai-oracle";
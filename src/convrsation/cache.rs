@@ -0,0 +1,174 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use ndarray::ArrayView1;
+
+use crate::config::AnswerCacheConfig;
+use crate::constants::INDEX_VERSION_REFRESH_SECS;
+use crate::db::RepositoryEmbeddingsDB;
+use crate::embeddings::{cosine_similarity, Embeddings, EmbeddingsModel};
+use crate::prelude::*;
+use crate::routes::events::QueryEvent;
+
+/// A generated answer together with the events emitted while generating it, so that a cache hit can be replayed through
+/// the same SSE sequence
+#[derive(Debug, Clone)]
+pub struct CachedAnswer {
+	pub events: Vec<QueryEvent>,
+	pub retrieved_paths: Vec<String>,
+	pub answer: String
+}
+
+struct Entry {
+	embeddings: Option<Embeddings>,
+	created_at: Instant,
+	answer: CachedAnswer
+}
+
+// By index version and normalised query
+type EntryKey = (String, String);
+
+#[derive(Default)]
+struct Entries {
+	by_key: HashMap<EntryKey, Entry>,
+	// Oldest first, so that the expired and the excess entries are evicted from the front
+	order: VecDeque<EntryKey>
+}
+
+/// Answers keyed by the normalised sanitised query and the index version, which also identifies the profile
+pub struct AnswerCache {
+	ttl: Duration,
+	max_entries: usize,
+	// Minimum cosine similarity between two queries for them to share an answer. Only exact matches hit when unset.
+	similarity_threshold: Option<f32>,
+	entries: RwLock<Entries>,
+	// By profile
+	index_versions: Mutex<HashMap<String, (String, Instant)>>
+}
+
+impl AnswerCache {
+	pub fn new(ttl: Duration, max_entries: usize, similarity_threshold: Option<f32>) -> Self {
+		Self {
+			ttl,
+			max_entries,
+			similarity_threshold,
+			entries: RwLock::new(Entries::default()),
+			index_versions: Mutex::new(HashMap::new())
+		}
+	}
+
+	pub fn from_config(config: &AnswerCacheConfig) -> Self {
+		Self::new(Duration::from_secs(config.ttl_secs), config.max_entries, config.similarity_threshold)
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		!self.ttl.is_zero() && self.max_entries > 0
+	}
+
+	/// The current index version of a profile, refreshed at most every `INDEX_VERSION_REFRESH_SECS`
+	pub async fn index_version<D: RepositoryEmbeddingsDB>(&self, profile: &str, db: &D) -> Result<String> {
+		let cached = self.index_versions.lock().unwrap().get(profile).cloned();
+		if let Some((version, fetched_at)) = cached {
+			if fetched_at.elapsed() < Duration::from_secs(INDEX_VERSION_REFRESH_SECS) {
+				return Ok(version);
+			}
+		}
+
+		let version = db.index_version().await?;
+		self.index_versions.lock().unwrap().insert(profile.to_string(), (version.clone(), Instant::now()));
+		Ok(version)
+	}
+
+	/// The embeddings `lookup` and `insert` compare queries with, `None` when only exact matches hit. The model runs on
+	/// the blocking pool, as it would stall the workers serving the other requests.
+	pub async fn query_embeddings<M: EmbeddingsModel + Send + Sync + 'static>(&self, query: &str, model: Arc<M>) -> Result<Option<Embeddings>> {
+		if !self.is_enabled() || self.similarity_threshold.is_none() {
+			return Ok(None);
+		}
+
+		let key = normalize_query(query);
+		let embeddings = tokio::task::spawn_blocking(move || model.embed(&key)).await??;
+		Ok(Some(embeddings))
+	}
+
+	pub fn lookup(&self, query: &str, index_version: &str, query_embeddings: Option<&Embeddings>) -> Option<CachedAnswer> {
+		if !self.is_enabled() {
+			return None;
+		}
+
+		let key = (index_version.to_string(), normalize_query(query));
+		let entries = self.entries.read().unwrap();
+
+		if let Some(entry) = entries.by_key.get(&key).filter(|entry| entry.created_at.elapsed() < self.ttl) {
+			log::info!("Answer cache hit");
+			log::debug!("Answer cache hit for query: {}", query);
+			return Some(entry.answer.clone());
+		}
+
+		let (threshold, query_embeddings) = self.similarity_threshold.zip(query_embeddings)?;
+		// At most `max_entries` entries to compare with
+		let best_match = entries
+			.by_key
+			.iter()
+			.filter(|((version, _), entry)| version == index_version && entry.created_at.elapsed() < self.ttl)
+			.filter_map(|((_, key), entry)| {
+				entry
+					.embeddings
+					.as_ref()
+					.map(|embeddings| (key, entry, cosine_similarity(ArrayView1::from(query_embeddings), ArrayView1::from(embeddings))))
+			})
+			.max_by(|a, b| a.2.total_cmp(&b.2));
+
+		match best_match {
+			Some((key, entry, similarity)) if similarity >= threshold => {
+				log::info!("Answer cache hit for a similar query, score {:.3}", similarity);
+				log::debug!("Answer cache hit for query: {} (similar to '{}', score {:.3})", query, key, similarity);
+				Some(entry.answer.clone())
+			}
+			_ => None
+		}
+	}
+
+	pub fn insert(&self, query: &str, index_version: &str, query_embeddings: Option<Embeddings>, answer: CachedAnswer) {
+		if !self.is_enabled() || answer.answer.is_empty() {
+			return;
+		}
+
+		let key = (index_version.to_string(), normalize_query(query));
+		let mut entries = self.entries.write().unwrap();
+		let Entries { by_key, order } = &mut *entries;
+
+		if by_key.remove(&key).is_some() {
+			order.retain(|other| other != &key);
+		}
+		// Entries of other profiles and prompt versions share the cache, stale index versions expire with the TTL
+		while let Some(oldest) = order.front() {
+			let expired = by_key.get(oldest).map_or(true, |entry| entry.created_at.elapsed() >= self.ttl);
+			if !expired && order.len() < self.max_entries {
+				break;
+			}
+			if let Some(oldest) = order.pop_front() {
+				by_key.remove(&oldest);
+			}
+		}
+
+		order.push_back(key.clone());
+		by_key.insert(key, Entry {
+			embeddings: query_embeddings,
+			created_at: Instant::now(),
+			answer
+		});
+	}
+}
+
+// Case, punctuation and whitespace differences should not cause a cache miss
+fn normalize_query(query: &str) -> String {
+	query
+		.to_lowercase()
+		.split_whitespace()
+		.map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+		.filter(|word| !word.is_empty())
+		.collect::<Vec<&str>>()
+		.join(" ")
+}
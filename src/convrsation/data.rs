@@ -2,13 +2,37 @@ use std::str::FromStr;
 
 use openai_api_rs::v1::chat_completion::FunctionCall;
 use serde::Deserialize;
+use utoipa::ToSchema;
 
+use super::settings::ModelOverrides;
+use crate::db::SearchFilter;
 use crate::prelude::*;
 use crate::utils::functions::Function;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct Query {
-	pub query: String
+	/// The question to answer, in any language
+	#[schema(example = "How long must I stay in Canada to keep my permanent resident status?")]
+	pub query: String,
+	/// The corpus to answer from, the default profile when unset
+	#[serde(default)]
+	#[schema(example = "default")]
+	pub profile: Option<String>,
+	/// Restricts every search to the documents matching it
+	#[serde(default)]
+	pub filter: SearchFilter,
+	/// Overrides the model settings used to pick the documents to search
+	#[serde(default)]
+	pub planning: Option<ModelOverrides>,
+	/// Overrides the model settings used to write the final answer
+	#[serde(default)]
+	pub answer: Option<ModelOverrides>
+}
+
+impl Query {
+	pub fn has_overrides(&self) -> bool {
+		[&self.planning, &self.answer].into_iter().flatten().any(|overrides| !overrides.is_empty())
+	}
 }
 
 impl ToString for Query {
@@ -17,15 +41,47 @@ impl ToString for Query {
 	}
 }
 
+/// A question answered earlier in the same session
+#[derive(Debug, Clone)]
+pub struct Turn {
+	pub question: String,
+	pub answer: String
+}
+
+/// Why a conversation stopped before producing an answer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cancellation {
+	/// Nobody is listening to the events anymore, e.g. the browser tab was closed
+	ClientDisconnected,
+	/// Cancelled through the cancel API or the WebSocket session
+	Requested
+}
+
+impl std::fmt::Display for Cancellation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Cancellation::ClientDisconnected => write!(f, "Conversation cancelled: client disconnected"),
+			Cancellation::Requested => write!(f, "Conversation cancelled on request")
+		}
+	}
+}
+
+impl std::error::Error for Cancellation {}
+
 #[derive(Debug)]
 pub struct RelevantChunk {
 	pub path: String,
-	pub content: String
+	pub content: String,
+	/// Where the document is published, when known
+	pub url: Option<String>
 }
 
 impl ToString for RelevantChunk {
 	fn to_string(&self) -> String {
-		format!("##Relevant file chunk##\nPath argument:{}\nRelevant content: {}", self.path, self.content.trim())
+		match &self.url {
+			Some(url) => format!("##Relevant file chunk##\nPath argument:{}\nSource URL:{}\nRelevant content: {}", self.path, url, self.content.trim()),
+			None => format!("##Relevant file chunk##\nPath argument:{}\nRelevant content: {}", self.path, self.content.trim())
+		}
 	}
 }
 
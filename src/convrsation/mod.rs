@@ -1,48 +1,83 @@
-#![allow(unused_must_use)]
+pub mod cache;
 pub mod data;
+pub mod profiles;
 mod prompts;
+pub mod settings;
+pub mod templates;
 
-use std::env;
 use std::sync::Arc;
 
-use actix_web_lab::sse::Sender;
-use openai_api_rs::v1::chat_completion::FinishReason;
-use openai_api_rs::v1::{
-	api::Client,
-	chat_completion::{ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, MessageRole}
+use openai_api_rs::v1::chat_completion::{
+	ChatCompletionChoice, ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, FinishReason, MessageRole
 };
-use prompts::{generate_completion_request, system_message};
+use prompts::generate_completion_request;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
-use self::prompts::{answer_generation_prompt, sanitize_query_prompt};
-use crate::constants::RELEVANT_CHUNKS_LIMIT;
+use self::cache::CachedAnswer;
+use self::settings::{ChatSettings, SearchSettings};
+use self::templates::Prompts;
 pub use crate::convrsation::data::*;
+use crate::constants::MAX_FUNCTION_CALLS;
+use crate::llm::ChatClient;
 use crate::prelude::*;
-use crate::routes::events::{emit, QueryEvent};
-use crate::utils::functions::{paths_to_completion_message, relevant_chunks_to_completion_message, search_documents, search_file, search_path, Function};
-use crate::{db::RepositoryEmbeddingsDB, embeddings::EmbeddingsModel};
+use crate::routes::events::{
+	AnswerPayload, EventSender, GetLinksPayload, ProcessQueryPayload, QueryEvent, SearchDocumentsPayload, SearchFilePayload, SearchPathPayload
+};
+use crate::utils::functions::{
+	links_to_completion_message, paths_to_completion_message, relevant_chunks_to_completion_message, resolve_document_path, search_documents, search_file,
+	search_path, Function
+};
+use crate::{
+	db::{RepositoryEmbeddingsDB, SearchFilter},
+	embeddings::EmbeddingsModel
+};
+
+/// How a conversation talks to the model: the client, the model settings with the overrides of the query applied, and
+/// the prompt templates
+pub struct Assistant {
+	pub llm: Arc<dyn ChatClient>,
+	pub settings: ChatSettings,
+	pub prompts: Arc<Prompts>
+}
 
 pub struct Conversation<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> {
+	id: String,
+	original_query: String,
 	query: data::Query,
-	client: Client,
+	assistant: Assistant,
 	messages: Vec<ChatCompletionMessage>,
 	db: Arc<D>,
 	model: Arc<M>,
-	sender: Sender
+	sender: EventSender,
+	retrieved_paths: Vec<String>,
+	answer: Option<String>,
+	// Events emitted by `generate`, kept so that the answer can be cached and replayed
+	events: Vec<QueryEvent>,
+	cancellation: CancellationToken,
+	search: SearchSettings,
+	// Set by the query, every search is restricted to it
+	filter: SearchFilter
 }
 
 impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
-	pub async fn initiate(mut query: data::Query, db: Arc<D>, model: Arc<M>, sender: Sender) -> Result<Self> {
-		log::info!("Initiating conversation with query: {}", &query.query);
-		emit(&sender, QueryEvent::ProcessQuery(None)).await;
+	pub async fn initiate(id: String, mut query: data::Query, db: Arc<D>, model: Arc<M>, assistant: Assistant, sender: EventSender) -> Result<Self> {
+		// Queries may hold personal details, they are kept out of the info level that is exported
+		tracing::info!(answer_id = %id, query_length = query.query.chars().count(), "Initiating conversation");
+		tracing::debug!(answer_id = %id, query = %query.query, "Received query");
+		sender
+			.send(QueryEvent::ProcessQuery(ProcessQueryPayload { answer_id: id.clone() }))
+			.await
+			.map_err(|_| Cancellation::ClientDisconnected)?;
 
-		query.query = sanitize_query(&query.query)?;
-		let client = Client::new(env::var("OPENAI_API_KEY").unwrap());
+		let original_query = query.query.clone();
+		query.query = sanitize_query(&query.query, &assistant).await?;
 		let messages = vec![
 			ChatCompletionMessage {
 				name: None,
 				function_call: None,
 				role: MessageRole::system,
-				content: system_message()
+				content: assistant.prompts.system_message()
 			},
 			ChatCompletionMessage {
 				name: None,
@@ -51,173 +86,373 @@ impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
 				content: query.to_string()
 			},
 		];
-		log::debug!("Initiated conversation with sanitized query: {}\n\n Messages: {:?}", &query.query, &messages);
+		tracing::debug!(answer_id = %id, sanitized_query = %query.query, "Initiated conversation");
 		Ok(Self {
+			id,
+			original_query,
 			query,
-			client,
+			assistant,
 			messages,
 			db,
 			model,
-			sender
+			sender,
+			retrieved_paths: Vec::new(),
+			answer: None,
+			events: Vec::new(),
+			cancellation: CancellationToken::new(),
+			search: SearchSettings::default(),
+			filter: SearchFilter::default()
 		})
 	}
 
+	/// Places earlier questions and answers of the session before the current query, so follow-ups can refer to them
+	pub fn with_history(mut self, history: &[Turn]) -> Self {
+		let turns = history.iter().flat_map(|turn| {
+			[
+				ChatCompletionMessage {
+					name: None,
+					function_call: None,
+					role: MessageRole::user,
+					content: turn.question.clone()
+				},
+				ChatCompletionMessage {
+					name: None,
+					function_call: None,
+					role: MessageRole::assistant,
+					content: turn.answer.clone()
+				},
+			]
+		});
+		// Keep the system message first and the current query last
+		self.messages.splice(1..1, turns);
+		self
+	}
+
+	/// Stops the conversation at the next step once `cancellation` is cancelled
+	pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+		self.cancellation = cancellation;
+		self
+	}
+
+	pub fn with_search(mut self, search: SearchSettings) -> Self {
+		self.search = search;
+		self
+	}
+
+	pub fn with_filter(mut self, filter: SearchFilter) -> Self {
+		self.filter = filter;
+		self
+	}
+
+	pub fn id(&self) -> &str {
+		&self.id
+	}
+
+	pub fn original_query(&self) -> &str {
+		&self.original_query
+	}
+
+	pub fn sanitized_query(&self) -> &str {
+		&self.query.query
+	}
+
+	/// Identifies the prompt templates the answer was generated with
+	pub fn prompt_version(&self) -> &str {
+		self.assistant.prompts.version()
+	}
+
+	/// Paths of every document returned by the functions so far, in retrieval order and without duplicates
+	pub fn retrieved_paths(&self) -> &[String] {
+		&self.retrieved_paths
+	}
+
+	/// The final answer, once `generate` has completed
+	pub fn answer(&self) -> Option<&str> {
+		self.answer.as_deref()
+	}
+
+	/// The answer with the events that led to it, once `generate` has completed
+	pub fn cached_answer(&self) -> Option<CachedAnswer> {
+		self.answer.as_ref().map(|answer| CachedAnswer {
+			events: self.events.clone(),
+			retrieved_paths: self.retrieved_paths.clone(),
+			answer: answer.clone()
+		})
+	}
+
+	/// Completes the conversation with a cached answer instead of calling `generate`
+	pub async fn replay(&mut self, cached: CachedAnswer) -> Result<()> {
+		tracing::info!(answer_id = %self.id, "Replaying cached answer");
+		self.retrieved_paths = cached.retrieved_paths;
+		self.answer = Some(cached.answer);
+		for event in cached.events {
+			self.emit(event).await?;
+		}
+		Ok(())
+	}
+
+	async fn emit(&mut self, event: QueryEvent) -> Result<()> {
+		self.events.push(event.clone());
+		self.sender.send(event).await.map_err(|_| Cancellation::ClientDisconnected)?;
+		Ok(())
+	}
+
+	// Called between steps, so that no further OpenAI requests are made for a conversation nobody waits for
+	fn ensure_active(&self) -> Result<()> {
+		if self.cancellation.is_cancelled() {
+			Err(Cancellation::Requested.into())
+		} else if self.sender.is_closed() {
+			Err(Cancellation::ClientDisconnected.into())
+		} else {
+			Ok(())
+		}
+	}
+
+	// Only the documents whose content went into the context count as retrieved, under their full path as the
+	// evaluation resolves citations
+	fn record_retrieved_chunks(&mut self, chunks: &[RelevantChunk]) {
+		for chunk in chunks {
+			let path = resolve_document_path(&chunk.path, &self.search.documents_path);
+			if !self.retrieved_paths.contains(&path) {
+				self.retrieved_paths.push(path);
+			}
+		}
+	}
+
+	// Gives the model the link to cite for each chunk
+	fn with_urls(&self, chunks: Vec<RelevantChunk>) -> Vec<RelevantChunk> {
+		chunks
+			.into_iter()
+			.map(|chunk| RelevantChunk {
+				url: Some(self.search.url_for(&chunk.path)),
+				..chunk
+			})
+			.collect()
+	}
+
 	fn append_message(&mut self, message: ChatCompletionMessage) {
 		self.messages.push(message);
 	}
 
 	fn prepare_final_explanation_message(&mut self) {
-		// Update the system prompt using answer_generation_prompt()
+		// Update the system prompt using the answer template
 		self.messages[0] = ChatCompletionMessage {
 			name: None,
 			function_call: None,
 			role: MessageRole::system,
-			content: answer_generation_prompt()
+			content: self.assistant.prompts.answer_generation_prompt()
 		}
 	}
 
-	fn send_request(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
-		log::debug!("Sending request to OpenAI API: \n{:?}", &request);
-		Ok(self.client.chat_completion(request)?)
+	async fn send_request(&self, request: ChatCompletionRequest, purpose: &'static str) -> Result<ChatCompletionResponse> {
+		chat_completion(self.assistant.llm.as_ref(), request, purpose).await
 	}
 
+	#[tracing::instrument(name = "generate", skip_all, fields(answer_id = %self.id))]
 	pub async fn generate(&mut self) -> Result<()> {
-		#[allow(unused_labels)]
-		'conversation: loop {
+		for _ in 0..MAX_FUNCTION_CALLS {
+			self.ensure_active()?;
+
 			// Generate a request with the message history and functions
-			let request = generate_completion_request(self.messages.clone(), "auto");
-
-			match self.send_request(request) {
-				Ok(response) => {
-					log::debug!("Response: {:?}", &response);
-					match response.choices[0].finish_reason {
-						FinishReason::function_call => {
-							log::debug!("Finish reason: Function call");
-							if let Some(function_call) = response.choices[0].message.function_call.clone() {
-								let parsed_function_call = ParsedFunctionCall::try_from(&function_call)?;
-								let function_call_message = ChatCompletionMessage {
-									name: None,
-									function_call: Some(function_call),
-									role: MessageRole::assistant,
-									content: String::new()
-								};
-								self.append_message(function_call_message);
-								log::debug!("Function call: {:?}", &parsed_function_call);
-								match parsed_function_call.name {
-									Function::SearchDocuments => {
-										let query: &str = parsed_function_call.args["query"].as_str().unwrap_or_default();
-										log::debug!("SearchDocuments with params: {}", query);
-
-										emit(&self.sender, QueryEvent::SearchDocuments(Some(parsed_function_call.clone().args))).await;
-
-										let relevant_chunks = search_documents(
-											query,
-											self.model.as_ref(),
-											self.db.as_ref(),
-											crate::constants::RELEVANT_FILES_LIMIT,
-											RELEVANT_CHUNKS_LIMIT
-										)
-										.await?;
-										let completion_message = relevant_chunks_to_completion_message(parsed_function_call.name, relevant_chunks);
-										log::debug!("Completion message: {:?}", &completion_message);
-										self.append_message(completion_message);
-									}
-									Function::SearchFile => {
-										let query: &str = parsed_function_call.args["query"].as_str().unwrap_or_default();
-										let path: &str = parsed_function_call.args["path"].as_str().unwrap_or_default();
-
-										log::debug!("SearchFile at {} with params: {}", path, query);
-
-										emit(&self.sender, QueryEvent::SearchFile(Some(parsed_function_call.clone().args))).await;
-
-										let relevant_chunks = search_file(path, query, self.model.as_ref(), RELEVANT_CHUNKS_LIMIT).await?;
-										let completion_message = relevant_chunks_to_completion_message(parsed_function_call.name, relevant_chunks);
-										log::debug!("Completion message: {:?}", &completion_message);
-										self.append_message(completion_message);
-									}
-									Function::SearchPath => {
-										let path: &str = parsed_function_call.args["path"].as_str().unwrap_or_default();
-										log::debug!("SearchPath with params: {}", path);
-
-										emit(&self.sender, QueryEvent::SearchPath(Some(parsed_function_call.clone().args))).await;
-
-										let fuzzy_matched_paths = search_path(path, self.db.as_ref(), 1).await?;
-										let completion_message = paths_to_completion_message(parsed_function_call.name, fuzzy_matched_paths);
-										log::debug!("Completion message: {:?}", &completion_message);
-										self.append_message(completion_message);
-									}
-									Function::Done => {
-										log::debug!("Generating final response");
-										self.prepare_final_explanation_message();
-
-										// Generate a request with the message history and no functions
-										let request = generate_completion_request(self.messages.clone(), "none");
-
-										emit(&self.sender, QueryEvent::GenerateResponse(None)).await;
-
-										let response = match self.send_request(request) {
-											Ok(response) => response,
-											Err(e) => {
-												log::debug!("Error: {}", e.to_string());
-												return Err(e);
-											}
-										};
-										log::info!("Response: {:?}", &response);
-										let response = response.choices[0].message.content.clone().unwrap_or_default();
-
-										emit(&self.sender, QueryEvent::Done(Some(response.into()))).await;
-
-										return Ok(());
-									}
-								}
-							};
-						}
+			let request = generate_completion_request(self.messages.clone(), "auto", &self.assistant.settings.planning);
+			let response = self.send_request(request, "planning").await?;
+			let choice = first_choice(&response)?;
 
-						FinishReason::stop => {
-							log::debug!("Finish reason: Stop");
-							// As of yet, there isn't a robust way to instruct the model to respond with function calls only except for switching to
-							// GPT-4 We can only suggest it do so in the system message
-							// prompts.rs#L127
-							// A warning from OpenAI's official documentation:
-							// "gpt-3.5-turbo-0301 does not always pay strong attention to system messages. Future models will be trained to pay
-							// strong attention to system messages." "If you are using GPT-3.5-turbo, you can already utilize the system role input;
-							// however, be aware that it will not pay strong attention to it. On the other hand, if you have access to the GPT-4
-							// preview, you can take full advantage of this powerful feature."
-
-							let response = response.choices[0].message.content.clone().unwrap_or_default();
-							log::info!("Response: {}", &response);
-							emit(&self.sender, QueryEvent::Done(Some(response.into()))).await;
+			match choice.finish_reason {
+				FinishReason::function_call => {
+					if let Some(function_call) = choice.message.function_call.clone() {
+						let parsed_function_call = ParsedFunctionCall::try_from(&function_call)?;
+						let function_call_message = ChatCompletionMessage {
+							name: None,
+							function_call: Some(function_call),
+							role: MessageRole::assistant,
+							content: String::new()
+						};
+						self.append_message(function_call_message);
 
+						let span = tracing::info_span!("function_call", function = %parsed_function_call.name.to_string());
+						if self.call_function(parsed_function_call).instrument(span).await? {
 							return Ok(());
 						}
-
-						_ => {
-							log::debug!("Model returned an unexpected response.");
-							return Err(anyhow::anyhow!("Model returned an unexpected response."));
-						}
 					}
 				}
-				Err(e) => {
-					log::debug!("Error: {}", e.to_string());
-					return Err(e);
+
+				FinishReason::stop => {
+					// As of yet, there isn't a robust way to instruct the model to respond with function calls only except for switching to
+					// GPT-4 We can only suggest it do so in the system message
+					// prompts.rs#L127
+					// A warning from OpenAI's official documentation:
+					// "gpt-3.5-turbo-0301 does not always pay strong attention to system messages. Future models will be trained to pay
+					// strong attention to system messages." "If you are using GPT-3.5-turbo, you can already utilize the system role input;
+					// however, be aware that it will not pay strong attention to it. On the other hand, if you have access to the GPT-4
+					// preview, you can take full advantage of this powerful feature."
+
+					let response = choice.message.content.clone().unwrap_or_default();
+					tracing::info!(length = response.len(), "Answered without calling done");
+					self.answer = Some(response.clone());
+					self.emit(QueryEvent::Done(AnswerPayload(response))).await?;
+
+					return Ok(());
+				}
+
+				_ => {
+					return Err(anyhow::anyhow!("Model returned an unexpected response."));
 				}
-			};
+			}
 		}
+
+		// The model keeps searching, it has to answer from what it found so far
+		tracing::warn!(max = MAX_FUNCTION_CALLS, "Answering after too many function calls");
+		let done = ParsedFunctionCall {
+			name: Function::Done,
+			args: serde_json::Value::Null
+		};
+		let span = tracing::info_span!("function_call", function = %done.name.to_string());
+		self.call_function(done).instrument(span).await?;
+		Ok(())
+	}
+
+	// Runs the function the model called and adds its result to the messages, returns whether it generated the answer
+	async fn call_function(&mut self, parsed_function_call: ParsedFunctionCall) -> Result<bool> {
+		match parsed_function_call.name {
+			Function::SearchDocuments => {
+				let query: &str = parsed_function_call.args["query"].as_str().unwrap_or_default();
+				let model_filter = parse_filter(&parsed_function_call.args["filter"]);
+				let filter = self.filter.and(&model_filter);
+				tracing::debug!(query, filter = ?filter, "Searching documents");
+
+				self.emit(QueryEvent::SearchDocuments(SearchDocumentsPayload {
+					query: query.to_string(),
+					filter: (!model_filter.is_empty()).then_some(model_filter)
+				}))
+				.await?;
+
+				let relevant_chunks = search_documents(query, self.model.as_ref(), self.db.as_ref(), &filter, &self.search).await?;
+				let relevant_chunks = self.with_urls(relevant_chunks);
+				self.record_retrieved_chunks(&relevant_chunks);
+				tracing::debug!(chunks = relevant_chunks.len(), "Found relevant chunks");
+				let completion_message = relevant_chunks_to_completion_message(parsed_function_call.name, relevant_chunks);
+				self.append_message(completion_message);
+			}
+			Function::SearchFile => {
+				let query: &str = parsed_function_call.args["query"].as_str().unwrap_or_default();
+				let path: &str = parsed_function_call.args["path"].as_str().unwrap_or_default();
+				tracing::debug!(path, query, "Searching file");
+
+				self.emit(QueryEvent::SearchFile(SearchFilePayload {
+					query: query.to_string(),
+					path: path.to_string()
+				}))
+				.await?;
+
+				let relevant_chunks = search_file(path, query, self.model.as_ref(), &self.search.documents_path, self.search.chunks_limit).await?;
+				let relevant_chunks = self.with_urls(relevant_chunks);
+				self.record_retrieved_chunks(&relevant_chunks);
+				tracing::debug!(chunks = relevant_chunks.len(), "Found relevant chunks");
+				let completion_message = relevant_chunks_to_completion_message(parsed_function_call.name, relevant_chunks);
+				self.append_message(completion_message);
+			}
+			Function::SearchPath => {
+				let path: &str = parsed_function_call.args["path"].as_str().unwrap_or_default();
+				tracing::debug!(path, "Searching path");
+
+				self.emit(QueryEvent::SearchPath(SearchPathPayload { path: path.to_string() })).await?;
+
+				let fuzzy_matched_paths = search_path(path, self.db.as_ref(), &self.filter, 1).await?;
+				tracing::debug!(paths = ?fuzzy_matched_paths, "Matched paths");
+				let completion_message = paths_to_completion_message(parsed_function_call.name, fuzzy_matched_paths);
+				self.append_message(completion_message);
+			}
+			Function::GetLinks => {
+				let path: &str = parsed_function_call.args["path"].as_str().unwrap_or_default();
+				tracing::debug!(path, "Getting links");
+
+				self.emit(QueryEvent::GetLinks(GetLinksPayload { path: path.to_string() })).await?;
+
+				// Links are stored with the documents path, as the paths returned by the other functions
+				let full_path = resolve_document_path(path, &self.search.documents_path);
+				let links = self.db.get_links(&full_path, &self.filter).await?;
+				tracing::debug!(outgoing = links.outgoing.len(), incoming = links.incoming.len(), "Found links");
+				let completion_message = links_to_completion_message(parsed_function_call.name, path, links);
+				self.append_message(completion_message);
+			}
+			Function::Done => {
+				self.prepare_final_explanation_message();
+
+				// Generate a request with the message history and no functions
+				let request = generate_completion_request(self.messages.clone(), "none", &self.assistant.settings.answer);
+
+				self.emit(QueryEvent::GenerateResponse).await?;
+				self.ensure_active()?;
+
+				let response = self.send_request(request, "answer").await?;
+				let response = first_choice(&response)?.message.content.clone().unwrap_or_default();
+				tracing::info!(length = response.len(), "Generated the answer");
+				self.answer = Some(response.clone());
+
+				self.emit(QueryEvent::Done(AnswerPayload(response))).await?;
+
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+}
+
+// Sends one request in a span of its own, recording what the model did and the tokens it used
+async fn chat_completion(llm: &dyn ChatClient, request: ChatCompletionRequest, purpose: &'static str) -> Result<ChatCompletionResponse> {
+	let span = tracing::info_span!(
+		"llm_request",
+		purpose,
+		model = %request.model,
+		messages = request.messages.len(),
+		finish_reason = tracing::field::Empty,
+		prompt_tokens = tracing::field::Empty,
+		completion_tokens = tracing::field::Empty
+	);
+	let response = match llm.chat_completion(request).instrument(span.clone()).await {
+		Ok(response) => response,
+		Err(e) => {
+			tracing::error!(parent: &span, "Chat completion failed: {:#}", e);
+			return Err(e);
+		}
+	};
+	if let Some(choice) = response.choices.first() {
+		span.record("finish_reason", format!("{:?}", choice.finish_reason).as_str());
+	}
+	span.record("prompt_tokens", response.usage.prompt_tokens);
+	span.record("completion_tokens", response.usage.completion_tokens);
+	Ok(response)
+}
+
+// Providers, fixtures and scripts can reply without any choice, which fails the conversation with an error event
+fn first_choice(response: &ChatCompletionResponse) -> Result<&ChatCompletionChoice> {
+	response.choices.first().ok_or_else(|| anyhow::anyhow!("The model replied with no choices"))
+}
+
+// The model may leave the filter out or get it wrong, in which case only the filter of the query applies
+fn parse_filter(args: &serde_json::Value) -> SearchFilter {
+	if args.is_null() {
+		return SearchFilter::default();
 	}
+	serde_json::from_value(args.clone()).unwrap_or_else(|e| {
+		tracing::warn!("Ignoring invalid search filter {}: {}", args, e);
+		SearchFilter::default()
+	})
 }
 
-fn sanitize_query(query: &str) -> Result<String> {
+async fn sanitize_query(query: &str, assistant: &Assistant) -> Result<String> {
 	let message = ChatCompletionMessage {
 		name: None,
 		function_call: None,
 		role: MessageRole::user,
-		content: sanitize_query_prompt(query)
+		content: assistant.prompts.sanitize_query_prompt(query)
 	};
-	let client = Client::new(env::var("OPENAI_API_KEY")?);
-	let request = generate_completion_request(vec![message], "none");
-	let response = client.chat_completion(request)?;
-	if let FinishReason::stop = response.choices[0].finish_reason {
-		let sanitized_query = response.choices[0].message.content.clone().unwrap_or_default();
+	let request = generate_completion_request(vec![message], "none", &assistant.settings.planning);
+	let response = chat_completion(assistant.llm.as_ref(), request, "sanitize_query").await?;
+	let choice = first_choice(&response)?;
+	if let FinishReason::stop = choice.finish_reason {
+		let sanitized_query = choice.message.content.clone().unwrap_or_default();
 		if sanitized_query.is_empty() {
 			Err(anyhow::anyhow!("No query found"))
 		} else {
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::settings::SearchSettings;
+use super::templates::PromptStore;
+use crate::config::Config;
+use crate::db::qdrant::QdrantDB;
+use crate::db::RepositoryEmbeddingsDB;
+use crate::prelude::*;
+
+/// A document corpus served by the oracle, with its own collection, prompts and citation links
+pub struct CorpusProfile {
+	pub name: String,
+	pub domain: String,
+	pub languages: Vec<String>,
+	pub db: Arc<QdrantDB>,
+	pub prompts: Arc<PromptStore>,
+	pub search: SearchSettings
+}
+
+impl CorpusProfile {
+	pub async fn is_indexed(&self) -> bool {
+		self.db.is_indexed().await.unwrap_or_default()
+	}
+}
+
+/// Describes a profile to the clients choosing one
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProfileSummary {
+	#[schema(example = "ircc")]
+	pub name: String,
+	#[schema(example = "immigration, refugees and citizenship of Canada")]
+	pub domain: String,
+	pub languages: Vec<String>,
+	pub indexed: bool
+}
+
+/// Every configured profile. The first one answers the queries that do not name a profile.
+pub struct Profiles {
+	profiles: Vec<Arc<CorpusProfile>>
+}
+
+impl Profiles {
+	pub fn from_config(config: &Config) -> Result<Self> {
+		let profiles = config.profiles();
+		let Some(first) = profiles.first() else {
+			return Err(anyhow::anyhow!("No profile is configured"));
+		};
+		let db = QdrantDB::initialize(&config.qdrant, &first.collection)?;
+
+		let profiles = profiles
+			.iter()
+			.map(|profile| {
+				log::info!("Loading profile {} (collection {})", profile.name, profile.collection);
+				Ok(Arc::new(CorpusProfile {
+					name: profile.name.clone(),
+					domain: profile.domain.clone(),
+					languages: profile.languages.clone(),
+					db: Arc::new(db.with_collection(&profile.collection)),
+					prompts: Arc::new(PromptStore::from_config(&config.prompts, profile)?),
+					search: SearchSettings::from_config(profile, &config.search)
+				}))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(Self { profiles })
+	}
+
+	/// The profile named `name`, or the default one
+	pub fn get(&self, name: Option<&str>) -> Result<Arc<CorpusProfile>> {
+		match name {
+			None => Ok(self.profiles[0].clone()),
+			Some(name) => self.profiles.iter().find(|profile| profile.name == name).cloned().ok_or_else(|| {
+				let names = self.profiles.iter().map(|profile| profile.name.as_str()).collect::<Vec<_>>();
+				anyhow::anyhow!("Unknown profile {}, use one of: {}", name, names.join(", "))
+			})
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Arc<CorpusProfile>> {
+		self.profiles.iter()
+	}
+
+	pub async fn summaries(&self) -> Vec<ProfileSummary> {
+		let mut summaries = Vec::with_capacity(self.profiles.len());
+		for profile in &self.profiles {
+			summaries.push(ProfileSummary {
+				name: profile.name.clone(),
+				domain: profile.domain.clone(),
+				languages: profile.languages.clone(),
+				indexed: profile.is_indexed().await
+			});
+		}
+		summaries
+	}
+}
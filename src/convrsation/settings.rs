@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::config::{ChatConfig, ChatModelConfig, ProfileConfig, SearchConfig};
+use crate::constants::{
+	CHAT_COMPLETION_MAX_TOKENS_LIMIT, DOCUMENTS_PATH_DEFAULT, LINKED_FILES_LIMIT_DEFAULT, RELEVANT_CHUNKS_LIMIT, RELEVANT_FILES_LIMIT, SITE_BASE_URL_DEFAULT
+};
+use crate::prelude::*;
+
+/// Model parameters of one kind of chat completion request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSettings {
+	pub model: String,
+	pub temperature: f64,
+	/// Left to the API default when unset
+	pub max_tokens: Option<u32>
+}
+
+impl From<&ChatModelConfig> for ModelSettings {
+	fn from(config: &ChatModelConfig) -> Self {
+		Self {
+			model: config.model.clone(),
+			temperature: config.temperature,
+			max_tokens: config.max_tokens
+		}
+	}
+}
+
+/// Per-request changes to `ModelSettings`, checked against the deployment's `ChatSettings`
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ModelOverrides {
+	/// One of the models allowed by the deployment
+	#[schema(example = "gpt-4")]
+	pub model: Option<String>,
+	/// Between 0 and 2
+	pub temperature: Option<f64>,
+	pub max_tokens: Option<u32>
+}
+
+impl ModelOverrides {
+	pub fn is_empty(&self) -> bool {
+		self.model.is_none() && self.temperature.is_none() && self.max_tokens.is_none()
+	}
+}
+
+/// Settings of the planning requests, which pick the functions to call and sanitise the query, and of the request
+/// generating the final answer
+#[derive(Debug, Clone)]
+pub struct ChatSettings {
+	pub planning: ModelSettings,
+	pub answer: ModelSettings,
+	// Models a request may switch to
+	allowed_models: Vec<String>
+}
+
+impl ChatSettings {
+	pub fn from_config(config: &ChatConfig) -> Self {
+		let planning = ModelSettings::from(&config.planning);
+		let answer = ModelSettings::from(&config.answer);
+
+		let mut allowed_models = config.allowed_models.clone();
+		for model in [&planning.model, &answer.model] {
+			if !allowed_models.contains(model) {
+				allowed_models.push(model.clone());
+			}
+		}
+
+		Self { planning, answer, allowed_models }
+	}
+
+	/// The settings for one request, with its overrides applied
+	pub fn resolve(&self, planning: Option<&ModelOverrides>, answer: Option<&ModelOverrides>) -> Result<Self> {
+		Ok(Self {
+			planning: self.apply(&self.planning, planning)?,
+			answer: self.apply(&self.answer, answer)?,
+			allowed_models: self.allowed_models.clone()
+		})
+	}
+
+	fn apply(&self, settings: &ModelSettings, overrides: Option<&ModelOverrides>) -> Result<ModelSettings> {
+		let Some(overrides) = overrides else {
+			return Ok(settings.clone());
+		};
+
+		if let Some(model) = &overrides.model {
+			if !self.allowed_models.contains(model) {
+				return Err(anyhow::anyhow!("Model {} is not allowed, use one of: {}", model, self.allowed_models.join(", ")));
+			}
+		}
+		if let Some(temperature) = overrides.temperature {
+			validate_temperature(temperature)?;
+		}
+		if let Some(max_tokens) = overrides.max_tokens {
+			validate_max_tokens(max_tokens)?;
+		}
+
+		Ok(ModelSettings {
+			model: overrides.model.clone().unwrap_or(settings.model.clone()),
+			temperature: overrides.temperature.unwrap_or(settings.temperature),
+			max_tokens: overrides.max_tokens.or(settings.max_tokens)
+		})
+	}
+}
+
+/// Where the function calls read the documents from, how much of them they return and where the documents are published
+#[derive(Debug, Clone)]
+pub struct SearchSettings {
+	pub documents_path: PathBuf,
+	pub files_limit: usize,
+	pub chunks_limit: usize,
+	pub linked_files_limit: usize,
+	pub site_base_url: String,
+	pub strip_suffix: Option<String>
+}
+
+impl SearchSettings {
+	pub fn from_config(profile: &ProfileConfig, search: &SearchConfig) -> Self {
+		Self {
+			documents_path: profile.documents_path.clone(),
+			files_limit: search.relevant_files_limit,
+			chunks_limit: search.relevant_chunks_limit,
+			linked_files_limit: search.linked_files_limit,
+			site_base_url: profile.site_base_url.clone(),
+			strip_suffix: profile.strip_suffix.clone()
+		}
+	}
+
+	/// The web page a document was published at, used to cite it
+	pub fn url_for(&self, path: &str) -> String {
+		let base_path = self.documents_path.to_string_lossy();
+		let relative = path.strip_prefix(base_path.as_ref()).unwrap_or(path).trim_start_matches('/');
+		let relative = match &self.strip_suffix {
+			Some(suffix) => relative.strip_suffix(suffix.as_str()).unwrap_or(relative),
+			None => relative
+		};
+		format!("{}/{}", self.site_base_url.trim_end_matches('/'), relative)
+	}
+
+	/// The path of the document `url_for` gives `url` for, `None` when `url` is not on the site
+	pub fn path_for(&self, url: &str) -> Option<String> {
+		let relative = url.strip_prefix(self.site_base_url.trim_end_matches('/'))?;
+		let relative = relative.split(['#', '?']).next().unwrap_or_default().trim_start_matches('/');
+		let suffix = self.strip_suffix.as_deref().unwrap_or_default();
+		Some(self.documents_path.join(format!("{}{}", relative, suffix)).to_string_lossy().to_string())
+	}
+}
+
+impl Default for SearchSettings {
+	fn default() -> Self {
+		Self {
+			documents_path: DOCUMENTS_PATH_DEFAULT.into(),
+			files_limit: RELEVANT_FILES_LIMIT,
+			chunks_limit: RELEVANT_CHUNKS_LIMIT,
+			linked_files_limit: LINKED_FILES_LIMIT_DEFAULT,
+			site_base_url: SITE_BASE_URL_DEFAULT.into(),
+			strip_suffix: None
+		}
+	}
+}
+
+fn validate_temperature(temperature: f64) -> Result<()> {
+	if (0.0..=2.0).contains(&temperature) {
+		Ok(())
+	} else {
+		Err(anyhow::anyhow!("Temperature must be between 0 and 2, got {}", temperature))
+	}
+}
+
+fn validate_max_tokens(max_tokens: u32) -> Result<()> {
+	if (1..=CHAT_COMPLETION_MAX_TOKENS_LIMIT).contains(&max_tokens) {
+		Ok(())
+	} else {
+		Err(anyhow::anyhow!("Max tokens must be between 1 and {}, got {}", CHAT_COMPLETION_MAX_TOKENS_LIMIT, max_tokens))
+	}
+}
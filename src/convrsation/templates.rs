@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use rand::seq::SliceRandom;
+
+use crate::config::{ProfileConfig, PromptsConfig};
+use crate::constants::PROMPTS_RELOAD_SECS;
+use crate::prelude::*;
+use crate::utils::hash::sha256_hex;
+
+/// The templates every prompt version must provide, with the variables each may use
+const TEMPLATES: [(Template, &str, &[&str]); 3] = [
+	(Template::System, "system.md", &COMMON_VARIABLES),
+	(Template::Answer, "answer.md", &COMMON_VARIABLES),
+	(Template::SanitizeQuery, "sanitize_query.md", &["site_base_url", "language", "languages", "date", "agency_name", "domain", "query"])
+];
+const COMMON_VARIABLES: [&str; 6] = ["site_base_url", "language", "languages", "date", "agency_name", "domain"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Template {
+	/// System message of the planning requests
+	System,
+	/// System message of the request writing the final answer
+	Answer,
+	/// Extracts the question from the raw user input
+	SanitizeQuery
+}
+
+/// Values substituted for `{{ name }}` placeholders, the same for every conversation of a profile
+#[derive(Debug, Clone)]
+pub struct PromptVariables {
+	pub site_base_url: String,
+	/// Language answers default to
+	pub language: String,
+	/// Every language the documents are published in, comma separated
+	pub languages: String,
+	pub agency_name: String,
+	pub domain: String
+}
+
+impl From<&ProfileConfig> for PromptVariables {
+	fn from(profile: &ProfileConfig) -> Self {
+		Self {
+			site_base_url: profile.site_base_url.clone(),
+			language: profile.languages.first().cloned().unwrap_or_default(),
+			languages: profile.languages.join(", "),
+			agency_name: profile.agency_name.clone(),
+			domain: profile.domain.clone()
+		}
+	}
+}
+
+/// One version of every template
+#[derive(Debug)]
+pub struct Prompts {
+	/// Directory name followed by the start of the SHA-256 of the templates, so that edits picked up by hot reload get a
+	/// new identifier while every build gives the same templates the same one
+	version: String,
+	templates: HashMap<Template, String>,
+	variables: PromptVariables
+}
+
+impl Prompts {
+	fn load(dir: &Path, name: &str, variables: PromptVariables) -> Result<Self> {
+		let mut templates = HashMap::new();
+		let mut contents = String::new();
+
+		for (template, file, allowed) in TEMPLATES {
+			let path = dir.join(file);
+			let text = std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Missing prompt template {}: {}", path.display(), e))?;
+			let text = text.trim_end().to_string();
+			for placeholder in placeholders(&text) {
+				if !allowed.contains(&placeholder.as_str()) {
+					return Err(anyhow::anyhow!("Unknown variable {{{{{}}}}} in prompt template {}", placeholder, path.display()));
+				}
+			}
+			contents.push_str(&text);
+			templates.insert(template, text);
+		}
+
+		if !placeholders(&templates[&Template::SanitizeQuery]).iter().any(|placeholder| placeholder == "query") {
+			return Err(anyhow::anyhow!("Prompt template {} must contain {{{{query}}}}", dir.join("sanitize_query.md").display()));
+		}
+
+		Ok(Self {
+			version: format!("{}+{}", name, &sha256_hex(&contents)[..8]),
+			templates,
+			variables
+		})
+	}
+
+	pub fn version(&self) -> &str {
+		&self.version
+	}
+
+	pub fn system_message(&self) -> String {
+		self.render(Template::System, None)
+	}
+
+	pub fn answer_generation_prompt(&self) -> String {
+		self.render(Template::Answer, None)
+	}
+
+	pub fn sanitize_query_prompt(&self, query: &str) -> String {
+		self.render(Template::SanitizeQuery, Some(&query.replace('`', "")))
+	}
+
+	// Substitutes every placeholder in a single pass, so that placeholders inside the query are left as they are
+	fn render(&self, template: Template, query: Option<&str>) -> String {
+		let date = prompt_date();
+		let text = &self.templates[&template];
+		let mut result = String::with_capacity(text.len());
+		let mut rest = text.as_str();
+		while let Some(start) = rest.find("{{") {
+			let Some(end) = rest[start..].find("}}") else {
+				break;
+			};
+			result.push_str(&rest[..start]);
+			match rest[start + 2..start + end].trim() {
+				"site_base_url" => result.push_str(&self.variables.site_base_url),
+				"language" => result.push_str(&self.variables.language),
+				"languages" => result.push_str(&self.variables.languages),
+				"agency_name" => result.push_str(&self.variables.agency_name),
+				"domain" => result.push_str(&self.variables.domain),
+				"date" => result.push_str(&date),
+				"query" => result.push_str(query.unwrap_or_default()),
+				// Unknown placeholders are rejected when loading
+				_ => result.push_str(&rest[start..start + end + 2])
+			}
+			rest = &rest[start + end + 2..];
+		}
+		result.push_str(rest);
+		result
+	}
+}
+
+/// The value of `{{date}}`, today in UTC
+pub fn prompt_date() -> String {
+	Utc::now().format("%Y-%m-%d").to_string()
+}
+
+/// The prompt versions in use, reloaded when a template file changes. With several versions, each conversation picks
+/// one at random so that they can be compared through the feedback on their answers.
+pub struct PromptStore {
+	base_path: PathBuf,
+	versions: Vec<String>,
+	variables: PromptVariables,
+	active: RwLock<Vec<Arc<Prompts>>>,
+	modified_at: RwLock<Option<SystemTime>>
+}
+
+impl PromptStore {
+	/// The prompts of `profile`. Fails if any template of the configured versions is missing or invalid.
+	pub fn from_config(config: &PromptsConfig, profile: &ProfileConfig) -> Result<Self> {
+		Self::open(config.path.clone(), config.versions.clone(), PromptVariables::from(profile))
+	}
+
+	pub fn open(base_path: PathBuf, versions: Vec<String>, variables: PromptVariables) -> Result<Self> {
+		let store = Self {
+			base_path,
+			versions,
+			variables,
+			active: RwLock::new(Vec::new()),
+			modified_at: RwLock::new(None)
+		};
+		store.reload()?;
+
+		let active = store.active.read().unwrap();
+		log::info!("Loaded prompt versions: {}", active.iter().map(|prompts| prompts.version()).collect::<Vec<_>>().join(", "));
+		drop(active);
+
+		Ok(store)
+	}
+
+	/// The prompts for a new conversation
+	pub fn pick(&self) -> Arc<Prompts> {
+		let active = self.active.read().unwrap();
+		active.choose(&mut rand::thread_rng()).cloned().expect("At least one prompt version is loaded")
+	}
+
+	/// Polls the template files every `PROMPTS_RELOAD_SECS` and reloads them when one changed. Invalid edits are logged
+	/// and the previous templates stay in use.
+	pub async fn watch(self: Arc<Self>) {
+		let mut interval = tokio::time::interval(Duration::from_secs(PROMPTS_RELOAD_SECS));
+		loop {
+			interval.tick().await;
+			let modified_at = self.latest_modification();
+			if modified_at.is_some() && modified_at != *self.modified_at.read().unwrap() {
+				match self.reload() {
+					Ok(()) => log::info!("Reloaded prompt templates from {}", self.base_path.display()),
+					Err(e) => {
+						log::error!("Keeping the previous prompt templates, reload failed: {}", e);
+						// Retry on the next change rather than on every tick
+						*self.modified_at.write().unwrap() = modified_at;
+					}
+				}
+			}
+		}
+	}
+
+	fn reload(&self) -> Result<()> {
+		let modified_at = self.latest_modification();
+		let prompts = self
+			.versions
+			.iter()
+			.map(|version| Prompts::load(&self.base_path.join(version), version, self.variables.clone()).map(Arc::new))
+			.collect::<Result<Vec<_>>>()?;
+
+		*self.active.write().unwrap() = prompts;
+		*self.modified_at.write().unwrap() = modified_at;
+		Ok(())
+	}
+
+	fn latest_modification(&self) -> Option<SystemTime> {
+		self.versions
+			.iter()
+			.flat_map(|version| TEMPLATES.iter().map(move |(_, file, _)| self.base_path.join(version).join(file)))
+			.filter_map(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+			.max()
+	}
+}
+
+/// Names of the `{{ name }}` placeholders in `text`
+fn placeholders(text: &str) -> Vec<String> {
+	let mut names = Vec::new();
+	let mut rest = text;
+	while let Some(start) = rest.find("{{") {
+		let Some(end) = rest[start..].find("}}") else {
+			break;
+		};
+		names.push(rest[start + 2..start + end].trim().to_string());
+		rest = &rest[start + end + 2..];
+	}
+	names
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use reqwest::Url;
+
+/// The URL a page is crawled once under, without its fragment and query
+pub fn normalize(url: &Url) -> Url {
+	let mut url = url.clone();
+	url.set_fragment(None);
+	url.set_query(None);
+	url
+}
+
+/// Whether `url` is an HTML page rather than an image or a download, going by its extension
+pub fn is_page(url: &Url) -> bool {
+	let name = url.path().rsplit('/').next().unwrap_or_default();
+	match name.rsplit_once('.') {
+		Some((_, extension)) => extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm"),
+		None => true
+	}
+}
+
+/// Where the Markdown document of the page at `url` is written, relative to the documents directory. The URL path is
+/// kept, with `.md` instead of `.html` and `index.md` for directories, so that the first directories are the language
+/// and the section of the document.
+pub fn document_path(url: &Url) -> PathBuf {
+	let path = url.path().trim_start_matches('/');
+	let path = if path.is_empty() || path.ends_with('/') {
+		format!("{}index.md", path)
+	} else if let Some(stem) = strip_extension(path, "html").or_else(|| strip_extension(path, "htm")) {
+		format!("{}.md", stem)
+	} else {
+		format!("{}.md", path)
+	};
+	PathBuf::from(path)
+}
+
+fn strip_extension<'a>(path: &'a str, extension: &str) -> Option<&'a str> {
+	let (stem, found) = path.rsplit_once('.')?;
+	(found.eq_ignore_ascii_case(extension) && !stem.ends_with('/')).then_some(stem)
+}
+
+/// The targets of the `<a href>` elements of a page, resolved against its URL
+pub fn page_links(html: &str, page: &Url) -> Vec<Url> {
+	let lowercase = html.to_ascii_lowercase();
+	let mut links = Vec::new();
+	let mut from = 0;
+	while let Some(start) = lowercase[from..].find("<a").map(|start| from + start) {
+		let Some(end) = lowercase[start..].find('>').map(|end| start + end) else {
+			break;
+		};
+		from = end;
+		// `<abbr>`, `<article>` and the like
+		if !lowercase[start + 2..].starts_with(|c: char| c.is_whitespace()) {
+			continue;
+		}
+		if let Some(href) = attribute(&html[start..end], &lowercase[start..end], "href") {
+			if let Ok(url) = page.join(&href.replace("&amp;", "&")) {
+				links.push(url);
+			}
+		}
+	}
+	links
+}
+
+// The value of the attribute `name` of a start tag
+fn attribute<'a>(tag: &'a str, lowercase: &str, name: &str) -> Option<&'a str> {
+	let pattern = format!("{}=", name);
+	let mut from = 0;
+	while let Some(position) = lowercase[from..].find(&pattern).map(|position| from + position) {
+		from = position + pattern.len();
+		// `data-href=` and the like
+		if !lowercase[..position].ends_with(|c: char| c.is_whitespace()) {
+			continue;
+		}
+		let value = &tag[from..];
+		return match value.chars().next() {
+			Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+			_ => value.split(|c: char| c.is_whitespace() || c == '>').next()
+		};
+	}
+	None
+}
+
+/// Rewrites the targets of the inline links of `markdown`, converted from the page at `page`. Links to pages `crawled`
+/// accepts point to their documents, absolute from the documents directory as `fs::links` reads them, and other relative
+/// links become absolute URLs so that they still lead somewhere outside the site.
+pub fn rewrite_links(markdown: &str, page: &Url, crawled: impl Fn(&Url) -> bool) -> String {
+	let mut rewritten = String::with_capacity(markdown.len());
+	let mut rest = markdown;
+	while let Some(start) = rest.find("](") {
+		rewritten.push_str(&rest[..start + 2]);
+		rest = &rest[start + 2..];
+		let Some(end) = rest.find(')') else {
+			break;
+		};
+
+		// Targets are not wrapped, titles may follow them
+		let link = &rest[..end];
+		let target = link.split_whitespace().next().unwrap_or_default();
+		let title = link.trim_start()[target.len()..].trim();
+		let target = target.trim_start_matches('<').trim_end_matches('>');
+
+		rewritten.push_str(&rewrite_target(target, page, &crawled));
+		if !title.is_empty() {
+			rewritten.push(' ');
+			rewritten.push_str(title);
+		}
+		rewritten.push(')');
+		rest = &rest[end + 1..];
+	}
+	rewritten.push_str(rest);
+	rewritten
+}
+
+fn rewrite_target(target: &str, page: &Url, crawled: &impl Fn(&Url) -> bool) -> String {
+	// Anchors within the document
+	if target.is_empty() || target.starts_with('#') {
+		return target.to_string();
+	}
+	let Ok(url) = page.join(target) else {
+		return target.to_string();
+	};
+	if url.scheme() != "http" && url.scheme() != "https" {
+		return target.to_string();
+	}
+
+	let normalized = normalize(&url);
+	if !crawled(&normalized) {
+		return url.to_string();
+	}
+	let fragment = url.fragment().map(|fragment| format!("#{}", fragment)).unwrap_or_default();
+	format!("/{}{}", document_path(&normalized).display(), fragment)
+}
@@ -0,0 +1,206 @@
+pub mod links;
+pub mod robots;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use reqwest::{header, Client, Url};
+use tokio::fs;
+use tokio::time::{sleep_until, Instant};
+
+use self::links::{document_path, is_page, normalize, page_links, rewrite_links};
+use self::robots::Robots;
+use crate::config::CrawlConfig;
+use crate::fs::loader::html_to_markdown;
+use crate::prelude::*;
+
+/// What a crawl did
+#[derive(Debug, Default)]
+pub struct CrawlSummary {
+	pub written: usize,
+	/// Pages disallowed by robots.txt, redirected out of the crawl or that are not HTML
+	pub skipped: usize,
+	pub failed: usize
+}
+
+/// Mirrors the pages under the allowed prefixes as Markdown documents, following their links breadth first from the
+/// start URL. Requests are made one at a time, so that the site sees at most one every `delay`.
+pub struct Crawler {
+	client: Client,
+	start_url: Url,
+	prefixes: Vec<String>,
+	delay: Duration,
+	respect_robots_txt: bool,
+	user_agent: String,
+	max_pages: Option<usize>,
+	output: PathBuf,
+	// The robots.txt of each site, by origin
+	robots: HashMap<String, Robots>,
+	next_request: Instant
+}
+
+impl Crawler {
+	/// A crawler writing the documents in `output`
+	pub fn new(config: &CrawlConfig, output: &Path) -> Result<Self> {
+		let start_url = normalize(&Url::parse(&config.start_url)?);
+		let prefixes = if config.allowed_prefixes.is_empty() {
+			let start = start_url.as_str();
+			vec![start.strip_suffix(".html").or_else(|| start.strip_suffix(".htm")).unwrap_or(start).to_string()]
+		} else {
+			config.allowed_prefixes.clone()
+		};
+		let client = Client::builder()
+			.user_agent(&config.user_agent)
+			.timeout(Duration::from_secs(config.request_timeout_secs))
+			.build()?;
+
+		Ok(Self {
+			client,
+			start_url,
+			prefixes,
+			delay: Duration::from_millis(config.delay_ms),
+			respect_robots_txt: config.respect_robots_txt,
+			user_agent: config.user_agent.clone(),
+			max_pages: config.max_pages,
+			output: output.to_path_buf(),
+			robots: HashMap::new(),
+			next_request: Instant::now()
+		})
+	}
+
+	/// Crawls until every page found is written or `max_pages` is reached. Pages that fail are logged and counted, only
+	/// errors writing the documents end the crawl.
+	pub async fn run(&mut self) -> Result<CrawlSummary> {
+		fs::create_dir_all(&self.output).await?;
+
+		let mut summary = CrawlSummary::default();
+		let mut queue = VecDeque::from([self.start_url.clone()]);
+		let mut seen = HashSet::from([self.start_url.clone()]);
+		while let Some(url) = queue.pop_front() {
+			if self.max_pages.is_some_and(|max_pages| summary.written >= max_pages) {
+				log::info!("Stopping after {} pages, {} left in the queue", summary.written, queue.len() + 1);
+				break;
+			}
+
+			let links = match self.crawl_page(&url).await {
+				Ok(Some(links)) => links,
+				Ok(None) => {
+					summary.skipped += 1;
+					continue;
+				}
+				Err(e) if e.is::<reqwest::Error>() => {
+					log::error!("Failed to fetch {}: {:#}", url, e);
+					summary.failed += 1;
+					continue;
+				}
+				Err(e) => return Err(e.context(format!("Failed to save {}", url)))
+			};
+			summary.written += 1;
+
+			for link in links {
+				if self.in_scope(&link) && seen.insert(link.clone()) {
+					queue.push_back(link);
+				}
+			}
+		}
+
+		Ok(summary)
+	}
+
+	// Fetches, converts and writes one page, returning the pages it links to, or `None` when it is skipped
+	async fn crawl_page(&mut self, url: &Url) -> Result<Option<Vec<Url>>> {
+		if self.respect_robots_txt && !self.robots_for(url).await.allows(url.path()) {
+			log::info!("Skipping {}: disallowed by robots.txt", url);
+			return Ok(None);
+		}
+
+		self.wait(url).await;
+		let response = self.client.get(url.clone()).send().await?.error_for_status()?;
+		let page_url = normalize(response.url());
+		if !self.in_scope(&page_url) {
+			log::info!("Skipping {}: redirected to {}", url, page_url);
+			return Ok(None);
+		}
+		let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or("text/html");
+		if !content_type.starts_with("text/html") {
+			log::info!("Skipping {}: {} is not HTML", url, content_type);
+			return Ok(None);
+		}
+		let html = response.text().await?;
+
+		let (title, markdown) = html_to_markdown(&html);
+		let markdown = rewrite_links(&markdown, &page_url, |link| self.in_scope(link));
+		let mut document = String::from("---\n");
+		if let Some(title) = title {
+			document.push_str(&format!("title: {}\n", title));
+		}
+		document.push_str(&format!("source_url: {}\n", page_url));
+		document.push_str(&format!("fetched_at: {}\n", Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)));
+		document.push_str("---\n\n");
+		document.push_str(&markdown);
+		document.push('\n');
+
+		let path = self.output.join(document_path(&page_url));
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir).await?;
+		}
+		fs::write(&path, document).await?;
+		log::info!("Saved {} to {}", page_url, path.display());
+
+		Ok(Some(page_links(&html, &page_url).iter().map(normalize).collect()))
+	}
+
+	fn in_scope(&self, url: &Url) -> bool {
+		matches!(url.scheme(), "http" | "https") && is_page(url) && self.prefixes.iter().any(|prefix| url.as_str().starts_with(prefix.as_str()))
+	}
+
+	async fn robots_for(&mut self, url: &Url) -> &Robots {
+		let origin = url.origin().ascii_serialization();
+		if !self.robots.contains_key(&origin) {
+			let robots = self.fetch_robots(url).await;
+			self.robots.insert(origin.clone(), robots);
+		}
+		&self.robots[&origin]
+	}
+
+	// Sites without a robots.txt allow everything, while one that cannot be fetched disallows everything
+	async fn fetch_robots(&mut self, url: &Url) -> Robots {
+		let Ok(robots_url) = url.join("/robots.txt") else {
+			return Robots::allow_all();
+		};
+
+		self.wait(url).await;
+		let response = match self.client.get(robots_url.clone()).send().await {
+			Ok(response) => response,
+			Err(e) => {
+				log::warn!("Failed to fetch {}, crawling nothing on its site: {}", robots_url, e);
+				return Robots::disallow_all();
+			}
+		};
+		let status = response.status();
+		if status.is_client_error() {
+			return Robots::allow_all();
+		}
+		if !status.is_success() {
+			log::warn!("Fetching {} returned {}, crawling nothing on its site", robots_url, status);
+			return Robots::disallow_all();
+		}
+
+		match response.text().await {
+			Ok(content) => Robots::parse(&content, &self.user_agent),
+			Err(e) => {
+				log::warn!("Failed to read {}, crawling nothing on its site: {}", robots_url, e);
+				Robots::disallow_all()
+			}
+		}
+	}
+
+	// Waits for the delay since the previous request, the `Crawl-delay` of the site when it is longer
+	async fn wait(&mut self, url: &Url) {
+		let crawl_delay = self.robots.get(&url.origin().ascii_serialization()).and_then(|robots| robots.crawl_delay);
+		sleep_until(self.next_request).await;
+		self.next_request = Instant::now() + crawl_delay.map_or(self.delay, |crawl_delay| crawl_delay.max(self.delay));
+	}
+}
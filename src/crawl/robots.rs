@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use crate::constants::CRAWL_MAX_DELAY_SECS;
+
+/// The rules of a robots.txt file that apply to the crawler, as described by RFC 9309
+#[derive(Debug, Clone, Default)]
+pub struct Robots {
+	rules: Vec<Rule>,
+	/// Seconds between two requests asked by the `Crawl-delay` extension, at most `CRAWL_MAX_DELAY_SECS`
+	pub crawl_delay: Option<Duration>
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+	allow: bool,
+	pattern: String
+}
+
+impl Robots {
+	/// When the site has no robots.txt
+	pub fn allow_all() -> Self {
+		Self::default()
+	}
+
+	/// When the robots.txt of the site cannot be fetched, which may mean the site is overloaded
+	pub fn disallow_all() -> Self {
+		Self {
+			rules: vec![Rule {
+				allow: false,
+				pattern: "/".into()
+			}],
+			crawl_delay: None
+		}
+	}
+
+	/// The rules of the groups naming `user_agent`, or of the `*` group when none does
+	pub fn parse(content: &str, user_agent: &str) -> Self {
+		// The product token, without its version
+		let token = user_agent.split('/').next().unwrap_or_default().trim().to_lowercase();
+
+		let mut specific = Robots::default();
+		let mut any = Robots::default();
+		let mut found_specific = false;
+		// Whether the current group applies to the crawler by name, and to every crawler
+		let (mut names_crawler, mut names_any) = (false, false);
+		let mut in_agents = false;
+
+		for line in content.lines() {
+			let line = line.split('#').next().unwrap_or_default().trim();
+			let Some((field, value)) = line.split_once(':') else {
+				continue;
+			};
+			let value = value.trim();
+
+			match field.trim().to_lowercase().as_str() {
+				"user-agent" => {
+					// Consecutive user agent lines share a group
+					if !in_agents {
+						(names_crawler, names_any) = (false, false);
+					}
+					in_agents = true;
+					let agent = value.to_lowercase();
+					if agent == "*" {
+						names_any = true;
+					} else if !agent.is_empty() && token.starts_with(&agent) {
+						names_crawler = true;
+						found_specific = true;
+					}
+				}
+				field @ ("allow" | "disallow" | "crawl-delay") => {
+					in_agents = false;
+					for (robots, applies) in [(&mut specific, names_crawler), (&mut any, names_any)] {
+						if !applies {
+							continue;
+						}
+						match field {
+							"crawl-delay" => {
+								robots.crawl_delay = value
+									.parse::<f64>()
+									.ok()
+									.filter(|secs| !secs.is_nan())
+									// Negative delays are ignored
+									.and_then(|secs| Duration::try_from_secs_f64(secs.min(CRAWL_MAX_DELAY_SECS)).ok())
+							}
+							// An empty `Disallow` allows everything
+							_ if value.is_empty() => {}
+							_ => robots.rules.push(Rule {
+								allow: field == "allow",
+								pattern: value.to_string()
+							})
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+
+		if found_specific {
+			specific
+		} else {
+			any
+		}
+	}
+
+	/// Whether the crawler may fetch `path`, the path and query of a URL. The longest matching rule decides, `Allow`
+	/// winning ties.
+	pub fn allows(&self, path: &str) -> bool {
+		self.rules
+			.iter()
+			.filter(|rule| matches(&rule.pattern, path))
+			.max_by_key(|rule| (rule.pattern.len(), rule.allow))
+			.map_or(true, |rule| rule.allow)
+	}
+}
+
+// `*` matches any characters and a final `$` the end of the path
+fn matches(pattern: &str, path: &str) -> bool {
+	let (pattern, anchored) = match pattern.strip_suffix('$') {
+		Some(pattern) => (pattern, true),
+		None => (pattern, false)
+	};
+	let mut parts = pattern.split('*');
+	let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+		return false;
+	};
+
+	let parts: Vec<&str> = parts.collect();
+	for (i, part) in parts.iter().enumerate() {
+		if anchored && i + 1 == parts.len() {
+			return rest.ends_with(part);
+		}
+		match rest.find(part) {
+			Some(position) => rest = &rest[position + part.len()..],
+			None => return false
+		}
+	}
+	!anchored || rest.is_empty()
+}
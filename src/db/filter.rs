@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Restricts a search to the documents whose metadata matches every field that is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "oracle", derive(utoipa::ToSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchFilter {
+	/// Top level sections to search, any of them
+	#[cfg_attr(feature = "oracle", schema(example = json!(["immigration-refugees-citizenship"])))]
+	pub sections: Vec<String>,
+	/// Top level sections never to return
+	#[cfg_attr(feature = "oracle", schema(example = json!(["news"])))]
+	pub exclude_sections: Vec<String>,
+	/// Languages of the documents to search, any of them, e.g. `en`
+	pub languages: Vec<String>,
+	/// Only documents modified on or after this date
+	#[cfg_attr(feature = "oracle", schema(value_type = Option<String>, format = Date, example = "2023-01-01"))]
+	pub modified_after: Option<NaiveDate>,
+	/// Front matter fields the documents must have, with these exact values
+	pub front_matter: BTreeMap<String, String>
+}
+
+impl SearchFilter {
+	pub fn is_empty(&self) -> bool {
+		self == &Self::default()
+	}
+
+	/// Narrows this filter with `other`. This filter is a hard constraint: where both restrict the sections or the
+	/// languages to sets that do not intersect, `other` is ignored for that field.
+	pub fn and(&self, other: &SearchFilter) -> SearchFilter {
+		let mut exclude_sections = self.exclude_sections.clone();
+		exclude_sections.extend(other.exclude_sections.iter().filter(|section| !self.exclude_sections.contains(section)).cloned());
+
+		let mut front_matter = other.front_matter.clone();
+		front_matter.extend(self.front_matter.clone());
+
+		SearchFilter {
+			sections: narrow(&self.sections, &other.sections),
+			exclude_sections,
+			languages: narrow(&self.languages, &other.languages),
+			modified_after: self.modified_after.max(other.modified_after),
+			front_matter
+		}
+	}
+}
+
+fn narrow(constraint: &[String], other: &[String]) -> Vec<String> {
+	if constraint.is_empty() {
+		return other.to_vec();
+	}
+
+	let intersection: Vec<String> = constraint.iter().filter(|value| other.contains(value)).cloned().collect();
+	if intersection.is_empty() {
+		constraint.to_vec()
+	} else {
+		intersection
+	}
+}
@@ -1,16 +1,35 @@
 use async_trait::async_trait;
 
+pub use self::filter::SearchFilter;
 use crate::embeddings::Embeddings;
 use crate::fs::FileEmbeddings;
 use crate::prelude::*;
 
+pub mod filter;
 pub mod qdrant;
 
+#[derive(Debug, Clone, Default)]
+pub struct DocumentLinks {
+	pub outgoing: Vec<String>,
+	pub incoming: Vec<String>
+}
+
 #[async_trait]
 pub trait RepositoryEmbeddingsDB {
+	/// Creates the collection unless it exists
+	async fn create_collection(&self) -> Result<()>;
 	async fn insert_embeddings(&self, embeddings: Vec<FileEmbeddings>) -> Result<()>;
-	async fn get_relevant_files(&self, query_embeddings: Embeddings, limit: f32) -> Result<Vec<String>>;
-	async fn get_file_paths(&self) -> Result<Vec<String>>;
+	/// Removes the points of the documents at `paths`, paths without a point are ignored
+	async fn delete_embeddings(&self, paths: &[String]) -> Result<()>;
+	async fn get_relevant_files(&self, query_embeddings: Embeddings, limit: f32, filter: &SearchFilter) -> Result<Vec<String>>;
+	async fn get_file_paths(&self, filter: &SearchFilter) -> Result<Vec<String>>;
+	/// The documents `path` links to and the documents linking to it, among those matching `filter`
+	async fn get_links(&self, path: &str, filter: &SearchFilter) -> Result<DocumentLinks>;
 	async fn delete_collection(&self) -> Result<()>;
 	async fn is_indexed(&self) -> Result<bool>;
+	async fn count_points(&self) -> Result<usize>;
+	/// Changes whenever a run of embed has added, removed or embedded documents again
+	async fn index_version(&self) -> Result<String>;
+	/// Stores a new index version, at the end of every run that changed the collection
+	async fn write_index_version(&self) -> Result<()>;
 }
@@ -1,44 +1,53 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use anyhow::Ok;
 use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
 use qdrant_client::{
 	prelude::*,
-	qdrant::{vectors_config::Config, ScrollPoints, VectorParams, VectorsConfig}
+	qdrant::{
+		points_selector::PointsSelectorOneOf, value::Kind, vectors_config::Config, Condition, CountPoints, Filter, PointsIdsList, PointsSelector, Range,
+		RetrievedPoint, ScrollPoints, Value, VectorParams, VectorsConfig
+	}
 };
 use rayon::prelude::*;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde_json::json;
 
-use super::RepositoryEmbeddingsDB;
+use super::{DocumentLinks, RepositoryEmbeddingsDB, SearchFilter};
+use crate::config::QdrantConfig;
+use crate::fs::metadata::DocumentMetadata;
 use crate::utils::hash::calculate_hash;
 use crate::{
-	constants::{EMBEDDINGS_DIMENSION, MAX_FILES_COUNT, QDRANT_COLLECTION_NAME, QDRANT_URL_DEFAULT},
+	constants::{DOCUMENT_LINKS_LIMIT, EMBEDDINGS_DIMENSION, INDEX_VERSION_POINT_ID, MAX_FILES_COUNT},
 	embeddings::Embeddings,
 	fs::FileEmbeddings,
 	prelude::*
 };
 
+/// One collection of a Qdrant server, the connection is shared by the collections of every profile
 pub struct QdrantDB {
-	client: QdrantClient
+	client: Arc<QdrantClient>,
+	collection: String
 }
 
 #[async_trait]
 impl RepositoryEmbeddingsDB for QdrantDB {
 	async fn delete_collection(&self) -> Result<()> {
-		if self.client.has_collection(QDRANT_COLLECTION_NAME).await? {
-			log::info!("Deleting collection {}", QDRANT_COLLECTION_NAME);
-			self.client.delete_collection(QDRANT_COLLECTION_NAME).await?;
+		if self.client.has_collection(&self.collection).await? {
+			log::info!("Deleting collection {}", &self.collection);
+			self.client.delete_collection(&self.collection).await?;
 		}
 
 		Ok(())
 	}
 
-	async fn insert_embeddings(&self, embeddings: Vec<FileEmbeddings>) -> Result<()> {
-		let collection_exists = self.client.has_collection(QDRANT_COLLECTION_NAME).await?;
+	async fn create_collection(&self) -> Result<()> {
+		let collection_exists = self.client.has_collection(&self.collection).await?;
 
 		if !collection_exists {
 			let collection_details = CreateCollection {
-				collection_name: QDRANT_COLLECTION_NAME.to_string(),
+				collection_name: self.collection.clone(),
 				vectors_config: Some(VectorsConfig {
 					config: Some(Config::Params(VectorParams {
 						size: EMBEDDINGS_DIMENSION as u64,
@@ -50,36 +59,56 @@ impl RepositoryEmbeddingsDB for QdrantDB {
 			};
 
 			self.client.create_collection(&collection_details).await?;
-			log::info!("Created collection {}", QDRANT_COLLECTION_NAME);
+			log::info!("Created collection {}", &self.collection);
 		}
 
+		Ok(())
+	}
+
+	async fn insert_embeddings(&self, embeddings: Vec<FileEmbeddings>) -> Result<()> {
+		self.create_collection().await?;
+
 		let points: Vec<PointStruct> = embeddings
 			.into_par_iter()
 			.map(|file| {
-				let FileEmbeddings { path, embeddings } = file;
+				let FileEmbeddings { path, embeddings, metadata } = file;
 				let path_hash = calculate_hash(&path);
 
-				let payload: Payload = HashMap::from([("path", path.into())]).into();
+				let payload = document_payload(path, metadata)?;
 
-				PointStruct::new(path_hash, embeddings, payload)
+				Ok(PointStruct::new(path_hash, embeddings, payload))
 			})
-			.collect();
+			.collect::<Result<_>>()?;
 
 		let points_len = points.len();
 
-		self.client.upsert_points(QDRANT_COLLECTION_NAME, points, None).await?;
+		self.client.upsert_points(&self.collection, points, None).await?;
 		log::info!("Upserted {} points", points_len);
 
 		Ok(())
 	}
 
-	async fn get_relevant_files(&self, query_embeddings: Embeddings, limit: f32) -> Result<Vec<String>> {
+	async fn delete_embeddings(&self, paths: &[String]) -> Result<()> {
+		// Points are keyed by the hash of their path
+		let ids = paths.iter().map(|path| calculate_hash(path).into()).collect();
+		let selector = PointsSelector {
+			points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList { ids }))
+		};
+
+		self.client.delete_points(&self.collection, &selector, None).await?;
+		log::info!("Deleted {} points", paths.len());
+
+		Ok(())
+	}
+
+	async fn get_relevant_files(&self, query_embeddings: Embeddings, limit: f32, filter: &SearchFilter) -> Result<Vec<String>> {
 		log::info!("Searching for relevant files");
 		let search_response = self
 			.client
 			.search_points(&SearchPoints {
-				collection_name: QDRANT_COLLECTION_NAME.to_string(),
+				collection_name: self.collection.clone(),
 				vector: query_embeddings,
+				filter: Some(to_qdrant_filter(filter)),
 				with_payload: Some(true.into()),
 				limit: limit as u64,
 				..Default::default()
@@ -95,22 +124,10 @@ impl RepositoryEmbeddingsDB for QdrantDB {
 		Ok(paths)
 	}
 
-	async fn get_file_paths(&self) -> Result<Vec<String>> {
-		let scroll_reponse = self
-			.client
-			.scroll(&ScrollPoints {
-				collection_name: QDRANT_COLLECTION_NAME.to_string(),
-				offset: None,
-				filter: None,
-				limit: Some(MAX_FILES_COUNT as u32),
-				with_payload: Some(true.into()),
-				with_vectors: None,
-				read_consistency: None
-			})
-			.await?;
-
-		let file_paths: Vec<String> = scroll_reponse
-			.result
+	async fn get_file_paths(&self, filter: &SearchFilter) -> Result<Vec<String>> {
+		let file_paths: Vec<String> = self
+			.scroll_all(to_qdrant_filter(filter))
+			.await?
 			.par_iter()
 			.map(|point| point.payload["path"].to_string().replace('\"', ""))
 			.collect();
@@ -118,22 +135,216 @@ impl RepositoryEmbeddingsDB for QdrantDB {
 		Ok(file_paths)
 	}
 
+	async fn get_links(&self, path: &str, filter: &SearchFilter) -> Result<DocumentLinks> {
+		let mut outgoing = self
+			.scroll_matching(Condition::matches("path", path.to_string()), &SearchFilter::default(), 1)
+			.await?
+			.first()
+			.map(|point| string_list(point.payload.get("links")))
+			.unwrap_or_default();
+		outgoing.truncate(DOCUMENT_LINKS_LIMIT);
+		// Only the linked documents the filter lets through, which leaves out the links to documents not indexed
+		if !filter.is_empty() && !outgoing.is_empty() {
+			let allowed: Vec<String> = self
+				.scroll_matching(Condition::matches("path", outgoing.clone()), filter, outgoing.len())
+				.await?
+				.iter()
+				.map(|point| point.payload["path"].to_string().replace('\"', ""))
+				.collect();
+			outgoing.retain(|link| allowed.contains(link));
+		}
+
+		let incoming = self
+			.scroll_matching(Condition::matches("links", path.to_string()), filter, DOCUMENT_LINKS_LIMIT)
+			.await?
+			.iter()
+			.map(|point| point.payload["path"].to_string().replace('\"', ""))
+			.collect();
+
+		Ok(DocumentLinks { outgoing, incoming })
+	}
+
+	async fn count_points(&self) -> Result<usize> {
+		let response = self
+			.client
+			.count(&CountPoints {
+				collection_name: self.collection.clone(),
+				filter: Some(to_qdrant_filter(&SearchFilter::default())),
+				exact: Some(true),
+				..Default::default()
+			})
+			.await?;
+
+		Ok(response.result.map_or(0, |result| result.count as usize))
+	}
+
 	async fn is_indexed(&self) -> Result<bool> {
-		self.client.has_collection(QDRANT_COLLECTION_NAME).await
+		self.client.has_collection(&self.collection).await
+	}
+
+	async fn index_version(&self) -> Result<String> {
+		let response = self
+			.client
+			.scroll(&ScrollPoints {
+				collection_name: self.collection.clone(),
+				filter: Some(Filter {
+					must: vec![Condition::has_id([INDEX_VERSION_POINT_ID])],
+					..Default::default()
+				}),
+				limit: Some(1),
+				with_payload: Some(true.into()),
+				..Default::default()
+			})
+			.await?;
+
+		match response.result.first().and_then(|point| point.payload.get("index_version")) {
+			Some(version) => Ok(version.to_string().replace('\"', "")),
+			// Collections embedded before versions were stored change with their number of documents
+			None => Ok(format!("unversioned-{}", self.count_points().await?))
+		}
+	}
+
+	async fn write_index_version(&self) -> Result<()> {
+		let version = Utc::now().timestamp_millis().to_string();
+		let payload = Payload::try_from(json!({ "index_version": version })).map_err(|e| anyhow::anyhow!("Invalid payload: {}", e))?;
+		let point = PointStruct::new(INDEX_VERSION_POINT_ID, vec![0.0; EMBEDDINGS_DIMENSION], payload);
+
+		self.client.upsert_points(&self.collection, vec![point], None).await?;
+		log::info!("Collection {} is at index version {}", &self.collection, version);
+
+		Ok(())
 	}
 }
 
 impl QdrantDB {
-	pub fn initialize() -> Result<QdrantDB> {
-		let mut qdrant_url = std::env::var("QDRANT_URL").unwrap_or(String::from(QDRANT_URL_DEFAULT));
-		log::info!("Qdrant URL: {}", qdrant_url);
+	pub fn initialize(config: &QdrantConfig, collection: &str) -> Result<QdrantDB> {
+		log::info!("Qdrant URL: {}", config.url);
 
-		if qdrant_url.is_empty() {
-			qdrant_url = QDRANT_URL_DEFAULT.to_string();
+		let config = QdrantClientConfig::from_url(&config.url);
+		let client = QdrantClient::new(Some(config))?;
+		Ok(QdrantDB {
+			client: Arc::new(client),
+			collection: collection.to_string()
+		})
+	}
+
+	/// Another collection on the same server
+	pub fn with_collection(&self, collection: &str) -> QdrantDB {
+		QdrantDB {
+			client: self.client.clone(),
+			collection: collection.to_string()
 		}
+	}
 
-		let config = QdrantClientConfig::from_url(&qdrant_url);
-		let client = QdrantClient::new(Some(config))?;
-		Ok(QdrantDB { client })
+	pub fn collection(&self) -> &str {
+		&self.collection
+	}
+
+	// Every point matching `filter`, a page of `MAX_FILES_COUNT` at a time
+	async fn scroll_all(&self, filter: Filter) -> Result<Vec<RetrievedPoint>> {
+		let mut points = Vec::new();
+		let mut offset = None;
+		loop {
+			let response = self
+				.client
+				.scroll(&ScrollPoints {
+					collection_name: self.collection.clone(),
+					offset,
+					filter: Some(filter.clone()),
+					limit: Some(MAX_FILES_COUNT as u32),
+					with_payload: Some(true.into()),
+					..Default::default()
+				})
+				.await?;
+
+			points.extend(response.result);
+			match response.next_page_offset {
+				Some(next_page_offset) => offset = Some(next_page_offset),
+				None => return Ok(points)
+			}
+		}
+	}
+
+	// The points matching `condition` and `filter`
+	async fn scroll_matching(&self, condition: Condition, filter: &SearchFilter, limit: usize) -> Result<Vec<RetrievedPoint>> {
+		let mut filter = to_qdrant_filter(filter);
+		filter.must.push(condition);
+
+		let response = self
+			.client
+			.scroll(&ScrollPoints {
+				collection_name: self.collection.clone(),
+				filter: Some(filter),
+				limit: Some(limit as u32),
+				with_payload: Some(true.into()),
+				..Default::default()
+			})
+			.await?;
+
+		Ok(response.result)
+	}
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+	match value.and_then(|value| value.kind.as_ref()) {
+		Some(Kind::ListValue(list)) => list
+			.values
+			.iter()
+			.filter_map(|value| match &value.kind {
+				Some(Kind::StringValue(value)) => Some(value.clone()),
+				_ => None
+			})
+			.collect(),
+		_ => Vec::new()
+	}
+}
+
+// Metadata left unset is not stored, so that filters on it never match
+fn document_payload(path: String, metadata: DocumentMetadata) -> Result<Payload> {
+	let mut payload = json!({
+		"path": path,
+		"front_matter": metadata.front_matter,
+		"links": metadata.links
+	});
+	for (key, value) in [("title", metadata.title), ("section", metadata.section), ("language", metadata.language)] {
+		if let Some(value) = value {
+			payload[key] = value.into();
+		}
+	}
+	if let Some(last_modified) = metadata.last_modified {
+		payload["last_modified"] = last_modified.timestamp().into();
+	}
+
+	Payload::try_from(payload).map_err(|e| anyhow::anyhow!("Invalid payload: {}", e))
+}
+
+// Leaves out the index version point, which is not a document
+fn to_qdrant_filter(filter: &SearchFilter) -> Filter {
+	let mut must = Vec::new();
+	if !filter.sections.is_empty() {
+		must.push(Condition::matches("section", filter.sections.clone()));
+	}
+	if !filter.languages.is_empty() {
+		must.push(Condition::matches("language", filter.languages.clone()));
+	}
+	if let Some(date) = filter.modified_after.and_then(|date| date.and_hms_opt(0, 0, 0)) {
+		must.push(Condition::range("last_modified", Range {
+			gte: Some(Utc.from_utc_datetime(&date).timestamp() as f64),
+			..Default::default()
+		}));
+	}
+	for (key, value) in &filter.front_matter {
+		must.push(Condition::matches(format!("front_matter.{}", key), value.clone()));
+	}
+
+	let mut must_not = vec![Condition::has_id([INDEX_VERSION_POINT_ID])];
+	if !filter.exclude_sections.is_empty() {
+		must_not.push(Condition::matches("section", filter.exclude_sections.clone()));
+	}
+
+	Filter {
+		must,
+		must_not,
+		..Default::default()
 	}
 }
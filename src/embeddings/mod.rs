@@ -7,10 +7,36 @@ use crate::prelude::Result;
 
 pub type Embeddings = Vec<f32>;
 
+/// The input could not be tokenized, as opposed to a failure of the model itself
+#[derive(Debug)]
+pub struct TokenizerError(pub String);
+
+impl std::fmt::Display for TokenizerError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Tokenizer error: {}", self.0)
+	}
+}
+
+impl std::error::Error for TokenizerError {}
+
 pub trait EmbeddingsModel {
 	fn embed(&self, string: &str) -> Result<Embeddings>;
 }
 
+/// Element-wise mean, used as the embeddings of a document split in chunks
+pub fn mean_embeddings(embeddings: &[Embeddings]) -> Option<Embeddings> {
+	let first = embeddings.first()?;
+	let mut mean = vec![0.0; first.len()];
+	for embedding in embeddings {
+		for (total, value) in mean.iter_mut().zip(embedding) {
+			*total += value;
+		}
+	}
+	let count = embeddings.len() as f32;
+	mean.iter_mut().for_each(|total| *total /= count);
+	Some(mean)
+}
+
 pub fn cosine_similarity(a: ArrayView1<f32>, b: ArrayView1<f32>) -> f32 {
 	let dot_product = a.dot(&b);
 	let norm_a = a.dot(&a).sqrt();
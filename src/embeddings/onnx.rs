@@ -3,7 +3,7 @@ use std::{path::Path, sync::Arc, thread::available_parallelism};
 use ndarray::{Array, Axis, CowArray};
 use ort::{execution_providers::CPUExecutionProviderOptions, Environment, ExecutionProvider, GraphOptimizationLevel, SessionBuilder, Value};
 
-use super::{Embeddings, EmbeddingsModel};
+use super::{Embeddings, EmbeddingsModel, TokenizerError};
 use crate::prelude::*;
 
 #[derive(Debug, Clone)]
@@ -41,7 +41,7 @@ impl EmbeddingsModel for Onnx {
 	/// The primary purpose of this function appears to be to convert a text sequence into a vector representation
 	/// (embedding) that can be used to find the documents siliar to the query
 	fn embed(&self, sequence: &str) -> Result<Embeddings> {
-		let tokenizer_output = self.tokenizer.encode(sequence, true).unwrap();
+		let tokenizer_output = self.tokenizer.encode(sequence, true).map_err(|e| TokenizerError(e.to_string()))?;
 
 		// The IDs are the main input to a Language Model. They are the token indices, the numerical representations that a LM
 		// understands.
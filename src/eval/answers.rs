@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::mpsc;
+
+use super::judge::{Judge, JudgeScores};
+use super::{ranked_documents, write_json, GoldenQuestion, NamedMetrics};
+use crate::constants::SSE_CHANNEL_BUFFER_SIZE;
+use crate::convrsation::settings::SearchSettings;
+use crate::convrsation::{Assistant, Conversation, Query};
+use crate::db::{RepositoryEmbeddingsDB, SearchFilter};
+use crate::embeddings::EmbeddingsModel;
+use crate::prelude::*;
+use crate::routes::events::{EventSender, QueryEvent};
+
+/// An event the conversation emitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEvent {
+	pub event: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub data: Option<serde_json::Value>
+}
+
+impl From<QueryEvent> for TraceEvent {
+	fn from(event: QueryEvent) -> Self {
+		Self {
+			event: event.name().to_string(),
+			data: event.into_data()
+		}
+	}
+}
+
+/// A link of an answer, resolved to the document it points to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+	pub url: String,
+	/// `None` when the link is not on the site of the profile
+	pub path: Option<String>,
+	/// Whether the document exists
+	pub valid: bool,
+	/// Whether the conversation retrieved the document, i.e. the answer could be based on it
+	pub retrieved: bool
+}
+
+/// What the conversation did for one question and how its answer scores
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerResult {
+	pub id: String,
+	pub question: String,
+	/// Why the conversation or the judge failed
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+	pub answer: Option<String>,
+	pub trace: Vec<TraceEvent>,
+	/// Relative to the documents directory, in the order they were retrieved
+	pub retrieved: Vec<String>,
+	pub citations: Vec<Citation>,
+	/// Share of the expected documents the conversation retrieved
+	pub expected_retrieved: f64,
+	/// Share of the citations that were retrieved, `None` when the answer cites nothing
+	pub groundedness: Option<f64>,
+	/// Share of the citations pointing to an existing document, `None` when the answer cites nothing
+	pub citation_validity: Option<f64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub judge: Option<JudgeScores>
+}
+
+/// Means of the scores of the answers of one configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnswerMetrics {
+	pub questions: usize,
+	/// Share of the questions that got an answer
+	pub answered: f64,
+	/// Answers citing no document
+	pub uncited: usize,
+	/// Share of the answers citing documents whose every citation was retrieved
+	pub grounded: f64,
+	/// Over the answers citing documents
+	pub groundedness: f64,
+	/// Over the answers citing documents
+	pub citation_validity: f64,
+	/// Over the answered questions
+	pub expected_retrieved: f64,
+	/// Mean judge score by criterion, over the judged answers
+	pub judge: BTreeMap<String, f64>
+}
+
+impl AnswerMetrics {
+	pub fn mean<'a>(results: impl IntoIterator<Item = &'a AnswerResult>) -> Self {
+		let mut metrics = AnswerMetrics::default();
+		let (mut answered, mut cited) = (0, 0);
+		let mut judged: BTreeMap<String, usize> = BTreeMap::new();
+		for result in results {
+			metrics.questions += 1;
+			if result.answer.is_none() {
+				continue;
+			}
+			answered += 1;
+			metrics.expected_retrieved += result.expected_retrieved;
+
+			match result.groundedness.zip(result.citation_validity) {
+				Some((groundedness, citation_validity)) => {
+					cited += 1;
+					metrics.grounded += if groundedness == 1.0 { 1.0 } else { 0.0 };
+					metrics.groundedness += groundedness;
+					metrics.citation_validity += citation_validity;
+				}
+				None => metrics.uncited += 1
+			}
+
+			for (criterion, score) in result.judge.iter().flat_map(|judge| &judge.scores) {
+				*metrics.judge.entry(criterion.clone()).or_default() += score;
+				*judged.entry(criterion.clone()).or_default() += 1;
+			}
+		}
+
+		if metrics.questions > 0 {
+			metrics.answered = answered as f64 / metrics.questions as f64;
+		}
+		if answered > 0 {
+			metrics.expected_retrieved /= answered as f64;
+		}
+		if cited > 0 {
+			let cited = cited as f64;
+			metrics.grounded /= cited;
+			metrics.groundedness /= cited;
+			metrics.citation_validity /= cited;
+		}
+		for (criterion, score) in metrics.judge.iter_mut() {
+			*score /= judged[criterion] as f64;
+		}
+		metrics
+	}
+
+	/// Every metric by name, judge scores as `judge.<criterion>`
+	pub fn named(&self) -> Vec<(String, f64)> {
+		let mut named = vec![
+			("answered".to_string(), self.answered),
+			("grounded".to_string(), self.grounded),
+			("groundedness".to_string(), self.groundedness),
+			("citation_validity".to_string(), self.citation_validity),
+			("expected_retrieved".to_string(), self.expected_retrieved)
+		];
+		named.extend(self.judge.iter().map(|(criterion, score)| (format!("judge.{}", criterion), *score)));
+		named
+	}
+}
+
+/// The answers of one configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerConfigurationResult {
+	pub name: String,
+	pub collection: String,
+	pub prompt_version: String,
+	pub metrics: AnswerMetrics,
+	pub answers: Vec<AnswerResult>
+}
+
+/// The output of an `eval --mode answers` run, which a later one can be compared with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerEvalRun {
+	pub finished_at: DateTime<Utc>,
+	pub golden_set: PathBuf,
+	/// Whether the answers came from the API rather than the scripted responses
+	pub live: bool,
+	pub configurations: Vec<AnswerConfigurationResult>
+}
+
+impl AnswerEvalRun {
+	pub async fn read(path: &Path) -> Result<Self> {
+		let content = fs::read_to_string(path).await.map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+		serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+	}
+
+	pub async fn write(&self, path: &Path) -> Result<()> {
+		write_json(path, self).await
+	}
+
+	/// The metrics of every configuration under the `answers` function
+	pub fn named_metrics(&self) -> NamedMetrics {
+		self.configurations
+			.iter()
+			.flat_map(|configuration| {
+				configuration
+					.metrics
+					.named()
+					.into_iter()
+					.map(move |(metric, value)| ((configuration.name.clone(), ANSWERS_FUNCTION.to_string(), metric), value))
+			})
+			.collect()
+	}
+}
+
+const ANSWERS_FUNCTION: &str = "answers";
+
+/// Asks the question through a whole conversation and scores its answer. Failures are recorded in the result, so that
+/// one question does not end the run.
+pub async fn answer_question<D: RepositoryEmbeddingsDB, M: EmbeddingsModel>(
+	question: &GoldenQuestion,
+	db: Arc<D>,
+	model: Arc<M>,
+	assistant: Assistant,
+	search: &SearchSettings,
+	judge: Option<&Judge>
+) -> AnswerResult {
+	log::info!("Answering question {}", question.id);
+
+	// Collects the events until the conversation drops its sender
+	let (sender, mut events) = mpsc::channel(SSE_CHANNEL_BUFFER_SIZE);
+	let trace = tokio::spawn(async move {
+		let mut trace = Vec::new();
+		while let Some(event) = events.recv().await {
+			trace.push(TraceEvent::from(event));
+		}
+		trace
+	});
+
+	let outcome = converse(question, db, model, assistant, search, sender).await;
+	let trace = trace.await.unwrap_or_default();
+
+	let mut result = AnswerResult {
+		id: question.id.clone(),
+		question: question.question.clone(),
+		error: None,
+		answer: None,
+		trace,
+		retrieved: Vec::new(),
+		citations: Vec::new(),
+		expected_retrieved: 0.0,
+		groundedness: None,
+		citation_validity: None,
+		judge: None
+	};
+	let (answer, retrieved_paths) = match outcome {
+		Ok(outcome) => outcome,
+		Err(e) => {
+			log::error!("Question {} failed: {:#}", question.id, e);
+			result.error = Some(format!("{:#}", e));
+			return result;
+		}
+	};
+
+	result.citations = cited_urls(&answer)
+		.into_iter()
+		.map(|url| {
+			let path = search.path_for(&url);
+			Citation {
+				valid: path.as_ref().is_some_and(|path| Path::new(path).is_file()),
+				retrieved: path.as_ref().is_some_and(|path| retrieved_paths.contains(path)),
+				url,
+				path
+			}
+		})
+		.collect();
+	if !result.citations.is_empty() {
+		let cited = result.citations.len() as f64;
+		result.groundedness = Some(result.citations.iter().filter(|citation| citation.retrieved).count() as f64 / cited);
+		result.citation_validity = Some(result.citations.iter().filter(|citation| citation.valid).count() as f64 / cited);
+	}
+
+	result.retrieved = ranked_documents(retrieved_paths.into_iter(), &search.documents_path);
+	if !question.expected_paths.is_empty() {
+		let found = question.expected_paths.iter().filter(|path| result.retrieved.contains(path)).count();
+		result.expected_retrieved = found as f64 / question.expected_paths.len() as f64;
+	}
+
+	if let Some(judge) = judge {
+		match judge.score(question, &answer).await {
+			Ok(scores) => result.judge = Some(scores),
+			Err(e) => {
+				log::error!("Failed to judge the answer to question {}: {:#}", question.id, e);
+				result.error = Some(format!("{:#}", e));
+			}
+		}
+	}
+	result.answer = Some(answer);
+	result
+}
+
+// The answer and the paths of the documents retrieved for it
+async fn converse<D: RepositoryEmbeddingsDB, M: EmbeddingsModel>(
+	question: &GoldenQuestion,
+	db: Arc<D>,
+	model: Arc<M>,
+	assistant: Assistant,
+	search: &SearchSettings,
+	sender: EventSender
+) -> Result<(String, Vec<String>)> {
+	let query = Query {
+		query: question.question.clone(),
+		profile: None,
+		filter: SearchFilter::default(),
+		planning: None,
+		answer: None
+	};
+	let mut conversation = Conversation::initiate(format!("eval-{}", question.id), query, db, model, assistant, sender)
+		.await?
+		.with_search(search.clone());
+	conversation.generate().await?;
+	Ok((conversation.answer().unwrap_or_default().to_string(), conversation.retrieved_paths().to_vec()))
+}
+
+// The http(s) URLs of a text, Markdown links included, in the order they first appear
+fn cited_urls(text: &str) -> Vec<String> {
+	let mut urls: Vec<String> = Vec::new();
+	let mut rest = text;
+	while let Some(start) = ["https://", "http://"].iter().filter_map(|scheme| rest.find(scheme)).min() {
+		let candidate = &rest[start..];
+		let end = candidate.find(|c: char| c.is_whitespace() || matches!(c, ')' | ']' | '>' | '<' | '"' | '\'')).unwrap_or(candidate.len());
+		let url = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+		if !urls.iter().any(|known| known == url) {
+			urls.push(url.to_string());
+		}
+		rest = &candidate[end..];
+	}
+	urls
+}
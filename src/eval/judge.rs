@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use openai_api_rs::v1::chat_completion::{ChatCompletionMessage, ChatCompletionRequest, MessageRole};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::GoldenQuestion;
+use crate::llm::ChatClient;
+use crate::prelude::*;
+
+const INSTRUCTIONS: &str = "You grade the answers of an assistant answering questions from a set of documents. Score the answer on each \
+                            criterion from 1 (poor) to 5 (excellent). Reply with a JSON object only, of the form {\"scores\": \
+                            {\"<criterion>\": <score>}, \"rationale\": \"<one or two sentences>\"}.";
+
+/// What the judge scores answers on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Criterion {
+	pub name: String,
+	pub description: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rubric {
+	criteria: Vec<Criterion>
+}
+
+/// The `[[criteria]]` of a TOML file
+pub async fn load_rubric(path: &Path) -> Result<Vec<Criterion>> {
+	let content = fs::read_to_string(path).await.map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+	let rubric: Rubric = toml::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+	if rubric.criteria.is_empty() {
+		return Err(anyhow::anyhow!("{} has no criteria", path.display()));
+	}
+	Ok(rubric.criteria)
+}
+
+pub fn default_rubric() -> Vec<Criterion> {
+	[
+		("correctness", "The answer is accurate and agrees with the reference answer when there is one"),
+		("completeness", "The answer addresses every part of the question"),
+		("clarity", "The answer is easy to follow and cites the documents it relies on")
+	]
+	.into_iter()
+	.map(|(name, description)| Criterion {
+		name: name.into(),
+		description: description.into()
+	})
+	.collect()
+}
+
+/// The judge's scores of one answer, by criterion
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JudgeScores {
+	pub scores: BTreeMap<String, f64>,
+	#[serde(default)]
+	pub rationale: String
+}
+
+/// Scores answers against a rubric with a model
+pub struct Judge {
+	llm: Arc<dyn ChatClient>,
+	model: String,
+	rubric: Vec<Criterion>
+}
+
+impl Judge {
+	pub fn new(llm: Arc<dyn ChatClient>, model: String, rubric: Vec<Criterion>) -> Self {
+		Self { llm, model, rubric }
+	}
+
+	/// Fails when the reply is not JSON or misses a criterion, scores out of range are clamped
+	pub async fn score(&self, question: &GoldenQuestion, answer: &str) -> Result<JudgeScores> {
+		let mut content = format!("Question: {}\n\n", question.question);
+		if let Some(reference_answer) = &question.reference_answer {
+			content.push_str(&format!("Reference answer: {}\n\n", reference_answer));
+		}
+		content.push_str(&format!("Answer: {}\n\nCriteria:\n", answer));
+		for criterion in &self.rubric {
+			content.push_str(&format!("- {}: {}\n", criterion.name, criterion.description));
+		}
+
+		let request = ChatCompletionRequest {
+			model: self.model.clone(),
+			messages: vec![
+				ChatCompletionMessage {
+					name: None,
+					function_call: None,
+					role: MessageRole::system,
+					content: INSTRUCTIONS.into()
+				},
+				ChatCompletionMessage {
+					name: None,
+					function_call: None,
+					role: MessageRole::user,
+					content
+				}
+			],
+			functions: None,
+			function_call: None,
+			temperature: Some(0.0),
+			top_p: None,
+			n: None,
+			stream: None,
+			stop: None,
+			max_tokens: None,
+			presence_penalty: None,
+			frequency_penalty: None,
+			logit_bias: None,
+			user: None
+		};
+		let response = self.llm.chat_completion(request).await?;
+		let reply = response
+			.choices
+			.first()
+			.ok_or_else(|| anyhow::anyhow!("The judge replied with no choices"))?
+			.message
+			.content
+			.clone()
+			.unwrap_or_default();
+
+		// Models sometimes wrap the object in a code block
+		let json = match (reply.find('{'), reply.rfind('}')) {
+			(Some(start), Some(end)) if start < end => &reply[start..=end],
+			_ => return Err(anyhow::anyhow!("The judge did not reply with JSON: {}", reply))
+		};
+		let mut scores: JudgeScores = serde_json::from_str(json).map_err(|e| anyhow::anyhow!("Invalid judge reply {}: {}", reply, e))?;
+		for criterion in &self.rubric {
+			let score = scores
+				.scores
+				.get_mut(&criterion.name)
+				.ok_or_else(|| anyhow::anyhow!("The judge did not score {}: {}", criterion.name, reply))?;
+			*score = score.clamp(1.0, 5.0);
+		}
+		scores.scores.retain(|name, _| self.rubric.iter().any(|criterion| &criterion.name == name));
+		Ok(scores)
+	}
+}
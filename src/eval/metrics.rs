@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Scores of the documents retrieved for one question, relevance being binary: a document is expected or it is not
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scores {
+	/// Share of the expected documents among the first k retrieved, by k
+	pub recall: BTreeMap<usize, f64>,
+	/// Inverse of the rank of the first expected document, 0 when none was retrieved
+	pub reciprocal_rank: f64,
+	/// Discounted cumulative gain of the first k documents over the best possible, by k
+	pub ndcg: BTreeMap<usize, f64>
+}
+
+impl Scores {
+	pub fn new(retrieved: &[String], expected: &[String], k: &[usize]) -> Self {
+		let expected: HashSet<&str> = expected.iter().map(String::as_str).collect();
+		let relevant: Vec<bool> = retrieved.iter().map(|path| expected.contains(path.as_str())).collect();
+
+		let recall = k
+			.iter()
+			.map(|k| {
+				let found = relevant.iter().take(*k).filter(|relevant| **relevant).count();
+				(*k, if expected.is_empty() { 0.0 } else { found as f64 / expected.len() as f64 })
+			})
+			.collect();
+		let reciprocal_rank = relevant.iter().position(|relevant| *relevant).map_or(0.0, |rank| 1.0 / (rank + 1) as f64);
+		let ndcg = k
+			.iter()
+			.map(|k| {
+				let dcg: f64 = relevant.iter().take(*k).enumerate().filter(|(_, relevant)| **relevant).map(|(rank, _)| discount(rank)).sum();
+				let ideal: f64 = (0..expected.len().min(*k)).map(discount).sum();
+				(*k, if ideal > 0.0 { dcg / ideal } else { 0.0 })
+			})
+			.collect();
+
+		Self { recall, reciprocal_rank, ndcg }
+	}
+}
+
+fn discount(rank: usize) -> f64 {
+	1.0 / (rank as f64 + 2.0).log2()
+}
+
+/// Means of the scores of every question evaluated with one function
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metrics {
+	pub questions: usize,
+	pub recall: BTreeMap<usize, f64>,
+	/// Mean reciprocal rank
+	pub mrr: f64,
+	pub ndcg: BTreeMap<usize, f64>
+}
+
+impl Metrics {
+	pub fn mean<'a>(scores: impl IntoIterator<Item = &'a Scores>) -> Self {
+		let mut metrics = Metrics::default();
+		for scores in scores {
+			metrics.questions += 1;
+			metrics.mrr += scores.reciprocal_rank;
+			for (k, recall) in &scores.recall {
+				*metrics.recall.entry(*k).or_default() += recall;
+			}
+			for (k, ndcg) in &scores.ndcg {
+				*metrics.ndcg.entry(*k).or_default() += ndcg;
+			}
+		}
+
+		if metrics.questions > 0 {
+			let questions = metrics.questions as f64;
+			metrics.mrr /= questions;
+			metrics.recall.values_mut().chain(metrics.ndcg.values_mut()).for_each(|value| *value /= questions);
+		}
+		metrics
+	}
+
+	/// Every metric by name, e.g. `recall@3`, `mrr` and `ndcg@5`
+	pub fn named(&self) -> Vec<(String, f64)> {
+		let mut named: Vec<(String, f64)> = self.recall.iter().map(|(k, recall)| (format!("recall@{}", k), *recall)).collect();
+		named.push(("mrr".into(), self.mrr));
+		named.extend(self.ndcg.iter().map(|(k, ndcg)| (format!("ndcg@{}", k), *ndcg)));
+		named
+	}
+}
@@ -0,0 +1,246 @@
+pub mod answers;
+pub mod judge;
+pub mod metrics;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use self::metrics::{Metrics, Scores};
+use crate::convrsation::settings::SearchSettings;
+use crate::db::{RepositoryEmbeddingsDB, SearchFilter};
+use crate::embeddings::EmbeddingsModel;
+use crate::llm::ScriptedResponse;
+use crate::prelude::*;
+use crate::utils::functions::{search_documents, search_path, Function};
+
+/// A question of a golden set, one line of its JSONL file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GoldenQuestion {
+	/// The line number when absent
+	#[serde(default)]
+	pub id: String,
+	pub question: String,
+	/// Paths of the documents answering the question, relative to the documents directory
+	pub expected_paths: Vec<String>,
+	/// What `search_path` is evaluated with, only `search_documents` is evaluated when absent
+	#[serde(default)]
+	pub path_query: Option<String>,
+	/// What the model replies to each request of the conversation answering the question, in order, for answer
+	/// evaluations run without the API
+	#[serde(default)]
+	pub responses: Vec<ScriptedResponse>,
+	/// Shown to the judge
+	#[serde(default)]
+	pub reference_answer: Option<String>
+}
+
+pub async fn load_golden_set(path: &Path) -> Result<Vec<GoldenQuestion>> {
+	let content = fs::read_to_string(path).await.map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+
+	let mut questions = Vec::new();
+	for (i, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+		let mut question: GoldenQuestion =
+			serde_json::from_str(line).map_err(|e| anyhow::anyhow!("Invalid question at {}:{}: {}", path.display(), i + 1, e))?;
+		if question.id.is_empty() {
+			question.id = (i + 1).to_string();
+		}
+		questions.push(question);
+	}
+	Ok(questions)
+}
+
+/// Settings evaluated together, unset ones are those of the configuration. Chunk sizes and embeddings models are
+/// compared by indexing the documents into one collection per variant.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EvalConfiguration {
+	pub name: String,
+	pub profile: Option<String>,
+	/// The collection of the profile by default
+	pub collection: Option<String>,
+	/// The model the collection was embedded with
+	pub model_path: Option<PathBuf>,
+	pub relevant_files_limit: Option<usize>,
+	pub relevant_chunks_limit: Option<usize>,
+	pub linked_files_limit: Option<usize>,
+	/// The first configured version by default, only used by answer evaluations
+	pub prompt_version: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EvalConfigurations {
+	configurations: Vec<EvalConfiguration>
+}
+
+/// The `[[configurations]]` of a TOML file
+pub async fn load_configurations(path: &Path) -> Result<Vec<EvalConfiguration>> {
+	let content = fs::read_to_string(path).await.map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+	let configurations: EvalConfigurations = toml::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+	Ok(configurations.configurations)
+}
+
+/// What one question retrieved with one function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionResult {
+	pub id: String,
+	pub function: String,
+	/// Relative to the documents directory, in rank order
+	pub retrieved: Vec<String>,
+	pub scores: Scores
+}
+
+/// The results of one configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigurationResult {
+	pub name: String,
+	pub collection: String,
+	pub model_path: PathBuf,
+	pub relevant_files_limit: usize,
+	pub relevant_chunks_limit: usize,
+	pub linked_files_limit: usize,
+	/// By function name
+	pub metrics: BTreeMap<String, Metrics>,
+	pub questions: Vec<QuestionResult>
+}
+
+/// The output of an `eval` run, which a later run can be compared with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalRun {
+	pub finished_at: DateTime<Utc>,
+	pub golden_set: PathBuf,
+	pub k: Vec<usize>,
+	pub configurations: Vec<ConfigurationResult>
+}
+
+impl EvalRun {
+	pub async fn read(path: &Path) -> Result<Self> {
+		let content = fs::read_to_string(path).await.map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+		serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+	}
+
+	pub async fn write(&self, path: &Path) -> Result<()> {
+		write_json(path, self).await
+	}
+
+	pub fn named_metrics(&self) -> NamedMetrics {
+		self.configurations
+			.iter()
+			.flat_map(|configuration| {
+				configuration.metrics.iter().flat_map(move |(function, metrics)| {
+					metrics
+						.named()
+						.into_iter()
+						.map(move |(metric, value)| ((configuration.name.clone(), function.clone(), metric), value))
+				})
+			})
+			.collect()
+	}
+}
+
+/// Metrics of a run by configuration, function and metric name
+pub type NamedMetrics = BTreeMap<(String, String, String), f64>;
+
+/// Scores every question with `search_documents`, and with `search_path` when it has a path query
+pub async fn evaluate<M: EmbeddingsModel, D: RepositoryEmbeddingsDB>(
+	questions: &[GoldenQuestion],
+	model: &M,
+	db: &D,
+	search: &SearchSettings,
+	k: &[usize]
+) -> Result<(BTreeMap<String, Metrics>, Vec<QuestionResult>)> {
+	let filter = SearchFilter::default();
+	let path_limit = k.iter().max().copied().unwrap_or(search.files_limit);
+	let mut results = Vec::new();
+
+	for question in questions {
+		log::info!("Evaluating question {}", question.id);
+		let chunks = search_documents(&question.question, model, db, &filter, search).await?;
+		let retrieved = ranked_documents(chunks.into_iter().map(|chunk| chunk.path), &search.documents_path);
+		results.push(QuestionResult {
+			id: question.id.clone(),
+			function: Function::SearchDocuments.to_string(),
+			scores: Scores::new(&retrieved, &question.expected_paths, k),
+			retrieved
+		});
+
+		if let Some(path_query) = &question.path_query {
+			let paths = search_path(path_query, db, &filter, path_limit).await?;
+			let retrieved = ranked_documents(paths.into_iter(), &search.documents_path);
+			results.push(QuestionResult {
+				id: question.id.clone(),
+				function: Function::SearchPath.to_string(),
+				scores: Scores::new(&retrieved, &question.expected_paths, k),
+				retrieved
+			});
+		}
+	}
+
+	let mut metrics = BTreeMap::new();
+	for function in [Function::SearchDocuments, Function::SearchPath] {
+		let function = function.to_string();
+		let scores: Vec<&Scores> = results.iter().filter(|result| result.function == function).map(|result| &result.scores).collect();
+		if !scores.is_empty() {
+			metrics.insert(function, Metrics::mean(scores));
+		}
+	}
+	Ok((metrics, results))
+}
+
+// The documents in the order they were first returned, relative to the documents directory as in golden sets
+fn ranked_documents(paths: impl Iterator<Item = String>, documents_path: &Path) -> Vec<String> {
+	let documents_path = documents_path.to_string_lossy();
+	let mut documents: Vec<String> = Vec::new();
+	for path in paths {
+		let relative = path.strip_prefix(documents_path.as_ref()).unwrap_or(&path).trim_start_matches('/').to_string();
+		if !documents.contains(&relative) {
+			documents.push(relative);
+		}
+	}
+	documents
+}
+
+/// How one metric moved since the baseline, either side is absent when the configuration, function or metric is new
+/// or gone
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricDiff {
+	pub configuration: String,
+	pub function: String,
+	pub metric: String,
+	pub baseline: Option<f64>,
+	pub current: Option<f64>,
+	pub delta: Option<f64>
+}
+
+/// Every metric of either run, configurations being matched by name
+pub fn diff(baseline: &NamedMetrics, current: &NamedMetrics) -> Vec<MetricDiff> {
+	let mut keys: Vec<&(String, String, String)> = baseline.keys().chain(current.keys()).collect();
+	keys.sort_unstable();
+	keys.dedup();
+	keys.into_iter()
+		.map(|key| {
+			let (baseline, current) = (baseline.get(key).copied(), current.get(key).copied());
+			MetricDiff {
+				configuration: key.0.clone(),
+				function: key.1.clone(),
+				metric: key.2.clone(),
+				baseline,
+				current,
+				delta: baseline.zip(current).map(|(baseline, current)| current - baseline)
+			}
+		})
+		.collect()
+}
+
+pub async fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		fs::create_dir_all(dir).await?;
+	}
+	fs::write(path, serde_json::to_string_pretty(value)?).await?;
+	Ok(())
+}
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::constants::FEEDBACK_COMMENT_MAX_LENGTH;
+use crate::prelude::*;
+
+const ANSWERS_FILE: &str = "answers.jsonl";
+const FEEDBACK_FILE: &str = "feedback.jsonl";
+
+/// Everything needed to review an answer offline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerRecord {
+	pub answer_id: String,
+	pub query: String,
+	pub sanitized_query: String,
+	pub retrieved_paths: Vec<String>,
+	pub answer: String,
+	/// Empty for answers recorded before prompt templates were versioned
+	#[serde(default)]
+	pub prompt_version: String,
+	/// Empty for answers recorded before corpus profiles were introduced
+	#[serde(default)]
+	pub profile: String,
+	pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+	Up,
+	Down
+}
+
+/// A user's verdict on an answer, as sent to `/feedback`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Feedback {
+	/// The ID sent in the `x-answer-id` header and the `PROCESS_QUERY` event
+	pub answer_id: String,
+	pub rating: Rating,
+	pub comment: Option<String>
+}
+
+impl Feedback {
+	pub fn validate(&self) -> Result<()> {
+		match &self.comment {
+			Some(comment) if comment.chars().count() > FEEDBACK_COMMENT_MAX_LENGTH => {
+				Err(anyhow::anyhow!("Comments are limited to {} characters", FEEDBACK_COMMENT_MAX_LENGTH))
+			}
+			_ => Ok(())
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRecord {
+	#[serde(flatten)]
+	pub feedback: Feedback,
+	pub created_at: DateTime<Utc>
+}
+
+/// An answer joined with all feedback it received, as produced by the export
+#[derive(Debug, Serialize)]
+pub struct ReviewItem {
+	#[serde(flatten)]
+	pub answer: AnswerRecord,
+	pub feedback: Vec<FeedbackRecord>
+}
+
+/// Append-only JSONL store for answers and their feedback
+pub struct FeedbackStore {
+	dir: PathBuf,
+	answer_ids: Mutex<HashSet<String>>
+}
+
+impl FeedbackStore {
+	pub async fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+		let dir = dir.as_ref().to_path_buf();
+		fs::create_dir_all(&dir).await?;
+
+		let answer_ids = read_jsonl::<AnswerRecord>(&dir.join(ANSWERS_FILE))
+			.await?
+			.into_iter()
+			.map(|record| record.answer_id)
+			.collect::<HashSet<String>>();
+		log::info!("Feedback store at {} holds {} answers", dir.display(), answer_ids.len());
+
+		Ok(Self {
+			dir,
+			answer_ids: Mutex::new(answer_ids)
+		})
+	}
+
+	pub async fn record_answer(&self, record: &AnswerRecord) -> Result<()> {
+		let mut answer_ids = self.answer_ids.lock().await;
+		append_jsonl(&self.dir.join(ANSWERS_FILE), record).await?;
+		answer_ids.insert(record.answer_id.clone());
+		Ok(())
+	}
+
+	/// Returns `false` without storing anything if the answer is unknown
+	pub async fn record_feedback(&self, feedback: Feedback) -> Result<bool> {
+		let answer_ids = self.answer_ids.lock().await;
+		if !answer_ids.contains(&feedback.answer_id) {
+			return Ok(false);
+		}
+
+		let record = FeedbackRecord {
+			feedback,
+			created_at: Utc::now()
+		};
+		append_jsonl(&self.dir.join(FEEDBACK_FILE), &record).await?;
+		Ok(true)
+	}
+
+	/// Joins every answer with its feedback. With `rated_only`, answers without feedback are left out.
+	pub async fn export(&self, rated_only: bool) -> Result<Vec<ReviewItem>> {
+		let _guard = self.answer_ids.lock().await;
+
+		let mut feedback: HashMap<String, Vec<FeedbackRecord>> = HashMap::new();
+		for record in read_jsonl::<FeedbackRecord>(&self.dir.join(FEEDBACK_FILE)).await? {
+			feedback.entry(record.feedback.answer_id.clone()).or_default().push(record);
+		}
+
+		let items = read_jsonl::<AnswerRecord>(&self.dir.join(ANSWERS_FILE))
+			.await?
+			.into_iter()
+			.map(|answer| ReviewItem {
+				feedback: feedback.remove(&answer.answer_id).unwrap_or_default(),
+				answer
+			})
+			.filter(|item| !rated_only || !item.feedback.is_empty())
+			.collect();
+
+		Ok(items)
+	}
+}
+
+async fn append_jsonl<T: Serialize>(path: &Path, record: &T) -> Result<()> {
+	let mut line = serde_json::to_string(record)?;
+	line.push('\n');
+
+	let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+	file.write_all(line.as_bytes()).await?;
+	file.flush().await?;
+	Ok(())
+}
+
+async fn read_jsonl<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Vec<T>> {
+	let content = match fs::read_to_string(path).await {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e.into())
+	};
+
+	let mut records = Vec::new();
+	for (number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+		match serde_json::from_str(line) {
+			Ok(record) => records.push(record),
+			Err(e) => log::warn!("Skipping malformed line {} in {}: {}", number + 1, path.display(), e)
+		}
+	}
+	Ok(records)
+}
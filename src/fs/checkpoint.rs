@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::prelude::*;
+
+/// One upserted batch, a line of the checkpoint file
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointBatch {
+	collection: String,
+	batch: usize,
+	paths: Vec<String>,
+	/// Documents removed from the collection by watch mode
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	deleted: Vec<String>
+}
+
+/// The documents stored in a collection by an embed run, appended to a JSON lines file after every upserted batch so
+/// that an interrupted run can resume where it stopped
+pub struct Checkpoint {
+	path: PathBuf,
+	collection: String,
+	file: File,
+	batches: usize,
+	completed: HashSet<String>
+}
+
+impl Checkpoint {
+	/// Starts an empty checkpoint, replacing the one at `path`
+	pub async fn create(path: &Path, collection: &str) -> Result<Self> {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir).await?;
+		}
+		let file = File::create(path).await?;
+
+		Ok(Self {
+			path: path.to_path_buf(),
+			collection: collection.to_string(),
+			file,
+			batches: 0,
+			completed: HashSet::new()
+		})
+	}
+
+	/// Continues the checkpoint at `path`, `None` if there is none
+	pub async fn resume(path: &Path, collection: &str) -> Result<Option<Self>> {
+		let content = match fs::read_to_string(path).await {
+			Ok(content) => content,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e.into())
+		};
+
+		let mut batches = 0;
+		let mut completed = HashSet::new();
+		for line in content.lines().filter(|line| !line.trim().is_empty()) {
+			// The last line is cut short when the run was killed while writing it, its batch is upserted again
+			let Ok(batch) = serde_json::from_str::<CheckpointBatch>(line) else {
+				log::warn!("Ignoring an incomplete line of checkpoint {}", path.display());
+				continue;
+			};
+			if batch.collection != collection {
+				return Err(anyhow::anyhow!(
+					"Checkpoint {} belongs to collection {}, not {}",
+					path.display(),
+					batch.collection,
+					collection
+				));
+			}
+			batches = batches.max(batch.batch + 1);
+			for path in &batch.deleted {
+				completed.remove(path);
+			}
+			completed.extend(batch.paths);
+		}
+
+		let mut file = OpenOptions::new().append(true).open(path).await?;
+		if !content.is_empty() && !content.ends_with('\n') {
+			file.write_all(b"\n").await?;
+		}
+		Ok(Some(Self {
+			path: path.to_path_buf(),
+			collection: collection.to_string(),
+			file,
+			batches,
+			completed
+		}))
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn batches(&self) -> usize {
+		self.batches
+	}
+
+	/// Paths of the documents upserted so far
+	pub fn completed(&self) -> &HashSet<String> {
+		&self.completed
+	}
+
+	/// Rewrites the checkpoint as a single batch holding the documents of the collection, dropping the upserts and
+	/// deletions watch mode keeps appending. The file is replaced at once, so an interruption leaves either version.
+	pub async fn compact(&mut self) -> Result<()> {
+		let mut paths: Vec<String> = self.completed.iter().cloned().collect();
+		paths.sort_unstable();
+		let batch = CheckpointBatch {
+			collection: self.collection.clone(),
+			batch: 0,
+			paths,
+			deleted: Vec::new()
+		};
+		let mut line = serde_json::to_string(&batch)?;
+		line.push('\n');
+
+		let compacted = self.path.with_extension("jsonl.tmp");
+		let mut file = File::create(&compacted).await?;
+		file.write_all(line.as_bytes()).await?;
+		file.sync_all().await?;
+		fs::rename(&compacted, &self.path).await?;
+
+		self.file = OpenOptions::new().append(true).open(&self.path).await?;
+		self.batches = 1;
+		Ok(())
+	}
+
+	/// Records a batch once it is upserted, the line is synced to disk before returning
+	pub async fn record(&mut self, paths: Vec<String>) -> Result<()> {
+		self.append(paths, Vec::new()).await
+	}
+
+	/// Records documents once they are deleted from the collection
+	pub async fn record_deleted(&mut self, paths: Vec<String>) -> Result<()> {
+		self.append(Vec::new(), paths).await
+	}
+
+	async fn append(&mut self, paths: Vec<String>, deleted: Vec<String>) -> Result<()> {
+		let batch = CheckpointBatch {
+			collection: self.collection.clone(),
+			batch: self.batches,
+			paths,
+			deleted
+		};
+		let mut line = serde_json::to_string(&batch)?;
+		line.push('\n');
+		self.file.write_all(line.as_bytes()).await?;
+		self.file.sync_data().await?;
+
+		self.batches += 1;
+		for path in &batch.deleted {
+			self.completed.remove(path);
+		}
+		self.completed.extend(batch.paths);
+		Ok(())
+	}
+}
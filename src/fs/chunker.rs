@@ -0,0 +1,246 @@
+use text_splitter::TextSplitter;
+
+use super::metadata::split_front_matter;
+use crate::constants::CHUNK_MAX_CHARS;
+
+const BREADCRUMB_SEPARATOR: &str = " > ";
+
+/// A part of a Markdown document that stays within one section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+	/// Headings of the sections the chunk is in, outermost first
+	pub breadcrumb: Vec<String>,
+	pub text: String
+}
+
+/// The text prefixed with its breadcrumb, as embedded and shown to the model
+impl std::fmt::Display for Chunk {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.breadcrumb.is_empty() {
+			write!(f, "{}", self.text)
+		} else {
+			write!(f, "{}\n\n{}", self.breadcrumb.join(BREADCRUMB_SEPARATOR), self.text)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockKind {
+	Paragraph,
+	List,
+	Table,
+	Code
+}
+
+struct Block {
+	kind: BlockKind,
+	lines: Vec<String>
+}
+
+impl Block {
+	fn len(&self) -> usize {
+		self.lines.iter().map(|line| char_count(line) + 1).sum()
+	}
+
+	fn text(&self) -> String {
+		self.lines.join("\n")
+	}
+}
+
+/// Splits a Markdown document at heading boundaries into chunks of at most `CHUNK_MAX_CHARS` characters, not counting
+/// the breadcrumb. Tables, lists and code blocks are kept whole when they fit, otherwise tables are split between rows
+/// with their header repeated, and lists between items.
+pub fn chunk_markdown(content: &str) -> Vec<Chunk> {
+	chunk_markdown_with_capacity(content, CHUNK_MAX_CHARS)
+}
+
+pub fn chunk_markdown_with_capacity(content: &str, max_chars: usize) -> Vec<Chunk> {
+	let (_, body) = split_front_matter(content);
+	let mut chunks = Vec::new();
+	let mut breadcrumb: Vec<(usize, String)> = Vec::new();
+	let mut blocks: Vec<Block> = Vec::new();
+	let mut lines = body.lines().map(|line| line.trim_end()).peekable();
+
+	while let Some(line) = lines.next() {
+		if let Some((level, heading)) = parse_heading(line) {
+			flush_section(&mut chunks, &breadcrumb, std::mem::take(&mut blocks), max_chars);
+			breadcrumb.retain(|(outer, _)| *outer < level);
+			breadcrumb.push((level, heading));
+			continue;
+		}
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let trimmed = line.trim_start();
+		let block = if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+			let fence = &trimmed[..3];
+			let mut block_lines = vec![line.to_string()];
+			for line in lines.by_ref() {
+				block_lines.push(line.to_string());
+				if line.trim_start().starts_with(fence) {
+					break;
+				}
+			}
+			Block {
+				kind: BlockKind::Code,
+				lines: block_lines
+			}
+		} else if trimmed.starts_with('|') {
+			let mut block_lines = vec![line.to_string()];
+			while let Some(line) = lines.next_if(|line| line.trim_start().starts_with('|')) {
+				block_lines.push(line.to_string());
+			}
+			Block {
+				kind: BlockKind::Table,
+				lines: block_lines
+			}
+		} else if is_list_item(trimmed) {
+			let mut block_lines = vec![line.to_string()];
+			// Items, and the indented lines continuing them
+			while let Some(line) = lines.next_if(|line| continues_list(line)) {
+				block_lines.push(line.to_string());
+			}
+			Block {
+				kind: BlockKind::List,
+				lines: block_lines
+			}
+		} else {
+			let mut block_lines = vec![line.to_string()];
+			while let Some(line) = lines.next_if(|line| continues_paragraph(line)) {
+				block_lines.push(line.to_string());
+			}
+			Block {
+				kind: BlockKind::Paragraph,
+				lines: block_lines
+			}
+		};
+		blocks.push(block);
+	}
+	flush_section(&mut chunks, &breadcrumb, blocks, max_chars);
+
+	chunks
+}
+
+// Packs the blocks of one section into chunks, splitting the blocks that do not fit in a chunk of their own
+fn flush_section(chunks: &mut Vec<Chunk>, breadcrumb: &[(usize, String)], blocks: Vec<Block>, max_chars: usize) {
+	let breadcrumb: Vec<String> = breadcrumb.iter().map(|(_, heading)| heading.clone()).collect();
+	let mut current = String::new();
+
+	let mut push = |text: String, current: &mut String| {
+		if !current.is_empty() && char_count(current) + char_count(&text) + 2 > max_chars {
+			chunks.push(Chunk {
+				breadcrumb: breadcrumb.clone(),
+				text: std::mem::take(current)
+			});
+		}
+		if !current.is_empty() {
+			current.push_str("\n\n");
+		}
+		current.push_str(&text);
+	};
+
+	for block in blocks {
+		if block.len() <= max_chars {
+			push(block.text(), &mut current);
+			continue;
+		}
+		for part in split_block(&block, max_chars) {
+			push(part, &mut current);
+		}
+	}
+
+	if !current.is_empty() {
+		chunks.push(Chunk {
+			breadcrumb,
+			text: current
+		});
+	}
+}
+
+fn split_block(block: &Block, max_chars: usize) -> Vec<String> {
+	match block.kind {
+		BlockKind::Table => {
+			// The header and the delimiter row are repeated in every part, so that each part can be read on its own
+			let header_len = if block.lines.len() > 2 && is_table_delimiter(&block.lines[1]) { 2 } else { 0 };
+			let header = &block.lines[..header_len];
+			group_lines(&block.lines[header_len..], header, max_chars)
+		}
+		BlockKind::List => {
+			let mut items: Vec<String> = Vec::new();
+			for line in &block.lines {
+				match items.last_mut() {
+					Some(item) if !is_list_item(line.trim_start()) => {
+						item.push('\n');
+						item.push_str(line);
+					}
+					_ => items.push(line.clone())
+				}
+			}
+			group_lines(&items, &[], max_chars)
+		}
+		BlockKind::Code => group_lines(&block.lines, &[], max_chars),
+		BlockKind::Paragraph => {
+			let splitter = TextSplitter::default().with_trim_chunks(true);
+			splitter.chunks(&block.text(), max_chars / 2..=max_chars).map(str::to_string).collect()
+		}
+	}
+}
+
+// Groups consecutive lines into parts of at most `max_chars` characters, each starting with `header`. A line longer than
+// `max_chars` makes a part of its own.
+fn group_lines(lines: &[String], header: &[String], max_chars: usize) -> Vec<String> {
+	let header = header.join("\n");
+	let mut parts = Vec::new();
+	let mut current = header.clone();
+
+	for line in lines {
+		if current.len() > header.len() && char_count(&current) + char_count(line) + 1 > max_chars {
+			parts.push(std::mem::replace(&mut current, header.clone()));
+		}
+		if !current.is_empty() {
+			current.push('\n');
+		}
+		current.push_str(line);
+	}
+	if current.len() > header.len() {
+		parts.push(current);
+	}
+
+	parts
+}
+
+// Lengths are compared with `CHUNK_MAX_CHARS` in characters, as `TextSplitter` does, not in bytes
+fn char_count(text: &str) -> usize {
+	text.chars().count()
+}
+
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+	let level = line.chars().take_while(|c| *c == '#').count();
+	if !(1..=6).contains(&level) {
+		return None;
+	}
+	let heading = line[level..].strip_prefix(' ')?.trim().trim_end_matches('#').trim();
+	(!heading.is_empty()).then(|| (level, heading.to_string()))
+}
+
+fn continues_list(line: &str) -> bool {
+	!line.trim().is_empty() && parse_heading(line).is_none() && (is_list_item(line.trim_start()) || line.starts_with([' ', '\t']))
+}
+
+fn continues_paragraph(line: &str) -> bool {
+	let trimmed = line.trim_start();
+	!trimmed.is_empty() && parse_heading(line).is_none() && !trimmed.starts_with(['|', '`', '~']) && !is_list_item(trimmed)
+}
+
+fn is_list_item(line: &str) -> bool {
+	if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+		return true;
+	}
+	let digits = line.chars().take_while(char::is_ascii_digit).count();
+	digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") "))
+}
+
+fn is_table_delimiter(line: &str) -> bool {
+	line.trim().chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
@@ -0,0 +1,72 @@
+use std::path::{Component, Path, PathBuf};
+
+use super::loader::loader_for;
+
+/// Paths of the local documents `content` links to, in the same form as the indexed paths. Links to other sites, images
+/// and anchors within the document are left out. In Markdown documents, links to `.html` pages are read as links to the
+/// `.md` documents of the same pages, as in pages converted by other tools, pages of a raw HTML mirror keep them as they are.
+pub fn extract_links(path: &Path, base_path: &Path, content: &str) -> Vec<String> {
+	let base_path = normalize(base_path);
+	let dir = path.parent().unwrap_or(&base_path);
+	let is_markdown = path.extension().map_or(false, |extension| extension == "md");
+	let mut links: Vec<String> = Vec::new();
+
+	for target in link_targets(content) {
+		let target = target.split(['#', '?']).next().unwrap_or_default().trim();
+		if target.contains("://") {
+			continue;
+		}
+
+		let target = match target.strip_suffix(".html").or_else(|| target.strip_suffix(".htm")) {
+			Some(stem) if is_markdown => format!("{}.md", stem),
+			_ if loader_for(Path::new(target)).is_some() => target.to_string(),
+			_ => continue
+		};
+		let resolved = match target.strip_prefix('/') {
+			Some(absolute) => normalize(&base_path.join(absolute)),
+			None => normalize(&dir.join(&target))
+		};
+
+		// Links leaving the documents directory cannot be followed
+		if !resolved.starts_with(&base_path) || resolved == path {
+			continue;
+		}
+		let resolved = resolved.to_string_lossy().to_string();
+		if !links.contains(&resolved) {
+			links.push(resolved);
+		}
+	}
+
+	links
+}
+
+// Targets of the inline `[text](target "title")` links, images included
+fn link_targets(content: &str) -> Vec<&str> {
+	let mut targets = Vec::new();
+	let mut rest = content;
+	while let Some(start) = rest.find("](") {
+		rest = &rest[start + 2..];
+		let Some(end) = rest.find(')') else {
+			break;
+		};
+		let target = rest[..end].split_whitespace().next().unwrap_or_default();
+		targets.push(target.trim_start_matches('<').trim_end_matches('>'));
+		rest = &rest[end + 1..];
+	}
+	targets
+}
+
+// Resolves `.` and `..` without touching the file system, the linked document may not exist
+fn normalize(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				normalized.pop();
+			}
+			component => normalized.push(component)
+		}
+	}
+	normalized
+}
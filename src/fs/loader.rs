@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use super::{read_file, SkipReason};
+use crate::prelude::*;
+
+// Elements holding no content worth indexing, removed before HTML is converted
+const HTML_IGNORED_ELEMENTS: [&str; 6] = ["head", "script", "style", "nav", "header", "footer"];
+
+/// Converts the files of one type to the Markdown the chunker and the metadata extraction work on
+pub trait DocumentLoader: Send + Sync {
+	/// Lowercase extensions of the files the loader reads
+	fn extensions(&self) -> &[&'static str];
+
+	fn load(&self, bytes: Vec<u8>) -> Result<String>;
+}
+
+/// Markdown, and plain text read as Markdown
+pub struct MarkdownLoader;
+
+impl DocumentLoader for MarkdownLoader {
+	fn extensions(&self) -> &[&'static str] {
+		&["md", "markdown", "txt"]
+	}
+
+	fn load(&self, bytes: Vec<u8>) -> Result<String> {
+		Ok(String::from_utf8(bytes)?)
+	}
+}
+
+/// HTML pages, only the `<main>` element when there is one. The `<title>` becomes the `title` front matter field.
+pub struct HtmlLoader;
+
+impl DocumentLoader for HtmlLoader {
+	fn extensions(&self) -> &[&'static str] {
+		&["html", "htm"]
+	}
+
+	fn load(&self, bytes: Vec<u8>) -> Result<String> {
+		let (title, markdown) = html_to_markdown(&String::from_utf8_lossy(&bytes));
+
+		Ok(match title {
+			Some(title) => format!("---\ntitle: {}\n---\n\n{}", title, markdown),
+			None => markdown
+		})
+	}
+}
+
+/// The title of an HTML page and its content as Markdown, as `HtmlLoader` reads it
+pub fn html_to_markdown(html: &str) -> (Option<String>, String) {
+	let title = element_content(html, "title").map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "));
+
+	let body = element_content(html, "main").unwrap_or(html);
+	let markdown = html2md::parse_html(&remove_elements(body, &HTML_IGNORED_ELEMENTS));
+
+	(title.filter(|title| !title.is_empty()), markdown.trim().to_string())
+}
+
+/// The text layer of PDF files, scanned documents without one have no text
+pub struct PdfLoader;
+
+impl DocumentLoader for PdfLoader {
+	fn extensions(&self) -> &[&'static str] {
+		&["pdf"]
+	}
+
+	fn load(&self, bytes: Vec<u8>) -> Result<String> {
+		let text = pdf_extract::extract_text_from_mem(&bytes).map_err(|e| anyhow!("Failed to extract the text of the PDF: {}", e))?;
+
+		// Pages are separated by form feeds, and lines of a paragraph by single line breaks
+		Ok(text.replace('\u{c}', "\n\n").lines().map(str::trim_end).collect::<Vec<_>>().join("\n"))
+	}
+}
+
+static LOADERS: [&dyn DocumentLoader; 3] = [&MarkdownLoader, &HtmlLoader, &PdfLoader];
+
+pub enum LoadedDocument {
+	Markdown(String),
+	Skipped(SkipReason)
+}
+
+pub fn loader_for(path: &Path) -> Option<&'static dyn DocumentLoader> {
+	let extension = path.extension()?.to_string_lossy().to_lowercase();
+	LOADERS.iter().find(|loader| loader.extensions().contains(&extension.as_str())).copied()
+}
+
+/// Reads the file at `path` with the loader of its type, converting it to Markdown
+pub async fn load_document(path: &Path) -> Result<LoadedDocument> {
+	let Some(loader) = loader_for(path) else {
+		let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+		return Ok(LoadedDocument::Skipped(SkipReason::UnsupportedType(extension)));
+	};
+
+	let bytes = read_file(path.to_path_buf()).await?;
+	// PDF extraction is CPU bound, and may panic on malformed files
+	let markdown = tokio::task::spawn_blocking(move || loader.load(bytes))
+		.await
+		.map_err(|e| anyhow!("Loader failed on {}: {}", path.display(), e))??;
+
+	if markdown.trim().is_empty() {
+		return Ok(LoadedDocument::Skipped(SkipReason::NoText));
+	}
+	Ok(LoadedDocument::Markdown(markdown))
+}
+
+// The content of the first `tag` element, case insensitive
+fn element_content<'a>(html: &'a str, tag: &str) -> Option<&'a str> {
+	let lowercase = html.to_ascii_lowercase();
+	let open = find_open_tag(&lowercase, tag, 0)?;
+	let start = open + lowercase[open..].find('>')? + 1;
+	let end = start + lowercase[start..].find(&format!("</{}>", tag))?;
+	Some(&html[start..end])
+}
+
+fn remove_elements(html: &str, tags: &[&str]) -> String {
+	let mut html = html.to_string();
+	for tag in tags {
+		let close = format!("</{}>", tag);
+		let mut from = 0;
+		loop {
+			let lowercase = html.to_ascii_lowercase();
+			let Some(start) = find_open_tag(&lowercase, tag, from) else {
+				break;
+			};
+			let end = match lowercase[start..].find(&close) {
+				Some(end) => start + end + close.len(),
+				None => html.len()
+			};
+			html.replace_range(start..end, "");
+			from = start;
+		}
+	}
+	html
+}
+
+// Position of the next `<tag` that is not the start of a longer tag name, such as `<header` for `<head`
+fn find_open_tag(lowercase: &str, tag: &str, from: usize) -> Option<usize> {
+	let open = format!("<{}", tag);
+	let mut from = from;
+	while let Some(position) = lowercase[from..].find(&open) {
+		let position = from + position;
+		match lowercase[position + open.len()..].chars().next() {
+			Some(c) if c == '>' || c == '/' || c.is_whitespace() => return Some(position),
+			_ => from = position + open.len()
+		}
+	}
+	None
+}
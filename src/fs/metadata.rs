@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::links::extract_links;
+
+// Front matter fields holding the last modification date, in order of preference
+const MODIFIED_FIELDS: [&str; 4] = ["last_modified", "date_modified", "modified", "date"];
+const LANGUAGE_FIELDS: [&str; 2] = ["language", "lang"];
+
+/// What the embed job knows about a document besides its content, stored in the payload of its point
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+	/// The `title` front matter field, or the first level one heading
+	pub title: Option<String>,
+	/// First directory of the path below the documents directory, after the language directory if there is one
+	pub section: Option<String>,
+	/// The `language` front matter field, or a two letter language directory such as `en/`
+	pub language: Option<String>,
+	/// From the front matter, or the modification time of the file
+	pub last_modified: Option<DateTime<Utc>>,
+	/// Every `key: value` line of the front matter
+	pub front_matter: BTreeMap<String, String>,
+	/// Paths of the documents linked to, see `extract_links`
+	pub links: Vec<String>
+}
+
+impl DocumentMetadata {
+	/// Extracts the metadata of the document at `path`, relative to `base_path`
+	pub fn extract(path: &Path, base_path: &Path, content: &str, modified: Option<SystemTime>) -> Self {
+		let (front_matter, body) = split_front_matter(content);
+
+		let components: Vec<String> = path
+			.strip_prefix(base_path)
+			.unwrap_or(path)
+			.parent()
+			.map(|dir| dir.iter().map(|component| component.to_string_lossy().to_string()).collect())
+			.unwrap_or_default();
+		let path_language = components.first().filter(|component| is_language_code(component)).cloned();
+		let section = match path_language {
+			Some(_) => components.get(1),
+			None => components.first()
+		}
+		.cloned();
+
+		let title = front_matter.get("title").cloned().or_else(|| {
+			body.lines()
+				.find_map(|line| line.strip_prefix("# "))
+				.map(|heading| heading.trim().to_string())
+				.filter(|heading| !heading.is_empty())
+		});
+		let language = LANGUAGE_FIELDS.iter().find_map(|field| front_matter.get(*field).cloned()).or(path_language);
+		let last_modified = MODIFIED_FIELDS
+			.iter()
+			.find_map(|field| front_matter.get(*field).and_then(|value| parse_date(value)))
+			.or_else(|| modified.map(DateTime::<Utc>::from));
+
+		Self {
+			title,
+			section,
+			language,
+			last_modified,
+			front_matter,
+			links: extract_links(path, base_path, body)
+		}
+	}
+}
+
+/// The `key: value` pairs of a front matter block delimited by `---` lines, and the rest of the document. Nested YAML
+/// is not supported, lists are kept as written.
+pub fn split_front_matter(content: &str) -> (BTreeMap<String, String>, &str) {
+	let mut fields = BTreeMap::new();
+	let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) else {
+		return (fields, content);
+	};
+	let Some(end) = rest.find("\n---") else {
+		return (fields, content);
+	};
+
+	for line in rest[..end].lines() {
+		if let Some((key, value)) = line.split_once(':') {
+			let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+			if !key.starts_with(char::is_whitespace) && !value.is_empty() {
+				fields.insert(key.trim().to_lowercase(), value.to_string());
+			}
+		}
+	}
+
+	let body = &rest[end + 4..];
+	(fields, body.trim_start_matches(['\r', '\n']))
+}
+
+fn is_language_code(component: &str) -> bool {
+	component.len() == 2 && component.chars().all(|c| c.is_ascii_lowercase())
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+	DateTime::parse_from_rfc3339(value).map(|date| date.with_timezone(&Utc)).ok().or_else(|| {
+		NaiveDate::parse_from_str(value, "%Y-%m-%d")
+			.ok()
+			.and_then(|date| date.and_hms_opt(0, 0, 0))
+			.map(|date| Utc.from_utc_datetime(&date))
+	})
+}
@@ -1,75 +1,248 @@
-use std::path::PathBuf;
+pub mod checkpoint;
+pub mod chunker;
+pub mod links;
+pub mod loader;
+pub mod metadata;
+pub mod progress;
+pub mod report;
+pub mod rules;
+#[cfg(feature = "embed")]
+pub mod watch;
+
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::thread::available_parallelism;
 
 use anyhow::Error;
-use async_recursion::async_recursion;
+use futures::future;
 use futures::stream::BoxStream;
 use futures::stream::{self, StreamExt};
-use log::{debug, info};
 use tokio::fs;
 use tokio::time::Duration;
 
+use self::chunker::chunk_markdown;
+use self::loader::{load_document, LoadedDocument};
+use self::metadata::DocumentMetadata;
+use self::progress::IndexProgress;
+use self::report::{FailureCategory, FileFailure};
+use self::rules::{IndexRules, ListedFile};
+use crate::constants::FILE_READ_CONCURRENCY;
 use crate::{
-	embeddings::{Embeddings, EmbeddingsModel},
+	embeddings::{mean_embeddings, Embeddings, EmbeddingsModel},
 	prelude::*
 };
 
 #[derive(Debug, Clone)]
 pub struct FileEmbeddings {
 	pub path: String,
-	pub embeddings: Embeddings
+	pub embeddings: Embeddings,
+	pub metadata: DocumentMetadata
 }
 
-async fn list_files_recursively(dir: PathBuf) -> Result<Vec<PathBuf>> {
-	#[async_recursion(?Send)]
-	async fn helper(dir: PathBuf, files: &mut Vec<PathBuf>) -> Result<()> {
-		info!("Processing: {}", dir.display());
-		let mut entries = fs::read_dir(dir).await?;
-		while let Some(res) = entries.next_entry().await? {
-			let path = res.path();
-			if path.is_dir() {
-				helper(path.clone(), files).await?;
-			} else {
-				debug!("File: {}", path.display());
-				files.push(path.clone());
-			}
+/// Why a file or directory of the documents directory was not indexed
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+	/// Its name starts with a dot
+	Hidden,
+	/// Matched by a rule of an ignore file, the rule and the file
+	Ignored(String),
+	/// Matched by this glob of `documents.exclude`
+	Excluded(String),
+	/// Matched by no glob of `documents.include`
+	NotIncluded,
+	/// Its size in bytes is over `documents.max_file_size_bytes`
+	TooLarge(u64),
+	/// No loader reads files with this extension
+	UnsupportedType(String),
+	/// The file was read but holds no text, e.g. a scanned PDF
+	NoText,
+	/// Listed in the checkpoint of the run being resumed
+	AlreadyIndexed,
+	/// Its metadata or, for a directory, its entries could not be read, e.g. a dangling symbolic link
+	Unreadable(String)
+}
+
+impl std::fmt::Display for SkipReason {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SkipReason::Hidden => write!(f, "hidden"),
+			SkipReason::Ignored(rule) => write!(f, "ignored by {}", rule),
+			SkipReason::Excluded(glob) => write!(f, "excluded by {}", glob),
+			SkipReason::NotIncluded => write!(f, "not matched by any include glob"),
+			SkipReason::TooLarge(size) => write!(f, "too large, {} bytes", size),
+			SkipReason::UnsupportedType(extension) if extension.is_empty() => write!(f, "unsupported file type, no extension"),
+			SkipReason::UnsupportedType(extension) => write!(f, "unsupported file type .{}", extension),
+			SkipReason::NoText => write!(f, "no text found"),
+			SkipReason::AlreadyIndexed => write!(f, "already indexed"),
+			SkipReason::Unreadable(error) => write!(f, "unreadable, {}", error)
 		}
-		Ok(())
 	}
+}
 
-	let mut files = Vec::new();
-	helper(dir, &mut files).await?;
-	Ok(files)
+/// What became of one file of the documents directory
+#[derive(Debug, Clone)]
+pub enum EmbedOutcome {
+	Embedded(FileEmbeddings),
+	Skipped { path: String, reason: SkipReason },
+	Failed(FileFailure)
 }
 
-pub async fn embed_path<M: EmbeddingsModel + Send + Sync + 'static>(model: Arc<M>, path: PathBuf) -> BoxStream<'static, Result<FileEmbeddings>> {
-	let files = match list_files_recursively(path).await {
-		Ok(f) => f,
-		Err(e) => return stream::once(futures::future::ready(Err(e))).boxed()
-	};
+impl EmbedOutcome {
+	fn skipped(path: &Path, reason: SkipReason) -> Self {
+		EmbedOutcome::Skipped {
+			path: path.to_string_lossy().to_string(),
+			reason
+		}
+	}
+}
 
-	let file_embeddings_stream = stream::iter(files.into_iter())
-		.then(move |path| {
-			let model_clone: Arc<M> = Arc::clone(&model);
-			async move {
-				let file_content = fetch_file_content(path.clone()).await?;
-				let embeddings = model_clone.embed(&file_content)?;
-				log::info!("Embeddings for {} calculated", path.display());
-				Ok(FileEmbeddings {
-					path: path.to_str().unwrap().to_string(),
-					embeddings
-				})
+/// Embeds the files of the directory selected by `rules` that their loader can read, the other files are reported as
+/// skipped. Files are read `FILE_READ_CONCURRENCY` at a time and embedded on the blocking pool, one file per core, so
+/// the outcomes come in no particular order. Files that cannot be read or embedded are reported as failed, errors end the
+/// stream only when the directory cannot be walked.
+pub fn embed_path<M: EmbeddingsModel + Send + Sync + 'static>(
+	model: Arc<M>,
+	path: PathBuf,
+	rules: &IndexRules,
+	progress: Arc<IndexProgress>
+) -> BoxStream<'static, Result<EmbedOutcome>> {
+	let walk_progress = Arc::clone(&progress);
+	let walk_done = Arc::clone(&progress);
+	let files = rules
+		.walk(&path)
+		.inspect(move |file| {
+			if file.is_ok() {
+				walk_progress.found();
 			}
 		})
-		.boxed();
+		.chain(stream::once(async move { walk_done.walk_finished() }).filter_map(|_| future::ready(None)));
+
+	embed_files(model, path, files.boxed(), progress)
+}
+
+/// Embeds `files`, listed from the documents directory `base_path`, through the same stages as `embed_path`
+pub fn embed_files<M: EmbeddingsModel + Send + Sync + 'static>(
+	model: Arc<M>,
+	base_path: PathBuf,
+	files: BoxStream<'static, Result<ListedFile>>,
+	progress: Arc<IndexProgress>
+) -> BoxStream<'static, Result<EmbedOutcome>> {
+	let read_progress = Arc::clone(&progress);
+	let workers = available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+	files
+		.map(move |file| read_stage(file, base_path.clone(), Arc::clone(&read_progress)))
+		.buffer_unordered(FILE_READ_CONCURRENCY)
+		.map(move |file| embed_stage(file, Arc::clone(&model), Arc::clone(&progress)))
+		.buffer_unordered(workers)
+		.boxed()
+}
+
+// A file between the read and the embedding stages
+enum ReadFile {
+	Loaded {
+		path: PathBuf,
+		content: String,
+		metadata: DocumentMetadata
+	},
+	Done(EmbedOutcome)
+}
+
+async fn read_stage(file: Result<ListedFile>, base_path: PathBuf, progress: Arc<IndexProgress>) -> Result<ReadFile> {
+	let path = match file? {
+		ListedFile::Included(path) => path,
+		ListedFile::Skipped(path, reason) => {
+			progress.skipped();
+			return Ok(ReadFile::Done(EmbedOutcome::skipped(&path, reason)));
+		}
+	};
 
-	file_embeddings_stream
+	progress.read.enter();
+	let loaded = load_document(&path).await;
+	progress.read.leave(1);
+	let content = match loaded {
+		Ok(LoadedDocument::Markdown(content)) => content,
+		Ok(LoadedDocument::Skipped(reason)) => {
+			log::info!("Skipping {}: {}", path.display(), reason);
+			progress.skipped();
+			return Ok(ReadFile::Done(EmbedOutcome::skipped(&path, reason)));
+		}
+		Err(e) => {
+			log::error!("Failed to read {}: {:#}", path.display(), e);
+			progress.failed();
+			let failure = FileFailure::new(&path, FailureCategory::of_read(&e), &e);
+			return Ok(ReadFile::Done(EmbedOutcome::Failed(failure)));
+		}
+	};
+
+	let modified = fs::metadata(&path).await.and_then(|metadata| metadata.modified()).ok();
+	let metadata = DocumentMetadata::extract(&path, &base_path, &content, modified);
+	progress.embed.enter();
+	Ok(ReadFile::Loaded { path, content, metadata })
+}
+
+async fn embed_stage<M: EmbeddingsModel + Send + Sync + 'static>(
+	file: Result<ReadFile>,
+	model: Arc<M>,
+	progress: Arc<IndexProgress>
+) -> Result<EmbedOutcome> {
+	let (path, content, metadata) = match file? {
+		ReadFile::Loaded { path, content, metadata } => (path, content, metadata),
+		ReadFile::Done(outcome) => return Ok(outcome)
+	};
+
+	// The model is CPU bound, running it on the async workers would stall the reads
+	let embeddings = tokio::task::spawn_blocking(move || embed_document(model.as_ref(), &content))
+		.await
+		.map_err(Error::from)
+		.and_then(|embeddings| embeddings);
+	progress.embed.leave(1);
+	let embeddings = match embeddings {
+		Ok(embeddings) => embeddings,
+		Err(e) => {
+			log::error!("Failed to embed {}: {:#}", path.display(), e);
+			progress.failed();
+			return Ok(EmbedOutcome::Failed(FileFailure::new(&path, FailureCategory::of_embedding(&e), &e)));
+		}
+	};
+
+	log::info!("Embeddings for {} calculated", path.display());
+	progress.upsert.enter();
+	Ok(EmbedOutcome::Embedded(FileEmbeddings {
+		// Lossy like the paths verify lists, so that both name a non-UTF-8 file the same way
+		path: path.to_string_lossy().to_string(),
+		embeddings,
+		metadata
+	}))
+}
+
+/// The mean of the embeddings of the chunks `search_file` compares queries with, so that the whole document counts
+/// rather than the part fitting in the model input. It is the only vector stored for the document, the chunks have no
+/// point of their own.
+pub fn embed_document<M: EmbeddingsModel>(model: &M, content: &str) -> Result<Embeddings> {
+	let chunk_embeddings = chunk_markdown(content)
+		.iter()
+		.map(|chunk| model.embed(&chunk.to_string()))
+		.collect::<Result<Vec<_>>>()?;
+
+	match mean_embeddings(&chunk_embeddings) {
+		Some(embeddings) => Ok(embeddings),
+		None => model.embed(content)
+	}
+}
+
+/// The content of a document as Markdown, whatever its file type
+pub async fn fetch_document(path: &Path) -> Result<String> {
+	match load_document(path).await? {
+		LoadedDocument::Markdown(content) => Ok(content),
+		LoadedDocument::Skipped(reason) => Err(anyhow::anyhow!("Cannot read {}: {}", path.display(), reason))
+	}
 }
 
-pub async fn fetch_file_content(path: PathBuf) -> Result<String> {
+async fn read_file(path: PathBuf) -> Result<Vec<u8>> {
 	let timeout = Duration::from_secs(60); // Adjust the timeout as needed.
 	let result = tokio::time::timeout(timeout, async {
-		let file_content = fs::read_to_string(path).await?;
+		let file_content = fs::read(path).await?;
 		anyhow::Ok(file_content)
 	})
 	.await;
@@ -77,6 +250,6 @@ pub async fn fetch_file_content(path: PathBuf) -> Result<String> {
 	match result {
 		Ok(Ok(content)) => Ok(content),
 		Ok(Err(err)) => Err(err),
-		Err(_) => Err(Error::msg("File content fetching timed out."))
+		Err(elapsed) => Err(Error::new(elapsed).context("File content fetching timed out."))
 	}
 }
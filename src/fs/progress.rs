@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+
+/// Files that entered a stage of the indexing pipeline and files that left it, the difference is its queue
+#[derive(Debug, Default)]
+pub struct Stage {
+	entered: AtomicUsize,
+	left: AtomicUsize
+}
+
+impl Stage {
+	pub fn enter(&self) {
+		self.entered.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn leave(&self, count: usize) {
+		self.left.fetch_add(count, Ordering::Relaxed);
+	}
+
+	pub fn queued(&self) -> usize {
+		self.entered.load(Ordering::Relaxed).saturating_sub(self.left.load(Ordering::Relaxed))
+	}
+}
+
+/// Counters of one indexing run, shared by the stages of `embed_path` and the upserts of the caller
+#[derive(Debug)]
+pub struct IndexProgress {
+	started: Instant,
+	walking: AtomicBool,
+	/// Files found by the walk that will be read
+	found: AtomicUsize,
+	skipped: AtomicUsize,
+	failed: AtomicUsize,
+	pub read: Stage,
+	pub embed: Stage,
+	pub upsert: Stage
+}
+
+impl Default for IndexProgress {
+	fn default() -> Self {
+		Self {
+			started: Instant::now(),
+			walking: AtomicBool::new(true),
+			found: AtomicUsize::new(0),
+			skipped: AtomicUsize::new(0),
+			failed: AtomicUsize::new(0),
+			read: Stage::default(),
+			embed: Stage::default(),
+			upsert: Stage::default()
+		}
+	}
+}
+
+impl IndexProgress {
+	pub fn found(&self) {
+		self.found.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn walk_finished(&self) {
+		self.walking.store(false, Ordering::Relaxed);
+	}
+
+	pub fn skipped(&self) {
+		self.skipped.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn failed(&self) {
+		self.failed.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn failed_count(&self) -> usize {
+		self.failed.load(Ordering::Relaxed)
+	}
+
+	/// Files stored in the database
+	pub fn indexed(&self) -> usize {
+		self.upsert.left.load(Ordering::Relaxed)
+	}
+
+	/// Logs the progress every `interval` until the returned task is aborted
+	pub fn report_every(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+		let progress = Arc::clone(self);
+		tokio::spawn(async move {
+			let mut ticks = tokio::time::interval(interval);
+			ticks.tick().await;
+			loop {
+				ticks.tick().await;
+				log::info!("{}", progress.report());
+			}
+		})
+	}
+
+	pub fn report(&self) -> String {
+		let elapsed = self.started.elapsed().as_secs_f64();
+		let found = self.found.load(Ordering::Relaxed);
+		let indexed = self.indexed();
+		let failed = self.failed_count();
+		let rate = if elapsed > 0.0 { indexed as f64 / elapsed } else { 0.0 };
+
+		// Only a lower bound while files are still being found
+		let remaining = found.saturating_sub(indexed + failed);
+		let eta = if rate > 0.0 { format_duration(Duration::from_secs_f64(remaining as f64 / rate)) } else { "unknown".to_string() };
+		let walking = if self.walking.load(Ordering::Relaxed) { ", still walking" } else { "" };
+
+		format!(
+			"Indexed {}/{} files ({} skipped, {} failed{}), {:.1} files/s, ETA {}, queues: read {}, embed {}, upsert {}",
+			indexed,
+			found,
+			self.skipped.load(Ordering::Relaxed),
+			failed,
+			walking,
+			rate,
+			eta,
+			self.read.queued(),
+			self.embed.queued(),
+			self.upsert.queued()
+		)
+	}
+}
+
+fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	match secs {
+		0..=59 => format!("{}s", secs),
+		60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+		_ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+	}
+}
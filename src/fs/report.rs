@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::time::error::Elapsed;
+
+use crate::embeddings::TokenizerError;
+use crate::prelude::*;
+
+/// The stage a file failed at, and what failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+	ReadTimeout,
+	/// Any other I/O error while reading the file
+	Read,
+	/// The loader could not convert the file, e.g. invalid UTF-8 or a malformed PDF
+	Decode,
+	Tokenizer,
+	Onnx,
+	Upsert
+}
+
+impl FailureCategory {
+	/// The category of an error of `loader::load_document`
+	pub fn of_read(error: &Error) -> Self {
+		if error.downcast_ref::<Elapsed>().is_some() {
+			FailureCategory::ReadTimeout
+		} else if error.downcast_ref::<std::io::Error>().is_some() {
+			FailureCategory::Read
+		} else {
+			FailureCategory::Decode
+		}
+	}
+
+	/// The category of an error of `embed_document`
+	pub fn of_embedding(error: &Error) -> Self {
+		if error.downcast_ref::<TokenizerError>().is_some() {
+			FailureCategory::Tokenizer
+		} else {
+			FailureCategory::Onnx
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileFailure {
+	pub path: String,
+	pub category: FailureCategory,
+	pub error: String
+}
+
+impl FileFailure {
+	pub fn new(path: &Path, category: FailureCategory, error: &Error) -> Self {
+		Self {
+			path: path.to_string_lossy().to_string(),
+			category,
+			error: format!("{:#}", error)
+		}
+	}
+}
+
+/// The outcome of indexing one profile
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProfileReport {
+	pub profile: String,
+	pub collection: String,
+	pub indexed: usize,
+	pub skipped: usize,
+	pub failed: usize,
+	/// Share of the files read that failed, skipped files are not counted
+	pub failed_percent: f64,
+	pub failed_by_category: BTreeMap<FailureCategory, usize>,
+	pub failures: Vec<FileFailure>,
+	/// Why indexing stopped early or the collection did not verify
+	pub error: Option<String>
+}
+
+impl ProfileReport {
+	pub fn new(profile: &str, collection: &str, indexed: usize, skipped: usize, failures: Vec<FileFailure>) -> Self {
+		let mut failed_by_category = BTreeMap::new();
+		for failure in &failures {
+			*failed_by_category.entry(failure.category).or_insert(0) += 1;
+		}
+		let failed = failures.len();
+		let attempted = indexed + failed;
+
+		Self {
+			profile: profile.to_string(),
+			collection: collection.to_string(),
+			indexed,
+			skipped,
+			failed,
+			failed_percent: if attempted == 0 { 0.0 } else { failed as f64 * 100.0 / attempted as f64 },
+			failed_by_category,
+			failures,
+			error: None
+		}
+	}
+
+	/// A profile whose indexing stopped before any count was known
+	pub fn aborted(profile: &str, collection: &str, error: &Error) -> Self {
+		Self {
+			profile: profile.to_string(),
+			collection: collection.to_string(),
+			error: Some(format!("{:#}", error)),
+			..Default::default()
+		}
+	}
+}
+
+/// Failures tolerated before the embed job counts as failed
+#[derive(Debug, Clone)]
+pub struct FailureThresholds {
+	pub max_failed_percent: f64,
+	pub max_failed_files: Option<usize>
+}
+
+/// Written as JSON at the end of every embed run
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbedReport {
+	pub finished_at: DateTime<Utc>,
+	/// False when a profile stopped early, did not verify or went over a threshold
+	pub passed: bool,
+	/// Why the run did not pass
+	pub violations: Vec<String>,
+	pub profiles: Vec<ProfileReport>
+}
+
+impl EmbedReport {
+	pub fn new(profiles: Vec<ProfileReport>, thresholds: &FailureThresholds) -> Self {
+		let mut violations = Vec::new();
+		for profile in &profiles {
+			if let Some(error) = &profile.error {
+				violations.push(format!("profile {}: {}", profile.profile, error));
+			}
+			if profile.failed_percent > thresholds.max_failed_percent {
+				violations.push(format!(
+					"profile {}: {:.1}% of the files failed, above the maximum of {}%",
+					profile.profile, profile.failed_percent, thresholds.max_failed_percent
+				));
+			}
+			if let Some(max_failed_files) = thresholds.max_failed_files.filter(|max| profile.failed > *max) {
+				violations.push(format!(
+					"profile {}: {} files failed, above the maximum of {}",
+					profile.profile, profile.failed, max_failed_files
+				));
+			}
+		}
+
+		Self {
+			finished_at: Utc::now(),
+			passed: violations.is_empty(),
+			violations,
+			profiles
+		}
+	}
+
+	pub async fn write(&self, path: &Path) -> Result<()> {
+		if let Some(dir) = path.parent() {
+			tokio::fs::create_dir_all(dir).await?;
+		}
+		tokio::fs::write(path, serde_json::to_string_pretty(self)?).await?;
+		Ok(())
+	}
+}
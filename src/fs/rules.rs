@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::stream::{self, BoxStream, StreamExt};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, info, warn};
+use tokio::fs;
+use tokio::sync::mpsc;
+
+use super::loader::loader_for;
+use super::SkipReason;
+use crate::config::DocumentsConfig;
+use crate::constants::WALK_QUEUE_SIZE;
+use crate::prelude::*;
+
+/// Decides which files of a documents directory are indexed
+#[derive(Debug, Clone)]
+pub struct IndexRules {
+	include: Option<Patterns>,
+	exclude: Patterns,
+	ignore_files: Vec<String>,
+	max_file_size_bytes: u64,
+	completed: Arc<HashSet<String>>
+}
+
+#[derive(Debug, Clone)]
+struct Patterns {
+	globs: Vec<String>,
+	set: GlobSet
+}
+
+impl Patterns {
+	fn new(globs: &[String]) -> Result<Self> {
+		let mut builder = GlobSetBuilder::new();
+		for glob in globs {
+			// As in ignore files, `*` does not cross directories while `**` does
+			builder.add(GlobBuilder::new(glob).literal_separator(true).build()?);
+		}
+		Ok(Self {
+			globs: globs.to_vec(),
+			set: builder.build()?
+		})
+	}
+
+	// The first glob matching `path`
+	fn matching(&self, path: &Path) -> Option<&str> {
+		self.set.matches(path).first().map(|index| self.globs[*index].as_str())
+	}
+}
+
+/// A file or directory found in the documents directory. Skipped directories are listed once, without their content.
+#[derive(Debug, Clone)]
+pub enum ListedFile {
+	Included(PathBuf),
+	Skipped(PathBuf, SkipReason)
+}
+
+impl IndexRules {
+	pub fn from_config(config: &DocumentsConfig) -> Result<Self> {
+		Ok(Self {
+			include: if config.include.is_empty() { None } else { Some(Patterns::new(&config.include)?) },
+			exclude: Patterns::new(&config.exclude)?,
+			ignore_files: config.ignore_files.clone(),
+			max_file_size_bytes: config.max_file_size_bytes,
+			completed: Arc::default()
+		})
+	}
+
+	/// The same rules, skipping the files an interrupted run already indexed
+	pub fn skip_completed(&self, completed: HashSet<String>) -> Self {
+		Self {
+			completed: Arc::new(completed),
+			..self.clone()
+		}
+	}
+
+	/// Every file and directory under `dir` as it is walked, with the reason the skipped ones are not indexed. Hidden
+	/// files and directories are always skipped. The walk runs ahead of the consumer by at most `WALK_QUEUE_SIZE` files.
+	pub fn walk(&self, dir: &Path) -> BoxStream<'static, Result<ListedFile>> {
+		self.walk_dir(dir, dir)
+	}
+
+	/// Walks `dir`, a directory below the documents directory `base_path`, as `walk` would
+	pub fn walk_dir(&self, base_path: &Path, dir: &Path) -> BoxStream<'static, Result<ListedFile>> {
+		let (sender, receiver) = mpsc::channel(WALK_QUEUE_SIZE);
+		let rules = self.clone();
+		let base_path = base_path.to_path_buf();
+		let dir = dir.to_path_buf();
+		tokio::spawn(async move {
+			if let Err(e) = rules.walk_into(&base_path, dir, &sender).await {
+				let _ = sender.send(Err(e)).await;
+			}
+		});
+
+		stream::unfold(receiver, |mut receiver| async move { receiver.recv().await.map(|file| (file, receiver)) }).boxed()
+	}
+
+	/// Whether `path` is one of the ignore files, whose changes decide what is indexed under its directory
+	pub fn is_ignore_file(&self, path: &Path) -> bool {
+		path.file_name().is_some_and(|name| self.ignore_files.iter().any(|ignore_file| name == ignore_file.as_str()))
+	}
+
+	/// Why the file or directory at `path`, below the documents directory `base_path`, is not indexed, `None` if it is.
+	/// The directories above it are checked as well.
+	pub async fn check(&self, base_path: &Path, path: &Path) -> Result<Option<SkipReason>> {
+		let metadata = fs::metadata(path).await?;
+		let components: Vec<_> = path.strip_prefix(base_path)?.components().collect();
+		let mut ignores = Vec::new();
+		let mut current = base_path.to_path_buf();
+
+		for (i, component) in components.iter().enumerate() {
+			ignores.extend(self.ignore_matcher(&current)?.map(Arc::new));
+			current.push(component);
+			let (is_dir, size) = if i + 1 == components.len() { (metadata.is_dir(), metadata.len()) } else { (true, 0) };
+			if let Some(reason) = self.skip_reason(&current, base_path, is_dir, size, &ignores) {
+				return Ok(Some(reason));
+			}
+		}
+		Ok(None)
+	}
+
+	async fn walk_into(&self, base_path: &Path, dir: PathBuf, sender: &mpsc::Sender<Result<ListedFile>>) -> Result<()> {
+		// The ignore files of the directories above `dir`
+		let mut ignores = Vec::new();
+		let mut current = base_path.to_path_buf();
+		for component in dir.strip_prefix(base_path)?.components() {
+			ignores.extend(self.ignore_matcher(&current)?.map(Arc::new));
+			current.push(component);
+		}
+
+		// Directories left to walk, with the ignore files of the directories above them
+		let start = dir.clone();
+		let mut pending: Vec<(PathBuf, Vec<Arc<Gitignore>>)> = vec![(dir, ignores)];
+
+		while let Some((dir, mut ignores)) = pending.pop() {
+			info!("Processing: {}", dir.display());
+			ignores.extend(self.ignore_matcher(&dir)?.map(Arc::new));
+
+			// A directory or an entry that cannot be read is reported as skipped and the rest of the walk goes on, unless
+			// nothing can be walked at all
+			let mut entries = match fs::read_dir(&dir).await {
+				Ok(entries) => entries,
+				Err(e) if dir == start => return Err(anyhow::Error::new(e).context(format!("Failed to read {}", dir.display()))),
+				Err(e) => {
+					warn!("Skipping {}: {}", dir.display(), e);
+					if sender.send(Ok(ListedFile::Skipped(dir, SkipReason::Unreadable(e.to_string())))).await.is_err() {
+						return Ok(());
+					}
+					continue;
+				}
+			};
+			loop {
+				let entry = match entries.next_entry().await {
+					Ok(Some(entry)) => entry,
+					Ok(None) => break,
+					Err(e) => {
+						warn!("Stopped listing {}: {}", dir.display(), e);
+						if sender.send(Ok(ListedFile::Skipped(dir.clone(), SkipReason::Unreadable(e.to_string())))).await.is_err() {
+							return Ok(());
+						}
+						break;
+					}
+				};
+				let path = entry.path();
+				// Follows symbolic links, as the files are read through them
+				let metadata = match fs::metadata(&path).await {
+					Ok(metadata) => metadata,
+					Err(e) => {
+						warn!("Skipping {}: {}", path.display(), e);
+						if sender.send(Ok(ListedFile::Skipped(path, SkipReason::Unreadable(e.to_string())))).await.is_err() {
+							return Ok(());
+						}
+						continue;
+					}
+				};
+				let file = match self.skip_reason(&path, base_path, metadata.is_dir(), metadata.len(), &ignores) {
+					Some(reason) => {
+						debug!("Skipping {}: {}", path.display(), reason);
+						ListedFile::Skipped(path, reason)
+					}
+					None if metadata.is_dir() => {
+						pending.push((path, ignores.clone()));
+						continue;
+					}
+					None => {
+						debug!("File: {}", path.display());
+						ListedFile::Included(path)
+					}
+				};
+
+				// The consumer stopped
+				if sender.send(Ok(file)).await.is_err() {
+					return Ok(());
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn skip_reason(&self, path: &Path, base_path: &Path, is_dir: bool, size: u64, ignores: &[Arc<Gitignore>]) -> Option<SkipReason> {
+		let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+		if name.starts_with('.') {
+			return Some(SkipReason::Hidden);
+		}
+
+		// The ignore file of the closest directory decides, so that it can re-include what a parent ignored
+		let ignored = ignores.iter().rev().map(|ignore| ignore.matched(path, is_dir)).find(|matched| !matched.is_none());
+		if let Some(Match::Ignore(glob)) = ignored {
+			let file = glob.from().map(|file| file.display().to_string()).unwrap_or_default();
+			return Some(SkipReason::Ignored(format!("{} in {}", glob.original(), file)));
+		}
+
+		let relative = path.strip_prefix(base_path).unwrap_or(path);
+		if let Some(glob) = self.exclude.matching(relative) {
+			return Some(SkipReason::Excluded(glob.to_string()));
+		}
+		if is_dir {
+			return None;
+		}
+
+		if self.include.as_ref().is_some_and(|include| include.matching(relative).is_none()) {
+			return Some(SkipReason::NotIncluded);
+		}
+		if size > self.max_file_size_bytes {
+			return Some(SkipReason::TooLarge(size));
+		}
+		if loader_for(path).is_none() {
+			let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+			return Some(SkipReason::UnsupportedType(extension));
+		}
+		if self.completed.contains(path.to_string_lossy().as_ref()) {
+			return Some(SkipReason::AlreadyIndexed);
+		}
+		None
+	}
+
+	// The rules of the ignore files of `dir`, if it has any
+	fn ignore_matcher(&self, dir: &Path) -> Result<Option<Gitignore>> {
+		let mut builder = GitignoreBuilder::new(dir);
+		let mut found = false;
+		for name in &self.ignore_files {
+			let file = dir.join(name);
+			if file.is_file() {
+				if let Some(e) = builder.add(&file) {
+					return Err(anyhow::anyhow!("Invalid ignore file {}: {}", file.display(), e));
+				}
+				found = true;
+			}
+		}
+		Ok(if found { Some(builder.build()?) } else { None })
+	}
+}
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use futures::stream::StreamExt;
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use tokio::fs;
+use tokio::sync::mpsc;
+
+use super::rules::{IndexRules, ListedFile};
+use crate::prelude::*;
+
+/// Documents to embed again and documents to remove from the collection
+#[derive(Debug, Default)]
+pub struct Changes {
+	pub upsert: Vec<PathBuf>,
+	pub delete: Vec<String>
+}
+
+impl Changes {
+	pub fn is_empty(&self) -> bool {
+		self.upsert.is_empty() && self.delete.is_empty()
+	}
+}
+
+/// Turns the file events of a documents directory into the changes its collection needs. Events are debounced, so a
+/// file written in several steps, or a directory rewritten by the scraper, is indexed once it settles.
+pub struct DocumentsWatcher {
+	// Watching stops when it is dropped
+	_debouncer: Debouncer<RecommendedWatcher>,
+	events: mpsc::UnboundedReceiver<Vec<PathBuf>>,
+	base_path: PathBuf,
+	rules: IndexRules
+}
+
+impl DocumentsWatcher {
+	/// Starts watching `base_path`, the events are kept until `next_changes` reads them
+	pub fn new(base_path: &Path, rules: IndexRules, debounce: Duration) -> Result<Self> {
+		let (sender, events) = mpsc::unbounded_channel();
+		let mut debouncer = new_debouncer(debounce, move |result: DebounceEventResult| match result {
+			Ok(events) => {
+				let _ = sender.send(events.into_iter().map(|event| event.path).collect());
+			}
+			Err(e) => log::error!("Watching the documents failed: {}", e)
+		})?;
+		debouncer.watcher().watch(base_path, RecursiveMode::Recursive)?;
+
+		Ok(Self {
+			_debouncer: debouncer,
+			events,
+			base_path: base_path.to_path_buf(),
+			rules
+		})
+	}
+
+	/// The changes made while nothing watched, given the documents in the collection: files it lacks or modified after
+	/// `since`, and documents no longer indexed
+	pub async fn catch_up(&self, known: &HashSet<String>, since: Option<SystemTime>) -> Result<Changes> {
+		let mut changes = Changes::default();
+		self.reconcile(&self.base_path, known, since, &mut changes).await?;
+		Ok(changes)
+	}
+
+	/// Waits for the next debounced events and returns the changes they make, `None` once the watcher stopped
+	pub async fn next_changes(&mut self, known: &HashSet<String>) -> Result<Option<Changes>> {
+		let Some(mut paths) = self.events.recv().await else {
+			return Ok(None);
+		};
+		// Events that came in while the previous changes were applied
+		while let Ok(more) = self.events.try_recv() {
+			paths.extend(more);
+		}
+		paths.sort_unstable();
+		paths.dedup();
+
+		let mut changes = Changes::default();
+		for path in paths {
+			self.changes_at(&path, known, &mut changes).await?;
+		}
+
+		changes.upsert.sort_unstable();
+		changes.upsert.dedup();
+		// A document indexed again replaces its point
+		let upserted: HashSet<String> = changes.upsert.iter().map(|path| path.to_string_lossy().to_string()).collect();
+		changes.delete.sort_unstable();
+		changes.delete.dedup();
+		changes.delete.retain(|path| !upserted.contains(path));
+		Ok(Some(changes))
+	}
+
+	async fn changes_at(&self, path: &Path, known: &HashSet<String>, changes: &mut Changes) -> Result<()> {
+		if !path.starts_with(&self.base_path) {
+			return Ok(());
+		}
+		// Renames are reported as events on both paths, the old one is gone
+		if !path.exists() {
+			changes.delete.extend(known_under(known, path));
+			return Ok(());
+		}
+		match self.changes_of_existing(path, known, changes).await {
+			// Removed since the check above, such as an editor temporary file or a directory the scraper rewrites
+			Err(e) if is_not_found(&e) => {
+				log::debug!("{} disappeared while looking at it: {:#}", path.display(), e);
+				changes.delete.extend(known_under(known, path));
+				Ok(())
+			}
+			result => result
+		}
+	}
+
+	async fn changes_of_existing(&self, path: &Path, known: &HashSet<String>, changes: &mut Changes) -> Result<()> {
+		if self.rules.is_ignore_file(path) {
+			let dir = path.parent().unwrap_or(&self.base_path);
+			return self.reconcile(dir, known, None, changes).await;
+		}
+
+		let is_dir = path.is_dir();
+		match self.rules.check(&self.base_path, path).await? {
+			Some(reason) => {
+				let skipped = known_under(known, path);
+				if !skipped.is_empty() {
+					log::info!("Removing {} documents under {}: {}", skipped.len(), path.display(), reason);
+					changes.delete.extend(skipped);
+				}
+			}
+			// Files created with the directory may have no event of their own
+			None if is_dir => self.reconcile(path, known, None, changes).await?,
+			None => changes.upsert.push(path.to_path_buf())
+		}
+		Ok(())
+	}
+
+	// Upserts the included files under `dir` the collection lacks or that were modified after `since`, and deletes the
+	// documents under it that are no longer included
+	async fn reconcile(&self, dir: &Path, known: &HashSet<String>, since: Option<SystemTime>, changes: &mut Changes) -> Result<()> {
+		if self.rules.check(&self.base_path, dir).await?.is_some() {
+			changes.delete.extend(known_under(known, dir));
+			return Ok(());
+		}
+
+		let mut included = HashSet::new();
+		let mut files = self.rules.walk_dir(&self.base_path, dir);
+		while let Some(file) = files.next().await {
+			let ListedFile::Included(path) = file? else {
+				continue;
+			};
+			let key = path.to_string_lossy().to_string();
+			if !known.contains(&key) || modified_since(&path, since).await {
+				changes.upsert.push(path);
+			}
+			included.insert(key);
+		}
+
+		changes.delete.extend(known_under(known, dir).into_iter().filter(|path| !included.contains(path)));
+		Ok(())
+	}
+}
+
+fn is_not_found(error: &anyhow::Error) -> bool {
+	error
+		.chain()
+		.any(|cause| cause.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound))
+}
+
+// The known documents at `path` or below it
+fn known_under(known: &HashSet<String>, path: &Path) -> Vec<String> {
+	known.iter().filter(|known| Path::new(known).starts_with(path)).cloned().collect()
+}
+
+async fn modified_since(path: &Path, since: Option<SystemTime>) -> bool {
+	let Some(since) = since else {
+		return false;
+	};
+	match fs::metadata(path).await.and_then(|metadata| metadata.modified()) {
+		Ok(modified) => modified > since,
+		Err(_) => true
+	}
+}
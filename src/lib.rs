@@ -1,13 +1,26 @@
+pub mod config;
 pub mod constants;
 #[cfg(feature = "oracle")]
 pub mod convrsation;
+#[cfg(feature = "embed")]
+pub mod crawl;
 #[cfg(any(feature = "oracle", feature = "embed"))]
 pub mod db;
 #[cfg(any(feature = "oracle", feature = "embed"))]
 pub mod embeddings;
+#[cfg(feature = "oracle")]
+pub mod eval;
+#[cfg(feature = "oracle")]
+pub mod feedback;
 #[cfg(any(feature = "oracle", feature = "embed"))]
 pub mod fs;
+#[cfg(feature = "oracle")]
+pub mod llm;
+#[cfg(feature = "oracle")]
+pub mod metrics;
 pub mod prelude;
 #[cfg(feature = "oracle")]
 pub mod routes;
+#[cfg(feature = "oracle")]
+pub mod telemetry;
 pub mod utils;
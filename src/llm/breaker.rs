@@ -0,0 +1,122 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+	/// Requests go through
+	Closed,
+	/// Requests fail fast until the reset timeout has elapsed
+	Open,
+	/// A single trial request is let through to probe whether the service recovered
+	HalfOpen
+}
+
+struct Inner {
+	consecutive_failures: u32,
+	opened_at: Option<Instant>,
+	trial_in_flight: bool
+}
+
+/// Stops calling a failing service after `failure_threshold` consecutive failures, and probes it again after
+/// `reset_timeout`
+pub struct CircuitBreaker {
+	failure_threshold: u32,
+	reset_timeout: Duration,
+	inner: Mutex<Inner>
+}
+
+impl CircuitBreaker {
+	pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+		Self {
+			failure_threshold,
+			reset_timeout,
+			inner: Mutex::new(Inner {
+				consecutive_failures: 0,
+				opened_at: None,
+				trial_in_flight: false
+			})
+		}
+	}
+
+	pub fn state(&self) -> BreakerState {
+		let inner = self.inner.lock().unwrap();
+		match inner.opened_at {
+			None => BreakerState::Closed,
+			Some(opened_at) if opened_at.elapsed() < self.reset_timeout => BreakerState::Open,
+			Some(_) => BreakerState::HalfOpen
+		}
+	}
+
+	/// A permit to send a request now, `None` while the breaker is open or its trial request is in flight
+	pub fn allow(&self) -> Option<Permit<'_>> {
+		let mut inner = self.inner.lock().unwrap();
+		let trial = match inner.opened_at {
+			None => false,
+			Some(opened_at) if opened_at.elapsed() < self.reset_timeout => return None,
+			Some(_) if inner.trial_in_flight => return None,
+			Some(_) => {
+				inner.trial_in_flight = true;
+				true
+			}
+		};
+		Some(Permit {
+			breaker: self,
+			trial,
+			recorded: false
+		})
+	}
+
+	fn record_success(&self) {
+		let mut inner = self.inner.lock().unwrap();
+		if inner.opened_at.is_some() {
+			log::info!("Circuit breaker closed, service recovered");
+		}
+		inner.consecutive_failures = 0;
+		inner.opened_at = None;
+		inner.trial_in_flight = false;
+	}
+
+	fn record_failure(&self) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.consecutive_failures += 1;
+		inner.trial_in_flight = false;
+		if inner.opened_at.is_some() || inner.consecutive_failures >= self.failure_threshold {
+			if inner.opened_at.is_none() {
+				log::warn!("Circuit breaker opened after {} consecutive failures", inner.consecutive_failures);
+			}
+			inner.opened_at = Some(Instant::now());
+		}
+	}
+}
+
+/// Lets one request through. Dropped without an outcome, e.g. when the caller stops waiting for the request, it frees
+/// the trial of a half-open breaker for the next request.
+pub struct Permit<'a> {
+	breaker: &'a CircuitBreaker,
+	trial: bool,
+	recorded: bool
+}
+
+impl Permit<'_> {
+	pub fn record_success(mut self) {
+		self.recorded = true;
+		self.breaker.record_success();
+	}
+
+	pub fn record_failure(mut self) {
+		self.recorded = true;
+		self.breaker.record_failure();
+	}
+}
+
+impl Drop for Permit<'_> {
+	fn drop(&mut self) {
+		if self.trial && !self.recorded {
+			self.breaker.inner.lock().unwrap().trial_in_flight = false;
+		}
+	}
+}
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, ChatCompletionResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs;
+
+use super::{BreakerState, ChatClient, OpenAIClient};
+use crate::convrsation::templates::prompt_date;
+use crate::prelude::*;
+use crate::utils::hash::sha256_hex;
+
+/// One request with the response the API gave to it, saved as `<key>.json` in the fixtures directory
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+	/// Normalised, as it was hashed
+	request: Value,
+	response: Value
+}
+
+/// The key of a request and its normalised form: the request as JSON with the keys of every object sorted, null fields
+/// and the `user` field removed, strings trimmed and the date the prompts render for `{{date}}` put back as the
+/// placeholder, so that changes in serialisation or template whitespace and the passing days do not invalidate the
+/// fixtures. The key is the SHA-256 digest of the normalised JSON.
+pub fn request_key(request: &ChatCompletionRequest) -> Result<(String, Value)> {
+	let mut request = serde_json::to_value(request)?;
+	if let Value::Object(fields) = &mut request {
+		fields.remove("user");
+	}
+	let request = normalize(request, &prompt_date());
+	Ok((sha256_hex(&request.to_string()), request))
+}
+
+fn normalize(value: Value, date: &str) -> Value {
+	match value {
+		Value::Object(fields) => {
+			let mut fields: Vec<(String, Value)> = fields.into_iter().filter(|(_, value)| !value.is_null()).collect();
+			fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+			Value::Object(fields.into_iter().map(|(key, value)| (key, normalize(value, date))).collect())
+		}
+		Value::Array(values) => Value::Array(values.into_iter().map(|value| normalize(value, date)).collect()),
+		Value::String(string) => Value::String(string.trim().replace(date, "{{date}}")),
+		value => value
+	}
+}
+
+fn fixture_path(dir: &Path, key: &str) -> PathBuf {
+	dir.join(format!("{}.json", key))
+}
+
+/// Sends the requests to the API and saves every exchange in the fixtures directory, replacing the previous recording of
+/// the same request
+pub struct RecordingClient {
+	inner: OpenAIClient,
+	path: PathBuf
+}
+
+impl RecordingClient {
+	pub fn new(inner: OpenAIClient, path: PathBuf) -> Self {
+		Self { inner, path }
+	}
+}
+
+#[async_trait]
+impl ChatClient for RecordingClient {
+	async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+		let (key, normalized) = request_key(&request)?;
+		let response = self.inner.chat_completion_json(&request).await?;
+
+		let fixture = Fixture {
+			request: normalized,
+			response
+		};
+		fs::create_dir_all(&self.path).await?;
+		let path = fixture_path(&self.path, &key);
+		fs::write(&path, serde_json::to_string_pretty(&fixture)?).await?;
+		log::debug!("Recorded chat completion {}", path.display());
+
+		Ok(serde_json::from_value(fixture.response)?)
+	}
+
+	fn state(&self) -> BreakerState {
+		self.inner.state()
+	}
+}
+
+/// Answers the requests from the fixtures directory without calling the API. Requests that were never recorded fail.
+pub struct ReplayClient {
+	path: PathBuf
+}
+
+impl ReplayClient {
+	pub fn new(path: PathBuf) -> Self {
+		Self { path }
+	}
+}
+
+#[async_trait]
+impl ChatClient for ReplayClient {
+	async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+		let (key, _) = request_key(&request)?;
+		let path = fixture_path(&self.path, &key);
+		let content = fs::read_to_string(&path).await.map_err(|e| {
+			anyhow::anyhow!(
+				"No recorded response for request {} in {} ({}), record it with LLM_FIXTURES_MODE=record",
+				key,
+				self.path.display(),
+				e
+			)
+		})?;
+		let fixture: Fixture = serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+		Ok(serde_json::from_value(fixture.response)?)
+	}
+}
@@ -0,0 +1,75 @@
+pub mod breaker;
+pub mod fixtures;
+pub mod openai;
+pub mod scripted;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, ChatCompletionResponse};
+
+pub use self::breaker::BreakerState;
+pub use self::fixtures::{RecordingClient, ReplayClient};
+pub use self::openai::*;
+pub use self::scripted::{ScriptedClient, ScriptedResponse};
+use crate::config::{FixturesMode, OpenAIConfig};
+use crate::prelude::*;
+
+#[async_trait]
+pub trait ChatClient: Send + Sync {
+	async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse>;
+
+	/// Whether the client currently sends requests or fails fast, surfaced in the health endpoints
+	fn state(&self) -> BreakerState {
+		BreakerState::Closed
+	}
+}
+
+/// The client of the configured fixtures mode: the API, the API with every exchange recorded, or the recordings only
+pub fn from_config(config: &OpenAIConfig) -> Result<Arc<dyn ChatClient>> {
+	Ok(match config.fixtures_mode {
+		FixturesMode::Off => Arc::new(OpenAIClient::new(config)?),
+		FixturesMode::Record => {
+			log::info!("Recording chat completions to {}", config.fixtures_path.display());
+			Arc::new(RecordingClient::new(OpenAIClient::new(config)?, config.fixtures_path.clone()))
+		}
+		FixturesMode::Replay => {
+			log::info!("Replaying chat completions from {}", config.fixtures_path.display());
+			Arc::new(ReplayClient::new(config.fixtures_path.clone()))
+		}
+	})
+}
+
+#[derive(Debug)]
+pub enum LlmError {
+	/// The API answered with an error status
+	Status(u16, String),
+	Timeout,
+	/// The circuit breaker is open and no request was sent
+	Unavailable,
+	Transport(String)
+}
+
+impl LlmError {
+	/// Rate limits, server errors, timeouts and connection failures are worth retrying
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			LlmError::Status(status, _) => *status == 429 || *status >= 500,
+			LlmError::Timeout | LlmError::Transport(_) => true,
+			LlmError::Unavailable => false
+		}
+	}
+}
+
+impl std::fmt::Display for LlmError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			LlmError::Status(status, body) => write!(f, "LLM API returned {}: {}", status, body),
+			LlmError::Timeout => write!(f, "LLM API request timed out"),
+			LlmError::Unavailable => write!(f, "LLM API is unavailable, circuit breaker is open"),
+			LlmError::Transport(message) => write!(f, "LLM API request failed: {}", message)
+		}
+	}
+}
+
+impl std::error::Error for LlmError {}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, ChatCompletionResponse};
+use rand::Rng;
+
+use super::breaker::{BreakerState, CircuitBreaker};
+use super::{ChatClient, LlmError};
+use crate::config::OpenAIConfig;
+use crate::constants::{LLM_BACKOFF_BASE_MS, LLM_BACKOFF_MAX_MS, LLM_BREAKER_FAILURE_THRESHOLD, LLM_BREAKER_RESET_SECS};
+use crate::prelude::*;
+
+/// Async OpenAI chat completion client with per-call timeouts, retries with jittered exponential backoff on 429/5xx and a
+/// circuit breaker shared by all conversations
+pub struct OpenAIClient {
+	http: reqwest::Client,
+	api_base: String,
+	api_key: String,
+	max_retries: u32,
+	breaker: CircuitBreaker
+}
+
+impl OpenAIClient {
+	pub fn new(config: &OpenAIConfig) -> Result<Self> {
+		let api_key = config.api_key.as_ref().ok_or_else(|| anyhow::anyhow!("OpenAI API key is not set"))?;
+
+		Ok(Self {
+			http: reqwest::Client::builder().timeout(Duration::from_secs(config.request_timeout_secs as u64)).build()?,
+			api_base: config.api_base.trim_end_matches('/').to_string(),
+			api_key: api_key.expose().to_string(),
+			max_retries: config.max_retries,
+			breaker: CircuitBreaker::new(LLM_BREAKER_FAILURE_THRESHOLD, Duration::from_secs(LLM_BREAKER_RESET_SECS))
+		})
+	}
+
+	/// The response as the API sent it, for callers that keep it
+	pub async fn chat_completion_json(&self, request: &ChatCompletionRequest) -> Result<serde_json::Value> {
+		let mut attempt = 0;
+		loop {
+			match self.send(request).await {
+				Ok(response) => return Ok(response),
+				Err(e) if e.is_retryable() && attempt < self.max_retries => {
+					let delay = backoff(attempt);
+					log::warn!("{}, retrying in {} ms (attempt {} of {})", e, delay.as_millis(), attempt + 1, self.max_retries);
+					tokio::time::sleep(delay).await;
+					attempt += 1;
+				}
+				Err(e) => return Err(e.into())
+			}
+		}
+	}
+
+	async fn send(&self, request: &ChatCompletionRequest) -> std::result::Result<serde_json::Value, LlmError> {
+		let Some(permit) = self.breaker.allow() else {
+			return Err(LlmError::Unavailable);
+		};
+
+		let result = self.post(request).await;
+		match &result {
+			Ok(_) => permit.record_success(),
+			// Client errors say nothing about the health of the service
+			Err(e) if e.is_retryable() => permit.record_failure(),
+			Err(_) => permit.record_success()
+		}
+		result
+	}
+
+	async fn post(&self, request: &ChatCompletionRequest) -> std::result::Result<serde_json::Value, LlmError> {
+		let response = self
+			.http
+			.post(format!("{}/chat/completions", self.api_base))
+			.bearer_auth(&self.api_key)
+			.json(request)
+			.send()
+			.await
+			.map_err(|e| if e.is_timeout() { LlmError::Timeout } else { LlmError::Transport(e.to_string()) })?;
+
+		let status = response.status();
+		if !status.is_success() {
+			let body = response.text().await.unwrap_or_default();
+			return Err(LlmError::Status(status.as_u16(), body));
+		}
+
+		response
+			.json::<serde_json::Value>()
+			.await
+			.map_err(|e| if e.is_timeout() { LlmError::Timeout } else { LlmError::Transport(e.to_string()) })
+	}
+}
+
+#[async_trait]
+impl ChatClient for OpenAIClient {
+	async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+		let response = self.chat_completion_json(&request).await?;
+		Ok(serde_json::from_value(response)?)
+	}
+
+	fn state(&self) -> BreakerState {
+		self.breaker.state()
+	}
+}
+
+// Exponential backoff with full jitter, see https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
+fn backoff(attempt: u32) -> Duration {
+	let ceiling = LLM_BACKOFF_BASE_MS.saturating_mul(1 << attempt.min(16)).min(LLM_BACKOFF_MAX_MS);
+	Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+}
@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, ChatCompletionResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::ChatClient;
+use crate::prelude::*;
+
+/// One response of a script: the content of a message, or a function call whose arguments are given as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScriptedResponse {
+	FunctionCall { function_call: ScriptedFunctionCall },
+	Content { content: String }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptedFunctionCall {
+	pub name: String,
+	/// None for functions without parameters
+	#[serde(default)]
+	pub arguments: serde_json::Value
+}
+
+impl ScriptedResponse {
+	/// The response as the API returns it
+	pub fn to_completion(&self, model: &str) -> Result<ChatCompletionResponse> {
+		let (message, finish_reason) = match self {
+			ScriptedResponse::FunctionCall { function_call } => {
+				let arguments = match &function_call.arguments {
+					serde_json::Value::Null => "{}".to_string(),
+					arguments => arguments.to_string()
+				};
+				let message = json!({
+					"role": "assistant",
+					"content": null,
+					"function_call": { "name": function_call.name, "arguments": arguments }
+				});
+				(message, "function_call")
+			}
+			ScriptedResponse::Content { content } => (json!({ "role": "assistant", "content": content }), "stop")
+		};
+
+		Ok(serde_json::from_value(json!({
+			"id": "scripted",
+			"object": "chat.completion",
+			"created": 0,
+			"model": model,
+			"choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+			"usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }
+		}))?)
+	}
+}
+
+/// Answers the requests of one conversation with the responses of a script, in order, so that it runs offline and
+/// gives the same answer every time. Requests made once the script is exhausted fail.
+pub struct ScriptedClient {
+	responses: Mutex<VecDeque<ScriptedResponse>>
+}
+
+impl ScriptedClient {
+	pub fn new(responses: Vec<ScriptedResponse>) -> Self {
+		Self {
+			responses: Mutex::new(responses.into())
+		}
+	}
+}
+
+#[async_trait]
+impl ChatClient for ScriptedClient {
+	async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+		let response = self.responses.lock().unwrap().pop_front();
+		match response {
+			Some(response) => response.to_completion(&request.model),
+			None => Err(anyhow::anyhow!("The script has no response left for this request"))
+		}
+	}
+}
@@ -0,0 +1,54 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Monotonic counter exported in the Prometheus text format
+pub struct Counter {
+	name: &'static str,
+	help: &'static str,
+	value: AtomicU64
+}
+
+impl Counter {
+	pub const fn new(name: &'static str, help: &'static str) -> Self {
+		Self {
+			name,
+			help,
+			value: AtomicU64::new(0)
+		}
+	}
+
+	pub fn increment(&self) {
+		self.value.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn get(&self) -> u64 {
+		self.value.load(Ordering::Relaxed)
+	}
+}
+
+pub static QUERIES_STARTED: Counter = Counter::new("oracle_queries_started_total", "Queries received");
+pub static QUERIES_ANSWERED: Counter = Counter::new("oracle_queries_answered_total", "Queries answered, including cache hits");
+pub static QUERIES_FAILED: Counter = Counter::new("oracle_queries_failed_total", "Queries that ended with an error");
+pub static QUERIES_CANCELLED_DISCONNECTED: Counter =
+	Counter::new("oracle_queries_cancelled_disconnected_total", "Queries stopped because the client disconnected");
+pub static QUERIES_CANCELLED_REQUESTED: Counter = Counter::new("oracle_queries_cancelled_requested_total", "Queries stopped by a cancel request");
+pub static ANSWER_CACHE_HITS: Counter = Counter::new("oracle_answer_cache_hits_total", "Queries answered from the answer cache");
+
+static COUNTERS: [&Counter; 6] = [
+	&QUERIES_STARTED,
+	&QUERIES_ANSWERED,
+	&QUERIES_FAILED,
+	&QUERIES_CANCELLED_DISCONNECTED,
+	&QUERIES_CANCELLED_REQUESTED,
+	&ANSWER_CACHE_HITS
+];
+
+pub fn render() -> String {
+	let mut output = String::new();
+	for counter in COUNTERS {
+		let _ = writeln!(output, "# HELP {} {}", counter.name, counter.help);
+		let _ = writeln!(output, "# TYPE {} counter", counter.name);
+		let _ = writeln!(output, "{} {}", counter.name, counter.get());
+	}
+	output
+}
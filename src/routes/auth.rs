@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use actix_web::{
+	body::{EitherBody, MessageBody},
+	dev::{ServiceRequest, ServiceResponse},
+	http::{header, StatusCode},
+	web, Error, HttpRequest, HttpResponse
+};
+use actix_web_lab::middleware::Next;
+
+use super::errors::error_response;
+use super::rate_limit::{DailyQuota, RateLimiter};
+use crate::config::AuthConfig;
+use crate::constants::{API_KEY_HEADER, PUBLIC_ROUTES};
+use crate::prelude::*;
+
+/// API keys and per-client limits enforced in front of the oracle routes
+pub struct AccessGuard {
+	// API key -> client name, used in logs
+	keys: HashMap<String, String>,
+	disabled: bool,
+	trusted_proxies: Vec<IpAddr>,
+	key_limiter: RateLimiter,
+	ip_limiter: RateLimiter,
+	quota: DailyQuota
+}
+
+impl AccessGuard {
+	/// Keys are read from `api_keys` and from the file at `api_keys_file`, which holds one key per line, optionally
+	/// followed by a client name. Empty lines and lines starting with `#` are ignored. Fails without any key unless
+	/// authentication is disabled.
+	pub fn new(config: &AuthConfig) -> Result<Self> {
+		let mut keys = HashMap::new();
+
+		// Clients are named after where their key is configured, the keys themselves never appear in the logs
+		for (i, key) in config.api_keys.iter().enumerate() {
+			let key = key.expose().trim();
+			if !key.is_empty() {
+				keys.insert(key.to_string(), format!("api_keys[{}]", i));
+			}
+		}
+
+		let keys_file = &config.api_keys_file;
+		match std::fs::read_to_string(keys_file) {
+			Ok(content) => {
+				for (i, line) in content.lines().enumerate() {
+					let line = line.trim();
+					if line.is_empty() || line.starts_with('#') {
+						continue;
+					}
+					let mut parts = line.split_whitespace();
+					let key = parts.next().unwrap_or_default();
+					let name = parts.next().map(str::to_string).unwrap_or_else(|| format!("{}:{}", keys_file.display(), i + 1));
+					keys.insert(key.to_string(), name);
+				}
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => log::debug!("API keys file {} not found", keys_file.display()),
+			Err(e) => return Err(anyhow::anyhow!("Failed to read API keys file {}: {}", keys_file.display(), e))
+		}
+
+		if config.disabled {
+			log::warn!("Authentication is disabled, requests will only be rate limited per IP");
+		} else if keys.is_empty() {
+			return Err(anyhow::anyhow!(
+				"No API keys configured in API_KEYS or {}, set AUTH_DISABLED=true to serve without authentication",
+				keys_file.display()
+			));
+		} else {
+			log::info!("Loaded {} API keys", keys.len());
+		}
+
+		Ok(Self {
+			keys,
+			disabled: config.disabled,
+			trusted_proxies: config.trusted_proxies.clone(),
+			key_limiter: RateLimiter::per_minute(config.rate_limit_per_key_per_minute),
+			ip_limiter: RateLimiter::per_minute(config.rate_limit_per_ip_per_minute),
+			quota: DailyQuota::new(config.daily_quota_per_key)
+		})
+	}
+
+	fn check(&self, req: &ServiceRequest) -> std::result::Result<(), HttpResponse> {
+		let ip = self.client_ip(req.request());
+		self.charge_ip(&ip).map_err(LimitExceeded::into_response)?;
+
+		if self.disabled {
+			return Ok(());
+		}
+
+		let key = api_key(req.request()).ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "missing_api_key", "An API key is required"))?;
+		if !self.keys.contains_key(&key) {
+			return Err(error_response(StatusCode::UNAUTHORIZED, "invalid_api_key", "The API key is not valid"));
+		}
+		self.charge_key(&key).map_err(LimitExceeded::into_response)
+	}
+
+	/// The client a request already let through by `guard` is charged to: its API key, `None` when authentication is
+	/// disabled, and its IP
+	pub fn client_of(&self, req: &HttpRequest) -> (Option<String>, String) {
+		let key = if self.disabled { None } else { api_key(req) };
+		(key, self.client_ip(req))
+	}
+
+	/// Takes one request from the limits of a client admitted earlier, such as a question on a WebSocket session
+	pub fn charge(&self, key: Option<&str>, ip: &str) -> std::result::Result<(), LimitExceeded> {
+		self.charge_ip(ip)?;
+		match key {
+			Some(key) => self.charge_key(key),
+			None => Ok(())
+		}
+	}
+
+	fn charge_ip(&self, ip: &str) -> std::result::Result<(), LimitExceeded> {
+		self.ip_limiter.check(ip).map_err(|retry_after| {
+			log::warn!("Rate limit exceeded for IP {}", ip);
+			LimitExceeded::new("rate_limited", "Too many requests from this address", retry_after)
+		})
+	}
+
+	fn charge_key(&self, key: &str) -> std::result::Result<(), LimitExceeded> {
+		let client = self.keys.get(key).map(String::as_str).unwrap_or("unknown");
+
+		self.key_limiter.check(key).map_err(|retry_after| {
+			log::warn!("Rate limit exceeded for client {}", client);
+			LimitExceeded::new("rate_limited", "Too many requests for this API key", retry_after)
+		})?;
+
+		self.quota.check(key).map_err(|retry_after| {
+			log::warn!("Daily quota exhausted for client {}", client);
+			LimitExceeded::new("quota_exceeded", "The daily quota for this API key is exhausted", retry_after)
+		})
+	}
+
+	// The peer address, or the address forwarded by the peer when it is a trusted proxy
+	fn client_ip(&self, req: &HttpRequest) -> String {
+		let Some(peer) = req.peer_addr().map(|addr| addr.ip()) else {
+			return "unknown".to_string();
+		};
+		if self.trusted_proxies.contains(&peer) {
+			if let Some(ip) = req.connection_info().realip_remote_addr() {
+				return ip.to_string();
+			}
+		}
+		peer.to_string()
+	}
+}
+
+/// A request over the rate limit or the daily quota of its client
+#[derive(Debug)]
+pub struct LimitExceeded {
+	pub error: &'static str,
+	pub message: &'static str,
+	/// Seconds until the client may retry
+	pub retry_after: u64
+}
+
+impl LimitExceeded {
+	fn new(error: &'static str, message: &'static str, retry_after: u64) -> Self {
+		Self { error, message, retry_after }
+	}
+
+	fn into_response(self) -> HttpResponse {
+		let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, self.error, self.message);
+		response.headers_mut().insert(header::RETRY_AFTER, self.retry_after.into());
+		response
+	}
+}
+
+/// Middleware rejecting requests without a valid API key or over their limits with 401/429
+pub async fn guard<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> std::result::Result<ServiceResponse<EitherBody<B>>, Error> {
+	if PUBLIC_ROUTES.contains(&req.path()) {
+		return next.call(req).await.map(ServiceResponse::map_into_left_body);
+	}
+
+	let rejection = match req.app_data::<web::Data<Arc<AccessGuard>>>() {
+		Some(access_guard) => access_guard.check(&req).err(),
+		None => {
+			log::error!("AccessGuard is not registered as app data");
+			Some(error_response(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Access control is misconfigured"))
+		}
+	};
+
+	match rejection {
+		Some(response) => Ok(req.into_response(response).map_into_right_body()),
+		None => next.call(req).await.map(ServiceResponse::map_into_left_body)
+	}
+}
+
+// Accepts `x-api-key: <key>`, `Authorization: Bearer <key>` and, on `/ws` only, an `api_key` query parameter for
+// browser WebSocket clients that cannot set headers. Anywhere else, keys in URLs would end up in access logs.
+fn api_key(req: &HttpRequest) -> Option<String> {
+	let headers = req.headers();
+	headers
+		.get(API_KEY_HEADER)
+		.and_then(|value| value.to_str().ok())
+		.or_else(|| {
+			headers
+				.get(header::AUTHORIZATION)
+				.and_then(|value| value.to_str().ok())
+				.and_then(|value| value.strip_prefix("Bearer "))
+		})
+		.map(str::to_string)
+		.or_else(|| {
+			(req.path() == "/ws")
+				.then(|| web::Query::<HashMap<String, String>>::from_query(req.query_string()).ok())
+				.flatten()
+				.and_then(|query| query.into_inner().remove("api_key"))
+		})
+}
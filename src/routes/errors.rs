@@ -0,0 +1,18 @@
+use actix_web::{http::StatusCode, HttpResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// JSON body returned with every rejected request
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+	/// Machine readable error code, e.g. `rate_limited`
+	pub error: String,
+	pub message: String
+}
+
+pub fn error_response(status: StatusCode, error: &'static str, message: impl Into<String>) -> HttpResponse {
+	HttpResponse::build(status).json(ErrorBody {
+		error: error.to_string(),
+		message: message.into()
+	})
+}
@@ -1,7 +1,15 @@
 use actix_web_lab::sse::{Data, SendError, Sender};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
 
+use crate::db::SearchFilter;
 use crate::sse_events;
 
+/// Transport-independent channel a conversation reports its progress on
+pub type EventSender = mpsc::Sender<QueryEvent>;
+pub type EventReceiver = mpsc::Receiver<QueryEvent>;
+
 pub async fn emit<T: Into<Data>>(sender: &Sender, event: T) -> Result<(), SendError> {
 	sender.send(event.into()).await?;
 	// Empty message to force send the above message to receiver
@@ -11,13 +19,73 @@ pub async fn emit<T: Into<Data>>(sender: &Sender, event: T) -> Result<(), SendEr
 	Ok(())
 }
 
+/// Pumps conversation events into an SSE stream until either side is closed
+pub async fn forward_to_sse(mut events: EventReceiver, sender: Sender) {
+	while let Some(event) = events.recv().await {
+		if emit(&sender, event).await.is_err() {
+			log::debug!("SSE client disconnected");
+			break;
+		}
+	}
+}
+
+/// An SSE event name and the name of the schema describing its JSON data, if it carries any
+#[derive(Debug, Serialize)]
+pub struct EventDescriptor {
+	pub name: &'static str,
+	pub payload: Option<&'static str>
+}
+
+/// Sent first on every stream
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ProcessQueryPayload {
+	/// Identifies the answer when sending feedback
+	pub answer_id: String
+}
+
+/// Arguments of a `search_documents` function call
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SearchDocumentsPayload {
+	pub query: String,
+	/// Restrictions chosen by the model, applied on top of the filter of the query
+	pub filter: Option<SearchFilter>
+}
+
+/// Arguments of a `search_file` function call
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SearchFilePayload {
+	pub query: String,
+	pub path: String
+}
+
+/// Arguments of a `search_path` function call
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SearchPathPayload {
+	pub path: String
+}
+
+/// Arguments of a `get_links` function call
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct GetLinksPayload {
+	pub path: String
+}
+
+/// The final answer, formatted as Markdown and encoded as a JSON string
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct AnswerPayload(pub String);
+
+/// Description of the failure, encoded as a JSON string
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ErrorPayload(pub String);
+
 sse_events! {
 	QueryEvent,
-	(ProcessQuery, "PROCESS_QUERY"),
-	(SearchDocuments, "SEARCH_DOCUMENTS"),
-	(SearchFile, "SEARCH_FILE"),
-	(SearchPath, "SEARCH_PATH"),
+	(ProcessQuery, "PROCESS_QUERY", ProcessQueryPayload),
+	(SearchDocuments, "SEARCH_DOCUMENTS", SearchDocumentsPayload),
+	(SearchFile, "SEARCH_FILE", SearchFilePayload),
+	(SearchPath, "SEARCH_PATH", SearchPathPayload),
+	(GetLinks, "GET_LINKS", GetLinksPayload),
 	(GenerateResponse, "GENERATE_RESPONSE"),
-	(Done, "DONE"),
-	(Error, "ERROR"),
+	(Done, "DONE", AnswerPayload),
+	(Error, "ERROR", ErrorPayload),
 }
@@ -0,0 +1,60 @@
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::pipeline::QueryPipeline;
+use crate::convrsation::profiles::ProfileSummary;
+use crate::llm::BreakerState;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+	Ok,
+	/// Queries are accepted but the LLM API is failing, answers fail fast until it recovers
+	Degraded,
+	Unavailable
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+	pub status: Status,
+	/// Whether every profile is indexed
+	pub indexed: bool,
+	pub llm: BreakerState,
+	pub profiles: Vec<ProfileSummary>
+}
+
+/// Liveness probe, succeeds as long as the server accepts connections
+#[utoipa::path(get, path = "/health", responses((status = 200, description = "The server is running")))]
+#[get("/health")]
+pub async fn liveness() -> HttpResponse {
+	HttpResponse::Ok().finish()
+}
+
+/// Readiness probe, reports whether the documents of every profile are indexed and whether the LLM API circuit breaker is open
+#[utoipa::path(
+	get,
+	path = "/health/ready",
+	responses(
+		(status = 200, description = "Ready, possibly degraded", body = Readiness),
+		(status = 503, description = "The documents of a profile have not been indexed yet", body = Readiness)
+	)
+)]
+#[get("/health/ready")]
+pub async fn readiness(pipeline: web::Data<QueryPipeline>) -> HttpResponse {
+	let profiles = pipeline.profiles.summaries().await;
+	let indexed = profiles.iter().all(|profile| profile.indexed);
+	let llm = pipeline.llm.state();
+	let status = match (indexed, llm) {
+		(false, _) => Status::Unavailable,
+		(true, BreakerState::Closed) => Status::Ok,
+		(true, _) => Status::Degraded
+	};
+
+	let readiness = Readiness { status, indexed, llm, profiles };
+	if indexed {
+		HttpResponse::Ok().json(readiness)
+	} else {
+		HttpResponse::ServiceUnavailable().json(readiness)
+	}
+}
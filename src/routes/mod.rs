@@ -1,42 +1,159 @@
+pub mod auth;
+pub mod errors;
 pub mod events;
-
-use std::sync::Arc;
+pub mod health;
+pub mod openapi;
+pub mod pipeline;
+pub mod rate_limit;
+pub mod ws;
 
 use actix_web::{
 	error::ErrorNotFound,
+	get,
+	http::StatusCode,
 	post,
-	web::{self, Json},
-	Responder, Result
+	web::{self, Json, Path},
+	Either, HttpResponse, Responder, Result
 };
 use actix_web_lab::sse;
+use tokio::sync::mpsc;
+use tracing::Instrument;
 
-use crate::constants::SSE_CHANNEL_BUFFER_SIZE;
-use crate::convrsation::data::Query;
-use crate::convrsation::Conversation;
-use crate::db::qdrant::QdrantDB;
-use crate::db::RepositoryEmbeddingsDB;
-use crate::embeddings::Onnx;
+use self::errors::{error_response, ErrorBody};
+use self::events::{forward_to_sse, ErrorPayload, QueryEvent};
+use self::pipeline::QueryPipeline;
+use crate::constants::{ANSWER_ID_HEADER, SSE_CHANNEL_BUFFER_SIZE};
+use crate::convrsation::data::{Cancellation, Query};
+use crate::convrsation::profiles::ProfileSummary;
+use crate::feedback::Feedback;
 
+/// Ask a question and receive the progress and the final answer as server-sent events
+#[utoipa::path(
+	post,
+	path = "/query",
+	request_body = Query,
+	responses(
+		(status = 200, description = "Stream of server-sent events, see `/events` for the event catalogue", content_type = "text/event-stream", body = String,
+			headers(("x-answer-id" = String, description = "Identifies the answer when sending feedback"))),
+		(status = 400, description = "Unknown profile or model overrides not allowed", body = ErrorBody),
+		(status = 401, description = "Missing or invalid API key", body = ErrorBody),
+		(status = 404, description = "The documents have not been indexed yet"),
+		(status = 429, description = "Rate limit or daily quota exceeded", body = ErrorBody)
+	),
+	security(("api_key" = []))
+)]
 #[post("/query")]
-async fn query(data: Json<Query>, db: web::Data<Arc<QdrantDB>>, model: web::Data<Arc<Onnx>>) -> Result<impl Responder> {
-	if db.is_indexed().await.unwrap_or_default() {
-		let (sender, rx) = sse::channel(SSE_CHANNEL_BUFFER_SIZE);
+async fn query(data: Json<Query>, pipeline: web::Data<QueryPipeline>) -> Result<impl Responder> {
+	let profile = match pipeline.validate(&data) {
+		Ok(profile) => profile,
+		Err(e) => return Ok(Either::Left(error_response(StatusCode::BAD_REQUEST, "invalid_query", e.to_string())))
+	};
 
-		actix_rt::spawn(async move {
-			let result = async {
-				let mut conversation = Conversation::initiate(data.into_inner(), db.get_ref().clone(), model.get_ref().clone(), sender.clone()).await?;
-				conversation.generate().await?;
+	if profile.is_indexed().await {
+		let (sender, rx) = sse::channel(SSE_CHANNEL_BUFFER_SIZE);
+		let (event_sender, events) = mpsc::channel(SSE_CHANNEL_BUFFER_SIZE);
+		let answer_id = uuid::Uuid::new_v4().to_string();
+		let id = answer_id.clone();
+		// Before spawning, so that a cancellation sent as soon as the header is read finds the answer
+		pipeline.in_flight.register(&answer_id);
 
-				Ok::<(), anyhow::Error>(())
-			};
-			if let Err(e) = result.await {
-				eprintln!("/query error: {}", e);
+		actix_rt::spawn(forward_to_sse(events, sender));
+		// In the span of the request, so that the trace covers the conversation streamed after the response is sent
+		actix_rt::spawn(
+			async move {
+				let error_sender = event_sender.clone();
+				match pipeline.answer(id, data.into_inner(), &[], event_sender).await {
+					Err(e) if e.downcast_ref::<Cancellation>().is_none() => {
+						log::error!("/query error: {}", e);
+						// The stream closes once the error is sent
+						let _ = error_sender.send(QueryEvent::Error(ErrorPayload(e.to_string()))).await;
+					}
+					_ => {}
+				}
 			}
-		});
+			.instrument(tracing::Span::current())
+		);
 
-		Ok(rx)
+		Ok(Either::Right(rx.customize().insert_header((ANSWER_ID_HEADER, answer_id))))
 	} else {
 		eprintln!("Repository is not indexed");
 		Err(ErrorNotFound("Repository is not indexed"))
 	}
 }
+
+/// Stop generating an answer. The conversation stops before its next step.
+#[utoipa::path(
+	post,
+	path = "/query/{answer_id}/cancel",
+	params(("answer_id" = String, Path, description = "The ID sent in the `x-answer-id` header and the `PROCESS_QUERY` event")),
+	responses(
+		(status = 202, description = "Cancellation requested"),
+		(status = 401, description = "Missing or invalid API key", body = ErrorBody),
+		(status = 404, description = "No answer with this ID is in progress", body = ErrorBody)
+	),
+	security(("api_key" = []))
+)]
+#[post("/query/{answer_id}/cancel")]
+async fn cancel(answer_id: Path<String>, pipeline: web::Data<QueryPipeline>) -> HttpResponse {
+	if pipeline.in_flight.cancel(&answer_id) {
+		log::info!("Cancellation requested for conversation {}", answer_id.as_str());
+		HttpResponse::Accepted().finish()
+	} else {
+		error_response(StatusCode::NOT_FOUND, "unknown_answer", "No answer with this ID is in progress")
+	}
+}
+
+/// The document corpora that can be selected with the `profile` field of a query, the first one is the default
+#[utoipa::path(
+	get,
+	path = "/profiles",
+	responses(
+		(status = 200, description = "Configured profiles", body = [ProfileSummary]),
+		(status = 401, description = "Missing or invalid API key", body = ErrorBody)
+	),
+	security(("api_key" = []))
+)]
+#[get("/profiles")]
+async fn profiles(pipeline: web::Data<QueryPipeline>) -> HttpResponse {
+	HttpResponse::Ok().json(pipeline.profiles.summaries().await)
+}
+
+/// Counters in the Prometheus text format
+#[utoipa::path(get, path = "/metrics", responses((status = 200, description = "Prometheus metrics", content_type = "text/plain")))]
+#[get("/metrics")]
+async fn metrics() -> HttpResponse {
+	HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(crate::metrics::render())
+}
+
+/// Rate an answer, optionally with a comment
+#[utoipa::path(
+	post,
+	path = "/feedback",
+	request_body = Feedback,
+	responses(
+		(status = 204, description = "Feedback recorded"),
+		(status = 400, description = "Invalid feedback", body = ErrorBody),
+		(status = 401, description = "Missing or invalid API key", body = ErrorBody),
+		(status = 404, description = "Unknown answer ID", body = ErrorBody),
+		(status = 429, description = "Rate limit or daily quota exceeded", body = ErrorBody),
+		(status = 500, description = "The feedback could not be stored", body = ErrorBody)
+	),
+	security(("api_key" = []))
+)]
+#[post("/feedback")]
+async fn feedback(data: Json<Feedback>, pipeline: web::Data<QueryPipeline>) -> HttpResponse {
+	let feedback = data.into_inner();
+
+	if let Err(e) = feedback.validate() {
+		return error_response(StatusCode::BAD_REQUEST, "invalid_feedback", e.to_string());
+	}
+
+	match pipeline.feedback_store.record_feedback(feedback).await {
+		Ok(true) => HttpResponse::NoContent().finish(),
+		Ok(false) => error_response(StatusCode::NOT_FOUND, "unknown_answer", "No answer with this ID was recorded"),
+		Err(e) => {
+			log::error!("/feedback error: {}", e);
+			error_response(StatusCode::INTERNAL_SERVER_ERROR, "feedback_failed", "Failed to record feedback")
+		}
+	}
+}
@@ -0,0 +1,93 @@
+use actix_web::{get, HttpResponse, Responder};
+use serde_json::json;
+use utoipa::{
+	openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+	Modify, OpenApi
+};
+
+use super::errors::ErrorBody;
+use super::events::{
+	AnswerPayload, ErrorPayload, GetLinksPayload, ProcessQueryPayload, QueryEvent, SearchDocumentsPayload, SearchFilePayload, SearchPathPayload
+};
+use super::health::{Readiness, Status};
+use crate::constants::API_KEY_HEADER;
+use crate::convrsation::data::Query;
+use crate::convrsation::profiles::ProfileSummary;
+use crate::convrsation::settings::ModelOverrides;
+use crate::db::SearchFilter;
+use crate::feedback::{Feedback, Rating};
+use crate::llm::BreakerState;
+
+#[derive(OpenApi)]
+#[openapi(
+	info(description = "Answers questions about government document corpora, such as the IRCC documentation"),
+	paths(
+		super::query,
+		super::cancel,
+		super::feedback,
+		super::profiles,
+		super::ws::chat,
+		super::metrics,
+		super::health::liveness,
+		super::health::readiness,
+		openapi_spec,
+		event_catalogue
+	),
+	components(schemas(
+		Query,
+		ModelOverrides,
+		SearchFilter,
+		Feedback,
+		Rating,
+		ErrorBody,
+		Readiness,
+		ProfileSummary,
+		Status,
+		BreakerState,
+		ProcessQueryPayload,
+		SearchDocumentsPayload,
+		SearchFilePayload,
+		SearchPathPayload,
+		GetLinksPayload,
+		AnswerPayload,
+		ErrorPayload
+	)),
+	modifiers(&ApiKeySecurity)
+)]
+pub struct ApiDoc;
+
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+	fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+		if let Some(components) = openapi.components.as_mut() {
+			components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))));
+		}
+	}
+}
+
+/// The OpenAPI document describing every route of the oracle
+#[utoipa::path(get, path = "/openapi.json", responses((status = 200, description = "OpenAPI 3 document", content_type = "application/json")))]
+#[get("/openapi.json")]
+pub async fn openapi_spec() -> impl Responder {
+	HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Every event sent on the `/query` stream, with the JSON schema of its data
+#[utoipa::path(get, path = "/events", responses((status = 200, description = "Event catalogue", content_type = "application/json")))]
+#[get("/events")]
+pub async fn event_catalogue() -> impl Responder {
+	let schemas = ApiDoc::openapi().components.map(|components| components.schemas).unwrap_or_default();
+
+	let events: Vec<serde_json::Value> = QueryEvent::catalogue()
+		.into_iter()
+		.map(|event| {
+			json!({
+				"event": event.name,
+				"payload": event.payload.and_then(|name| schemas.get(name))
+			})
+		})
+		.collect();
+
+	HttpResponse::Ok().json(events)
+}
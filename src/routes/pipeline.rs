@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use tokio_util::sync::CancellationToken;
+
+use super::events::EventSender;
+use crate::convrsation::cache::AnswerCache;
+use crate::convrsation::data::{Cancellation, Query, Turn};
+use crate::convrsation::profiles::{CorpusProfile, Profiles};
+use crate::convrsation::settings::ChatSettings;
+use crate::convrsation::{Assistant, Conversation};
+use crate::embeddings::Onnx;
+use crate::feedback::{AnswerRecord, FeedbackStore};
+use crate::llm::ChatClient;
+use crate::metrics;
+use crate::prelude::*;
+
+/// Cancellation tokens of the conversations in progress, by answer ID
+#[derive(Default)]
+pub struct InFlightQueries {
+	tokens: Mutex<HashMap<String, CancellationToken>>
+}
+
+impl InFlightQueries {
+	/// Makes the conversation `id` cancellable before it starts. `QueryPipeline::answer` registers it otherwise.
+	pub fn register(&self, id: &str) -> CancellationToken {
+		self.tokens.lock().unwrap().entry(id.to_string()).or_default().clone()
+	}
+
+	fn finish(&self, id: &str) {
+		self.tokens.lock().unwrap().remove(id);
+	}
+
+	/// Returns `false` if no conversation with this ID is in progress
+	pub fn cancel(&self, id: &str) -> bool {
+		match self.tokens.lock().unwrap().get(id) {
+			Some(token) => {
+				token.cancel();
+				true
+			}
+			None => false
+		}
+	}
+}
+
+/// Everything needed to answer a query, shared by the SSE and WebSocket transports
+#[derive(Clone)]
+pub struct QueryPipeline {
+	pub profiles: Arc<Profiles>,
+	pub model: Arc<Onnx>,
+	pub llm: Arc<dyn ChatClient>,
+	pub chat_settings: Arc<ChatSettings>,
+	pub feedback_store: Arc<FeedbackStore>,
+	pub answer_cache: Arc<AnswerCache>,
+	pub in_flight: Arc<InFlightQueries>
+}
+
+impl QueryPipeline {
+	/// The profile `query` is answered from, or an error if it names an unknown one
+	pub fn profile_for(&self, query: &Query) -> Result<Arc<CorpusProfile>> {
+		self.profiles.get(query.profile.as_deref())
+	}
+
+	/// The model settings for `query`, or an error if its overrides are not allowed
+	pub fn settings_for(&self, query: &Query) -> Result<ChatSettings> {
+		self.chat_settings.resolve(query.planning.as_ref(), query.answer.as_ref())
+	}
+
+	/// Checks everything about `query` that can be rejected before answering, and returns the profile to answer from
+	pub fn validate(&self, query: &Query) -> Result<Arc<CorpusProfile>> {
+		self.settings_for(query)?;
+		self.profile_for(query)
+	}
+
+	/// Answers `query` in the context of `history`, reporting progress on `sender`, and records the answer for feedback.
+	/// The conversation can be stopped with `in_flight.cancel(id)` until it completes.
+	#[tracing::instrument(
+		name = "query",
+		skip_all,
+		fields(answer_id = %id, turns = history.len(), profile = tracing::field::Empty, cache_hit = tracing::field::Empty)
+	)]
+	pub async fn answer(&self, id: String, query: Query, history: &[Turn], sender: EventSender) -> Result<Turn> {
+		metrics::QUERIES_STARTED.increment();
+		let cancellation = self.in_flight.register(&id);
+
+		let result = self.run(id.clone(), query, history, sender, cancellation).await;
+		self.in_flight.finish(&id);
+
+		match &result {
+			Ok(_) => metrics::QUERIES_ANSWERED.increment(),
+			Err(e) => match e.downcast_ref::<Cancellation>() {
+				Some(Cancellation::ClientDisconnected) => {
+					log::info!("Conversation {} stopped: client disconnected", &id);
+					metrics::QUERIES_CANCELLED_DISCONNECTED.increment();
+				}
+				Some(Cancellation::Requested) => {
+					log::info!("Conversation {} stopped: cancelled on request", &id);
+					metrics::QUERIES_CANCELLED_REQUESTED.increment();
+				}
+				None => metrics::QUERIES_FAILED.increment()
+			}
+		}
+
+		result
+	}
+
+	async fn run(&self, id: String, query: Query, history: &[Turn], sender: EventSender, cancellation: CancellationToken) -> Result<Turn> {
+		let profile = self.profile_for(&query)?;
+		tracing::Span::current().record("profile", profile.name.as_str());
+		let assistant = Assistant {
+			llm: self.llm.clone(),
+			settings: self.settings_for(&query)?,
+			prompts: profile.prompts.pick()
+		};
+		// Follow-ups depend on the rest of the session and overrides and filters change the answer, so only standalone
+		// questions with the deployment settings are cached
+		let cacheable = self.answer_cache.is_enabled() && history.is_empty() && !query.has_overrides() && query.filter.is_empty();
+		let filter = query.filter.clone();
+
+		let mut conversation = Conversation::initiate(id, query, profile.db.clone(), self.model.clone(), assistant, sender)
+			.await?
+			.with_history(history)
+			.with_cancellation(cancellation)
+			.with_search(profile.search.clone())
+			.with_filter(filter);
+
+		let index_version = if cacheable {
+			self.answer_cache
+				.index_version(&profile.name, profile.db.as_ref())
+				.await
+				.map_err(|e| log::warn!("Answer cache disabled for this query, index version unavailable: {}", e))
+				.ok()
+				// A prompt change invalidates answers just like an index change
+				.map(|version| format!("{}/{}/{}", profile.name, version, conversation.prompt_version()))
+		} else {
+			None
+		};
+
+		let query_embeddings = match &index_version {
+			Some(_) => self.answer_cache.query_embeddings(conversation.sanitized_query(), self.model.clone()).await?,
+			None => None
+		};
+		let cached = match &index_version {
+			Some(version) => self.answer_cache.lookup(conversation.sanitized_query(), version, query_embeddings.as_ref()),
+			None => None
+		};
+
+		tracing::Span::current().record("cache_hit", cached.is_some());
+		match cached {
+			Some(cached) => {
+				metrics::ANSWER_CACHE_HITS.increment();
+				conversation.replay(cached).await?
+			}
+			None => {
+				conversation.generate().await?;
+				if let (Some(version), Some(answer)) = (&index_version, conversation.cached_answer()) {
+					self.answer_cache.insert(conversation.sanitized_query(), version, query_embeddings, answer);
+				}
+			}
+		}
+
+		let record = AnswerRecord {
+			answer_id: conversation.id().to_string(),
+			query: conversation.original_query().to_string(),
+			sanitized_query: conversation.sanitized_query().to_string(),
+			retrieved_paths: conversation.retrieved_paths().to_vec(),
+			answer: conversation.answer().unwrap_or_default().to_string(),
+			prompt_version: conversation.prompt_version().to_string(),
+			profile: profile.name.clone(),
+			created_at: Utc::now()
+		};
+		self.feedback_store.record_answer(&record).await?;
+
+		Ok(Turn {
+			question: record.sanitized_query,
+			answer: record.answer
+		})
+	}
+}
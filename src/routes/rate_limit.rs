@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::constants::RATE_LIMIT_MAX_TRACKED_CLIENTS;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+struct TokenBucket {
+	tokens: f64,
+	last_refill: Instant
+}
+
+/// Token-bucket rate limiter keyed by an arbitrary client identifier (API key or IP address)
+pub struct RateLimiter {
+	capacity: f64,
+	refill_per_second: f64,
+	buckets: Mutex<HashMap<String, TokenBucket>>
+}
+
+impl RateLimiter {
+	/// Allows bursts of up to `requests` and refills the bucket over one minute
+	pub fn per_minute(requests: u32) -> Self {
+		Self {
+			capacity: requests as f64,
+			refill_per_second: requests as f64 / 60.0,
+			buckets: Mutex::new(HashMap::new())
+		}
+	}
+
+	/// Takes a token for `client`. On rejection, returns the number of seconds until a token is available.
+	pub fn check(&self, client: &str) -> Result<(), u64> {
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().unwrap();
+
+		if buckets.len() >= RATE_LIMIT_MAX_TRACKED_CLIENTS {
+			// Buckets that would be full by now carry no state worth keeping
+			let (capacity, refill_per_second) = (self.capacity, self.refill_per_second);
+			buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.last_refill).as_secs_f64() * refill_per_second < capacity);
+		}
+
+		let bucket = buckets.entry(client.to_string()).or_insert(TokenBucket {
+			tokens: self.capacity,
+			last_refill: now
+		});
+
+		let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+		bucket.tokens = (bucket.tokens + elapsed * self.refill_per_second).min(self.capacity);
+		bucket.last_refill = now;
+
+		if bucket.tokens >= 1.0 {
+			bucket.tokens -= 1.0;
+			Ok(())
+		} else {
+			Err(((1.0 - bucket.tokens) / self.refill_per_second).ceil() as u64)
+		}
+	}
+}
+
+/// Number of requests a client may make per UTC day
+pub struct DailyQuota {
+	limit: u32,
+	usage: Mutex<HashMap<String, (u64, u32)>>
+}
+
+impl DailyQuota {
+	pub fn new(limit: u32) -> Self {
+		Self {
+			limit,
+			usage: Mutex::new(HashMap::new())
+		}
+	}
+
+	/// Counts a request for `client`. On rejection, returns the number of seconds until the quota resets.
+	pub fn check(&self, client: &str) -> Result<(), u64> {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let today = now / SECONDS_PER_DAY;
+
+		let mut usage = self.usage.lock().unwrap();
+		usage.retain(|_, (day, _)| *day == today);
+
+		let (_, count) = usage.entry(client.to_string()).or_insert((today, 0));
+		if *count >= self.limit {
+			Err((today + 1) * SECONDS_PER_DAY - now)
+		} else {
+			*count += 1;
+			Ok(())
+		}
+	}
+}
@@ -0,0 +1,223 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use actix_ws::{Message, MessageStream, Session};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use super::auth::AccessGuard;
+use super::events::EventReceiver;
+use super::pipeline::QueryPipeline;
+use crate::constants::SSE_CHANNEL_BUFFER_SIZE;
+use crate::convrsation::data::{Cancellation, Query, Turn};
+use crate::feedback::Feedback;
+
+/// Messages accepted from the client, tagged by `type`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+	/// A new question, answered in the context of the previous questions of the session
+	Question(Query),
+	/// Stops the answer in progress
+	Cancel,
+	Feedback(Feedback)
+}
+
+/// Messages sent to the client, tagged by `type`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+	Session { session_id: String },
+	/// A `QueryEvent`, with the same name and data as on the `/query` stream
+	Event { event: &'static str, data: Option<serde_json::Value> },
+	Cancelled { answer_id: String },
+	FeedbackRecorded { answer_id: String },
+	Error { error: String, message: String }
+}
+
+impl ServerMessage {
+	fn error(error: &str, message: impl ToString) -> Self {
+		ServerMessage::Error {
+			error: error.to_string(),
+			message: message.to_string()
+		}
+	}
+}
+
+/// Opens a chat session carrying questions, cancellations and feedback from the client and `QueryEvent`s back
+#[utoipa::path(
+	get,
+	path = "/ws",
+	responses(
+		(status = 101, description = "WebSocket session. The client sends JSON messages of type `question` (with the `/query` body), \
+		                              `cancel` or `feedback` (with the `/feedback` body). The server replies with messages of type `session`, \
+		                              `event` (see `/events`), `cancelled`, `feedback_recorded` and `error`."),
+		(status = 401, description = "Missing or invalid API key")
+	),
+	security(("api_key" = []))
+)]
+#[get("/ws")]
+pub async fn chat(
+	req: HttpRequest,
+	body: web::Payload,
+	pipeline: web::Data<QueryPipeline>,
+	access_guard: web::Data<Arc<AccessGuard>>
+) -> Result<HttpResponse, Error> {
+	let (response, session, messages) = actix_ws::handle(&req, body)?;
+	let session_id = uuid::Uuid::new_v4().to_string();
+	// The upgrade request only counts once, every question is charged to the same client
+	let (key, ip) = access_guard.client_of(&req);
+	let client = SessionClient {
+		access_guard: access_guard.get_ref().clone(),
+		key,
+		ip
+	};
+	// A child of the span of the upgrade request, and the parent of the spans of every answer of the session
+	let span = tracing::info_span!("chat_session", session_id = %session_id);
+	actix_rt::spawn(run_session(pipeline.get_ref().clone(), client, session, messages, session_id).instrument(span));
+	Ok(response)
+}
+
+// Who the questions of a session are charged to
+struct SessionClient {
+	access_guard: Arc<AccessGuard>,
+	key: Option<String>,
+	ip: String
+}
+
+struct InFlight {
+	answer_id: String,
+	handle: JoinHandle<()>
+}
+
+async fn run_session(pipeline: QueryPipeline, client: SessionClient, mut session: Session, mut messages: MessageStream, session_id: String) {
+	let history: Arc<Mutex<Vec<Turn>>> = Arc::new(Mutex::new(Vec::new()));
+	let mut in_flight: Option<InFlight> = None;
+
+	log::info!("Chat session {} opened", &session_id);
+	send(&mut session, ServerMessage::Session {
+		session_id: session_id.clone()
+	})
+	.await;
+
+	while let Some(Ok(message)) = messages.next().await {
+		let text = match message {
+			Message::Text(text) => text,
+			Message::Ping(bytes) => {
+				if session.pong(&bytes).await.is_err() {
+					break;
+				}
+				continue;
+			}
+			Message::Close(_) => break,
+			_ => continue
+		};
+
+		match serde_json::from_str::<ClientMessage>(&text) {
+			Ok(ClientMessage::Question(query)) => {
+				if in_flight.as_ref().is_some_and(|answer| !answer.handle.is_finished()) {
+					send(&mut session, ServerMessage::error("busy", "An answer is already in progress, cancel it first")).await;
+					continue;
+				}
+				if let Err(limit) = client.access_guard.charge(client.key.as_deref(), &client.ip) {
+					let message = format!("{}, retry in {} seconds", limit.message, limit.retry_after);
+					send(&mut session, ServerMessage::error(limit.error, message)).await;
+					continue;
+				}
+				let profile = match pipeline.validate(&query) {
+					Ok(profile) => profile,
+					Err(e) => {
+						send(&mut session, ServerMessage::error("invalid_query", e)).await;
+						continue;
+					}
+				};
+				if !profile.is_indexed().await {
+					send(&mut session, ServerMessage::error("not_indexed", "Repository is not indexed")).await;
+					continue;
+				}
+				in_flight = Some(ask(&pipeline, session.clone(), history.clone(), query));
+			}
+			// The answer in progress stays the busy one unless it was actually cancelled
+			Ok(ClientMessage::Cancel) => match in_flight.as_ref() {
+				Some(answer) if !answer.handle.is_finished() && pipeline.in_flight.cancel(&answer.answer_id) => {
+					let answer_id = answer.answer_id.clone();
+					in_flight = None;
+					log::info!("Answer {} cancelled by the client", &answer_id);
+					send(&mut session, ServerMessage::Cancelled { answer_id }).await;
+				}
+				_ => send(&mut session, ServerMessage::error("nothing_to_cancel", "No answer is in progress")).await
+			},
+			Ok(ClientMessage::Feedback(feedback)) => {
+				let answer_id = feedback.answer_id.clone();
+				let reply = match feedback.validate() {
+					Err(e) => ServerMessage::error("invalid_feedback", e),
+					Ok(()) => match pipeline.feedback_store.record_feedback(feedback).await {
+						Ok(true) => ServerMessage::FeedbackRecorded { answer_id },
+						Ok(false) => ServerMessage::error("unknown_answer", "No answer with this ID was recorded"),
+						Err(e) => {
+							log::error!("Failed to record feedback: {}", e);
+							ServerMessage::error("internal_error", "Failed to record feedback")
+						}
+					}
+				};
+				send(&mut session, reply).await;
+			}
+			Err(e) => send(&mut session, ServerMessage::error("invalid_message", e)).await
+		}
+	}
+
+	if let Some(answer) = in_flight {
+		pipeline.in_flight.cancel(&answer.answer_id);
+	}
+	let _ = session.close(None).await;
+	log::info!("Chat session {} closed", &session_id);
+}
+
+fn ask(pipeline: &QueryPipeline, mut session: Session, history: Arc<Mutex<Vec<Turn>>>, query: Query) -> InFlight {
+	let answer_id = uuid::Uuid::new_v4().to_string();
+	let (event_sender, events) = mpsc::channel(SSE_CHANNEL_BUFFER_SIZE);
+	let pipeline = pipeline.clone();
+	let id = answer_id.clone();
+	// Before spawning, so that a cancellation sent right after the question finds the answer
+	pipeline.in_flight.register(&answer_id);
+
+	actix_rt::spawn(forward_to_session(events, session.clone()));
+	let handle = actix_rt::spawn(
+		async move {
+			let previous_turns = history.lock().unwrap().clone();
+			match pipeline.answer(id, query, &previous_turns, event_sender).await {
+				Ok(turn) => history.lock().unwrap().push(turn),
+				Err(e) if e.downcast_ref::<Cancellation>().is_some() => {}
+				Err(e) => {
+					log::error!("/ws error: {}", e);
+					send(&mut session, ServerMessage::error("answer_failed", e)).await;
+				}
+			}
+		}
+		.instrument(tracing::Span::current())
+	);
+
+	InFlight { answer_id, handle }
+}
+
+async fn forward_to_session(mut events: EventReceiver, mut session: Session) {
+	while let Some(event) = events.recv().await {
+		let message = ServerMessage::Event {
+			event: event.name(),
+			data: event.into_data()
+		};
+		if session.text(serde_json::to_string(&message).unwrap_or_default()).await.is_err() {
+			log::debug!("WebSocket client disconnected");
+			break;
+		}
+	}
+}
+
+async fn send(session: &mut Session, message: ServerMessage) {
+	if let Err(e) = session.text(serde_json::to_string(&message).unwrap_or_default()).await {
+		log::debug!("Failed to send WebSocket message: {:?}", e);
+	}
+}
@@ -0,0 +1,65 @@
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::config::TelemetryConfig;
+use crate::prelude::*;
+
+/// Flushes the spans not exported yet when dropped, to be kept until the end of `main`
+pub struct TelemetryGuard {
+	otlp: bool
+}
+
+impl Drop for TelemetryGuard {
+	fn drop(&mut self) {
+		if self.otlp {
+			#[cfg(feature = "otlp")]
+			opentelemetry::global::shutdown_tracer_provider();
+		}
+	}
+}
+
+/// Logs events and spans to stderr as filtered by `RUST_LOG`, records of the `log` crate included, and exports spans
+/// of level info and above over OTLP when an endpoint is configured, whatever `RUST_LOG` is
+pub fn init(config: &TelemetryConfig) -> Result<TelemetryGuard> {
+	// Only errors when `RUST_LOG` is unset, as with `pretty_env_logger`
+	let filter = EnvFilter::builder().with_default_directive(LevelFilter::ERROR.into()).from_env_lossy();
+	let otlp = otlp_layer(config)?;
+	let guard = TelemetryGuard { otlp: otlp.is_some() };
+
+	tracing_subscriber::registry()
+		.with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_filter(filter))
+		.with(otlp)
+		.try_init()?;
+	if let Some(endpoint) = &config.otlp_endpoint {
+		log::info!("Exporting spans of {} to {}", config.service_name, endpoint);
+	}
+	Ok(guard)
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>(config: &TelemetryConfig) -> Result<Option<impl Layer<S>>>
+where
+	S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>
+{
+	use opentelemetry::sdk::{trace, Resource};
+	use opentelemetry::KeyValue;
+	use opentelemetry_otlp::WithExportConfig;
+
+	let Some(endpoint) = &config.otlp_endpoint else {
+		return Ok(None);
+	};
+	let tracer = opentelemetry_otlp::new_pipeline()
+		.tracing()
+		.with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+		.with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", config.service_name.clone())])))
+		.install_batch(opentelemetry::runtime::Tokio)?;
+	Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer).with_filter(LevelFilter::INFO)))
+}
+
+// Configuration validation rejects an endpoint without the `otlp` feature
+#[cfg(not(feature = "otlp"))]
+fn otlp_layer(_config: &TelemetryConfig) -> Result<Option<tracing_subscriber::layer::Identity>> {
+	Ok(None)
+}
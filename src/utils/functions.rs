@@ -1,15 +1,16 @@
-use std::{env, str::FromStr};
+use std::path::Path;
+use std::str::FromStr;
 
 use ndarray::ArrayView1;
 use openai_api_rs::v1::chat_completion::{ChatCompletionMessage, MessageRole};
 use rayon::prelude::*;
+use tracing::Instrument;
 
-use crate::convrsation::data::RelevantChunk;
+use crate::convrsation::{data::RelevantChunk, settings::SearchSettings};
 use crate::{
-	constants::FILE_CHUNKER_CAPACITY_RANGE,
-	db::RepositoryEmbeddingsDB,
+	db::{DocumentLinks, RepositoryEmbeddingsDB, SearchFilter},
 	embeddings::{cosine_similarity, Embeddings, EmbeddingsModel},
-	fs::fetch_file_content,
+	fs::{chunker::chunk_markdown, fetch_document},
 	functions_enum,
 	prelude::*
 };
@@ -19,49 +20,61 @@ functions_enum! {
 	(SearchDocuments, "search_documents"),
 	(SearchFile, "search_file"),
 	(SearchPath, "search_path"),
+	(GetLinks, "get_links"),
 	(Done, "done"),
 }
 
+#[tracing::instrument(skip_all, fields(files = tracing::field::Empty, chunks = tracing::field::Empty))]
 pub async fn search_documents<M: EmbeddingsModel, D: RepositoryEmbeddingsDB>(
 	query: &str,
 	model: &M,
 	db: &D,
-	files_limit: usize,
-	chunks_limit: usize
+	filter: &SearchFilter,
+	search: &SearchSettings
 ) -> Result<Vec<RelevantChunk>> {
-	let query_embeddings = model.embed(query)?;
-	let relevant_files = db.get_relevant_files(query_embeddings, files_limit as f32).await?;
+	let query_embeddings = tracing::info_span!("embed_query").in_scope(|| model.embed(query))?;
+	let mut relevant_files = db
+		.get_relevant_files(query_embeddings, search.files_limit as f32, filter)
+		.instrument(tracing::info_span!("qdrant_search", limit = search.files_limit))
+		.await?;
+
+	// One hop along the links of the closest documents, in their order, for answers spread over linked pages
+	if search.linked_files_limit > 0 {
+		let mut linked_files: Vec<String> = Vec::new();
+		for path in &relevant_files {
+			let links = db.get_links(path, filter).instrument(tracing::info_span!("qdrant_links")).await?;
+			for link in links.outgoing {
+				if linked_files.len() < search.linked_files_limit && !relevant_files.contains(&link) && !linked_files.contains(&link) {
+					linked_files.push(link);
+				}
+			}
+		}
+		tracing::debug!("Following links to: {:?}", &linked_files);
+		relevant_files.extend(linked_files);
+	}
+	tracing::Span::current().record("files", relevant_files.len());
+
 	let mut relevant_chunks: Vec<RelevantChunk> = Vec::new();
 	for path in relevant_files {
-		let chunks = search_file(&path, query, model, chunks_limit).await?;
+		let chunks = search_file(&path, query, model, &search.documents_path, search.chunks_limit).await?;
 		relevant_chunks.extend(chunks);
 	}
+	tracing::Span::current().record("chunks", relevant_chunks.len());
 
 	Ok(relevant_chunks)
 }
 
-pub async fn search_file<M: EmbeddingsModel>(path: &str, query: &str, model: &M, chunks_limit: usize) -> Result<Vec<RelevantChunk>> {
-	// Get DOCUMENTS_BASE_PATH from the environment
-	let base_path = env::var("DOCUMENTS_BASE_PATH").unwrap_or("/content".to_string());
-
-	// Ensure path starts with DOCUMENTS_BASE_PATH
-	let full_path = if path.starts_with(&base_path) {
-		path.to_string()
-	} else {
-		log::debug!("Accessing inline document link: {}", path);
-		format!("{}{}", base_path, path)
-	};
-
-	let file_content = fetch_file_content((&full_path).into()).await.unwrap_or_default();
+#[tracing::instrument(skip(query, model, base_path), fields(chunks = tracing::field::Empty))]
+pub async fn search_file<M: EmbeddingsModel>(path: &str, query: &str, model: &M, base_path: &Path, chunks_limit: usize) -> Result<Vec<RelevantChunk>> {
+	let full_path = resolve_document_path(path, base_path);
+	let file_content = fetch_document(Path::new(&full_path)).await.unwrap_or_default();
 
-	let splitter = text_splitter::TextSplitter::default().with_trim_chunks(true);
+	// Split the same way as when indexing, each chunk carrying its heading breadcrumb
+	let chunks: Vec<String> = chunk_markdown(&file_content).iter().map(|chunk| chunk.to_string()).collect();
+	let chunks_embeddings: Vec<Embeddings> = tracing::info_span!("embed_chunks", count = chunks.len())
+		.in_scope(|| chunks.iter().map(|chunk| model.embed(chunk)).collect::<Result<_>>())?;
 
-	let chunks: Vec<&str> = splitter.chunks(&file_content, FILE_CHUNKER_CAPACITY_RANGE).collect();
-
-	let cleaned_chunks: Vec<String> = clean_chunks(chunks);
-	let chunks_embeddings: Vec<Embeddings> = cleaned_chunks.iter().map(|chunk| model.embed(chunk).unwrap()).collect();
-
-	let query_embeddings = model.embed(query)?;
+	let query_embeddings = tracing::info_span!("embed_query").in_scope(|| model.embed(query))?;
 
 	let similarities: Vec<f32> = similarity_score(chunks_embeddings, query_embeddings);
 
@@ -71,14 +84,27 @@ pub async fn search_file<M: EmbeddingsModel>(path: &str, query: &str, model: &M,
 		.iter()
 		.map(|index| RelevantChunk {
 			path: path.to_string(),
-			content: cleaned_chunks[*index].to_string()
+			content: chunks[*index].to_string(),
+			url: None
 		})
 		.collect();
+	tracing::Span::current().record("chunks", relevant_chunks.len());
 	Ok(relevant_chunks)
 }
 
-pub async fn search_path<D: RepositoryEmbeddingsDB>(path: &str, db: &D, limit: usize) -> Result<Vec<String>> {
-	let list = db.get_file_paths().await?;
+// Ensure path starts with the documents base path
+pub fn resolve_document_path(path: &str, base_path: &Path) -> String {
+	if path.starts_with(base_path.to_string_lossy().as_ref()) {
+		path.to_string()
+	} else {
+		tracing::debug!("Accessing inline document link: {}", path);
+		base_path.join(path.trim_start_matches('/')).to_string_lossy().to_string()
+	}
+}
+
+#[tracing::instrument(skip(db, filter))]
+pub async fn search_path<D: RepositoryEmbeddingsDB>(path: &str, db: &D, filter: &SearchFilter, limit: usize) -> Result<Vec<String>> {
+	let list = db.get_file_paths(filter).instrument(tracing::info_span!("qdrant_scroll")).await?;
 	let file_paths: Vec<&str> = list.iter().map(String::as_ref).collect();
 	let response: Vec<(&str, f32)> = rust_fuzzy_search::fuzzy_search_best_n(path, &file_paths, limit);
 	let file_paths = response.iter().map(|(path, _)| path.to_string()).collect::<Vec<String>>();
@@ -96,6 +122,23 @@ pub fn paths_to_completion_message(function_name: Function, paths: Vec<String>)
 	}
 }
 
+pub fn links_to_completion_message(function_name: Function, path: &str, links: DocumentLinks) -> ChatCompletionMessage {
+	let content = format!(
+		"Links from {}: {}\nLinks to {}: {}",
+		path,
+		links.outgoing.join(", "),
+		path,
+		links.incoming.join(", ")
+	);
+
+	ChatCompletionMessage {
+		name: Some(function_name.to_string()),
+		role: MessageRole::function,
+		content,
+		function_call: None
+	}
+}
+
 pub fn relevant_chunks_to_completion_message(function_name: Function, relevant_chunks: Vec<RelevantChunk>) -> ChatCompletionMessage {
 	let chunks = relevant_chunks
 		.iter()
@@ -111,11 +154,6 @@ pub fn relevant_chunks_to_completion_message(function_name: Function, relevant_c
 	}
 }
 
-// Remove extra whitespaces from chunks
-fn clean_chunks(chunks: Vec<&str>) -> Vec<String> {
-	chunks.iter().map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" ")).collect()
-}
-
 // Compute cosine similarity between query and file content chunks
 fn similarity_score(files_embeddings: Vec<Embeddings>, query_embeddings: Embeddings) -> Vec<f32> {
 	files_embeddings
@@ -1,8 +1,15 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
+use sha2::{Digest, Sha256};
+
 pub fn calculate_hash(input: &str) -> u64 {
 	let mut hasher = DefaultHasher::new();
 	input.hash(&mut hasher);
 	hasher.finish()
 }
+
+/// Hex encoded SHA-256 digest, stable across processes and Rust releases unlike `calculate_hash`
+pub fn sha256_hex(input: &str) -> String {
+	format!("{:x}", Sha256::digest(input.as_bytes()))
+}
@@ -2,39 +2,60 @@
 /// Example usage
 // sse_events! {
 //    QueryEvent,
-//    (ProcessQuery, "PROCESS_QUERY"),
-//    (SearchCodebase, "SEARCH_CODEBASE"),
-//    (SearchFile, "SEARCH_FILE"),
-//    (SearchPath, "SEARCH_PATH"),
+//    (ProcessQuery, "PROCESS_QUERY", ProcessQueryPayload),
+//    (SearchCodebase, "SEARCH_CODEBASE", SearchCodebasePayload),
+//    (SearchFile, "SEARCH_FILE", SearchFilePayload),
+//    (SearchPath, "SEARCH_PATH", SearchPathPayload),
 //    (GenerateResponse, "GENERATE_RESPONSE"),
-//    (Done, "DONE"),
-//    (Error, "ERROR"),
+//    (Done, "DONE", AnswerPayload),
+//    (Error, "ERROR", ErrorPayload),
 // }
-// tx.send(EmbedEvent::QueryEvent(Some(json!({
-//         "files": files.len(),
-//          }))).into())
+// tx.send(QueryEvent::SearchPath(SearchPathPayload { path })).await
+// QueryEvent::catalogue(); // Event names with the name of their payload schema
 ///
 #[macro_export]
 macro_rules!  sse_events {
-    ($name:ident, $(($key:ident, $value:expr),)*) => {
-       #[derive(Debug, PartialEq)]
+    (@payload) => { None };
+    (@payload $payload:ident) => { Some(stringify!($payload)) };
+    ($name:ident, $(($key:ident, $value:expr $(, $payload:ident)?),)*) => {
+       // Untagged, so that an event serialises as its payload, or `null` without one
+       #[derive(Debug, PartialEq, Clone, serde::Serialize)]
+       #[serde(untagged)]
        pub enum $name
         {
-            $($key(Option<serde_json::Value>)),*
+            $($key $(($payload))?),*
         }
 
         impl From<$name> for Data {
             fn from(event: $name) -> Data {
-                match event {
+                let name = event.name();
+                Data::new(serde_json::to_value(&event).unwrap_or_default().to_string()).event(name)
+            }
+        }
+
+        impl $name {
+            pub fn name(&self) -> &'static str {
+                match self {
                     $(
-                        $name::$key(data) => Data::new(data.unwrap_or_default().to_string()).event($value)
+                        $name::$key { .. } => $value
                     ),*
                 }
             }
-        }
 
-        impl $name {
+            pub fn into_data(self) -> Option<serde_json::Value> {
+                serde_json::to_value(&self).ok().filter(|data| !data.is_null())
+            }
 
+            pub fn catalogue() -> Vec<EventDescriptor> {
+                vec![
+                    $(
+                        EventDescriptor {
+                            name: $value,
+                            payload: $crate::sse_events!(@payload $($payload)?)
+                        }
+                    ),*
+                ]
+            }
         }
 
     }
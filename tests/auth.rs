@@ -0,0 +1,189 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+use actix_web_lab::middleware::from_fn;
+use ircc_ai::config::{AuthConfig, Secret};
+use ircc_ai::routes::auth::{self, AccessGuard};
+
+const CLIENT: &str = "203.0.113.7:41000";
+const PROXY: &str = "10.0.0.2:41000";
+
+// A keys file that does not exist, so that only `api_keys` count
+fn no_keys_file() -> PathBuf {
+	std::env::temp_dir().join(format!("ircc-ai-no-api-keys-{}", std::process::id()))
+}
+
+fn config(keys: &[&str]) -> AuthConfig {
+	AuthConfig {
+		api_keys: keys.iter().map(|key| Secret::new(*key)).collect(),
+		api_keys_file: no_keys_file(),
+		..AuthConfig::default()
+	}
+}
+
+macro_rules! app {
+	($config:expr) => {
+		test::init_service(
+			App::new()
+				.app_data(web::Data::new(Arc::new(AccessGuard::new(&$config).unwrap())))
+				.wrap(from_fn(auth::guard))
+				.route("/query", web::get().to(|| async { HttpResponse::Ok() }))
+				.route("/ws", web::get().to(|| async { HttpResponse::Ok() }))
+				.route("/health", web::get().to(|| async { HttpResponse::Ok() }))
+		)
+		.await
+	};
+}
+
+fn get(uri: &str, peer: &str) -> test::TestRequest {
+	test::TestRequest::get().uri(uri).peer_addr(peer.parse::<SocketAddr>().unwrap())
+}
+
+#[test]
+fn refuses_to_start_without_keys() {
+	assert!(AccessGuard::new(&config(&[])).is_err());
+	assert!(AccessGuard::new(&config(&["  "])).is_err());
+	assert!(AccessGuard::new(&AuthConfig {
+		disabled: true,
+		..config(&[])
+	})
+	.is_ok());
+}
+
+#[actix_web::test]
+async fn reads_keys_from_the_keys_file() {
+	let keys_file = std::env::temp_dir().join(format!("ircc-ai-api-keys-{}", std::process::id()));
+	std::fs::write(&keys_file, "# clients\n\nfile-key partner\nunnamed-key\n").unwrap();
+	let config = AuthConfig {
+		api_keys_file: keys_file.clone(),
+		..config(&[])
+	};
+	let app = app!(config);
+	std::fs::remove_file(&keys_file).unwrap();
+
+	for key in ["file-key", "unnamed-key"] {
+		let response = test::call_service(&app, get("/query", CLIENT).insert_header(("x-api-key", key)).to_request()).await;
+		assert_eq!(response.status(), StatusCode::OK, "{}", key);
+	}
+	let response = test::call_service(&app, get("/query", CLIENT).insert_header(("x-api-key", "# clients")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn requires_a_valid_key() {
+	let app = app!(config(&["secret-key"]));
+
+	let response = test::call_service(&app, get("/query", CLIENT).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	let body: serde_json::Value = test::read_body_json(response).await;
+	assert_eq!(body["error"], "missing_api_key");
+
+	let response = test::call_service(&app, get("/query", CLIENT).insert_header(("x-api-key", "wrong-key")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	let body: serde_json::Value = test::read_body_json(response).await;
+	assert_eq!(body["error"], "invalid_api_key");
+
+	let response = test::call_service(&app, get("/query", CLIENT).insert_header(("x-api-key", "secret-key")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let response = test::call_service(&app, get("/query", CLIENT).insert_header(("authorization", "Bearer secret-key")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+
+	// Public routes need no key
+	let response = test::call_service(&app, get("/health", CLIENT).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn accepts_keys_in_the_url_on_websockets_only() {
+	let app = app!(config(&["secret-key"]));
+
+	let response = test::call_service(&app, get("/ws?api_key=secret-key", CLIENT).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let response = test::call_service(&app, get("/query?api_key=secret-key", CLIENT).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn limits_requests_per_key() {
+	let app = app!(AuthConfig {
+		rate_limit_per_key_per_minute: 1,
+		..config(&["secret-key"])
+	});
+
+	let response = test::call_service(&app, get("/query", CLIENT).insert_header(("x-api-key", "secret-key")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	// A new address does not reset the limit of the key
+	let response = test::call_service(&app, get("/query", "198.51.100.1:41000").insert_header(("x-api-key", "secret-key")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+	assert!(response.headers().contains_key("retry-after"));
+	let body: serde_json::Value = test::read_body_json(response).await;
+	assert_eq!(body["error"], "rate_limited");
+}
+
+#[actix_web::test]
+async fn limits_requests_per_ip_even_when_authentication_is_disabled() {
+	let app = app!(AuthConfig {
+		disabled: true,
+		rate_limit_per_ip_per_minute: 1,
+		..config(&[])
+	});
+
+	let response = test::call_service(&app, get("/query", CLIENT).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let response = test::call_service(&app, get("/query", CLIENT).to_request()).await;
+	assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn ignores_forwarded_addresses_from_untrusted_peers() {
+	let app = app!(AuthConfig {
+		disabled: true,
+		rate_limit_per_ip_per_minute: 1,
+		..config(&[])
+	});
+
+	// Changing `X-Forwarded-For` on each request does not escape the limit of the peer
+	for (forwarded, status) in [("192.0.2.1", StatusCode::OK), ("192.0.2.2", StatusCode::TOO_MANY_REQUESTS)] {
+		let response = test::call_service(&app, get("/query", CLIENT).insert_header(("x-forwarded-for", forwarded)).to_request()).await;
+		assert_eq!(response.status(), status, "{}", forwarded);
+	}
+}
+
+#[actix_web::test]
+async fn uses_forwarded_addresses_from_trusted_proxies() {
+	let app = app!(AuthConfig {
+		disabled: true,
+		rate_limit_per_ip_per_minute: 1,
+		trusted_proxies: vec!["10.0.0.2".parse().unwrap()],
+		..config(&[])
+	});
+
+	// Each client behind the proxy has a limit of its own
+	for forwarded in ["192.0.2.1", "192.0.2.2"] {
+		let response = test::call_service(&app, get("/query", PROXY).insert_header(("x-forwarded-for", forwarded)).to_request()).await;
+		assert_eq!(response.status(), StatusCode::OK, "{}", forwarded);
+	}
+	let response = test::call_service(&app, get("/query", PROXY).insert_header(("x-forwarded-for", "192.0.2.1")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[test]
+fn charges_the_daily_quota_of_the_key() {
+	let access_guard = AccessGuard::new(&AuthConfig {
+		daily_quota_per_key: 2,
+		..config(&["secret-key"])
+	})
+	.unwrap();
+
+	// As a WebSocket session does for each question
+	assert!(access_guard.charge(Some("secret-key"), "203.0.113.7").is_ok());
+	assert!(access_guard.charge(Some("secret-key"), "203.0.113.8").is_ok());
+	let exceeded = access_guard.charge(Some("secret-key"), "203.0.113.9").unwrap_err();
+	assert_eq!(exceeded.error, "quota_exceeded");
+	assert!(exceeded.retry_after > 0 && exceeded.retry_after <= 24 * 60 * 60);
+
+	// Without a key, only the limit of the address applies
+	assert!(access_guard.charge(None, "203.0.113.10").is_ok());
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ircc_ai::convrsation::cache::{AnswerCache, CachedAnswer};
+use ircc_ai::db::{DocumentLinks, RepositoryEmbeddingsDB, SearchFilter};
+use ircc_ai::embeddings::{Embeddings, EmbeddingsModel};
+use ircc_ai::fs::FileEmbeddings;
+use ircc_ai::prelude::*;
+use ircc_ai::routes::events::{AnswerPayload, QueryEvent};
+
+const TTL: Duration = Duration::from_secs(60);
+
+fn answer(text: &str) -> CachedAnswer {
+	CachedAnswer {
+		events: vec![QueryEvent::GenerateResponse, QueryEvent::Done(AnswerPayload(text.to_string()))],
+		retrieved_paths: vec!["en/express-entry.md".to_string()],
+		answer: text.to_string()
+	}
+}
+
+fn cached(cache: &AnswerCache, query: &str, index_version: &str, query_embeddings: Option<&Embeddings>) -> Option<String> {
+	cache.lookup(query, index_version, query_embeddings).map(|answer| answer.answer)
+}
+
+// Counts how often the index version is read
+#[derive(Default)]
+struct CountingDb {
+	index_version_reads: AtomicUsize
+}
+
+#[async_trait]
+impl RepositoryEmbeddingsDB for CountingDb {
+	async fn create_collection(&self) -> Result<()> {
+		Ok(())
+	}
+
+	async fn insert_embeddings(&self, _embeddings: Vec<FileEmbeddings>) -> Result<()> {
+		Ok(())
+	}
+
+	async fn delete_embeddings(&self, _paths: &[String]) -> Result<()> {
+		Ok(())
+	}
+
+	async fn get_relevant_files(&self, _query_embeddings: Embeddings, _limit: f32, _filter: &SearchFilter) -> Result<Vec<String>> {
+		Ok(Vec::new())
+	}
+
+	async fn get_file_paths(&self, _filter: &SearchFilter) -> Result<Vec<String>> {
+		Ok(Vec::new())
+	}
+
+	async fn get_links(&self, _path: &str, _filter: &SearchFilter) -> Result<DocumentLinks> {
+		Ok(DocumentLinks::default())
+	}
+
+	async fn delete_collection(&self) -> Result<()> {
+		Ok(())
+	}
+
+	async fn is_indexed(&self) -> Result<bool> {
+		Ok(true)
+	}
+
+	async fn count_points(&self) -> Result<usize> {
+		Ok(0)
+	}
+
+	async fn index_version(&self) -> Result<String> {
+		let reads = self.index_version_reads.fetch_add(1, Ordering::SeqCst);
+		Ok(format!("version-{}", reads))
+	}
+
+	async fn write_index_version(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+struct WordsModel;
+
+impl EmbeddingsModel for WordsModel {
+	fn embed(&self, string: &str) -> Result<Embeddings> {
+		Ok(["express", "entry", "study", "permit"].iter().map(|word| string.matches(word).count() as f32).collect())
+	}
+}
+
+#[test]
+fn exact_queries_hit_regardless_of_case_and_punctuation() {
+	let cache = AnswerCache::new(TTL, 10, None);
+	cache.insert("How do I apply for Express Entry?", "v1", None, answer("Create a profile."));
+
+	assert_eq!(cached(&cache, "  how do i APPLY for express entry", "v1", None).as_deref(), Some("Create a profile."));
+	assert_eq!(cached(&cache, "How do I apply for a study permit?", "v1", None), None);
+	// A different index version, or profile, does not share the answer
+	assert_eq!(cached(&cache, "How do I apply for Express Entry?", "v2", None), None);
+}
+
+#[test]
+fn empty_answers_are_not_cached() {
+	let cache = AnswerCache::new(TTL, 10, None);
+	cache.insert("How do I apply?", "v1", None, answer(""));
+
+	assert_eq!(cached(&cache, "How do I apply?", "v1", None), None);
+}
+
+#[test]
+fn disabled_cache_keeps_nothing() {
+	for cache in [AnswerCache::new(Duration::ZERO, 10, None), AnswerCache::new(TTL, 0, None)] {
+		assert!(!cache.is_enabled());
+		cache.insert("How do I apply?", "v1", None, answer("Online."));
+		assert_eq!(cached(&cache, "How do I apply?", "v1", None), None);
+	}
+}
+
+#[test]
+fn entries_expire() {
+	let cache = AnswerCache::new(Duration::from_millis(50), 10, None);
+	cache.insert("How do I apply?", "v1", None, answer("Online."));
+	assert!(cached(&cache, "How do I apply?", "v1", None).is_some());
+
+	std::thread::sleep(Duration::from_millis(100));
+	assert_eq!(cached(&cache, "How do I apply?", "v1", None), None);
+}
+
+#[test]
+fn oldest_entries_are_evicted() {
+	let cache = AnswerCache::new(TTL, 2, None);
+	cache.insert("first", "v1", None, answer("1"));
+	cache.insert("second", "v1", None, answer("2"));
+	// Inserting an entry again makes it the newest
+	cache.insert("first", "v1", None, answer("1 again"));
+	cache.insert("third", "v1", None, answer("3"));
+
+	assert_eq!(cached(&cache, "second", "v1", None), None);
+	assert_eq!(cached(&cache, "first", "v1", None).as_deref(), Some("1 again"));
+	assert_eq!(cached(&cache, "third", "v1", None).as_deref(), Some("3"));
+}
+
+#[tokio::test]
+async fn similar_queries_share_an_answer_above_the_threshold() {
+	let cache = AnswerCache::new(TTL, 10, Some(0.9));
+	let model = Arc::new(WordsModel);
+
+	let stored = cache.query_embeddings("express entry", model.clone()).await.unwrap();
+	cache.insert("express entry", "v1", stored, answer("Create a profile."));
+
+	let similar = cache.query_embeddings("express entry, express entry please", model.clone()).await.unwrap();
+	assert_eq!(cached(&cache, "express entry, express entry please", "v1", similar.as_ref()).as_deref(), Some("Create a profile."));
+	assert_eq!(cached(&cache, "express entry, express entry please", "v2", similar.as_ref()), None);
+	let different = cache.query_embeddings("study permit", model).await.unwrap();
+	assert_eq!(cached(&cache, "study permit", "v1", different.as_ref()), None);
+}
+
+#[tokio::test]
+async fn queries_are_only_embedded_with_a_threshold() {
+	let cache = AnswerCache::new(TTL, 10, None);
+
+	assert_eq!(cache.query_embeddings("express entry", Arc::new(WordsModel)).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn index_versions_are_read_once_per_refresh() {
+	let cache = AnswerCache::new(TTL, 10, None);
+	let db = CountingDb::default();
+
+	assert_eq!(cache.index_version("ircc", &db).await.unwrap(), "version-0");
+	assert_eq!(cache.index_version("ircc", &db).await.unwrap(), "version-0");
+	// Each profile has its own index
+	assert_eq!(cache.index_version("other", &db).await.unwrap(), "version-1");
+	assert_eq!(db.index_version_reads.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,35 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory of its own for each test, removed with everything in it once dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+	pub fn new(name: &str) -> TempDir {
+		let dir = std::env::temp_dir().join(format!("ircc-ai-{}-{}", name, std::process::id()));
+		// Left behind by a run that panicked
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		TempDir(dir)
+	}
+}
+
+impl Deref for TempDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.0);
+	}
+}
+
+impl AsRef<Path> for TempDir {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
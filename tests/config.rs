@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use ircc_ai::config::{Binary, Config, ConfigArgs, ProfileConfig, Secret};
+
+use self::common::TempDir;
+
+mod common;
+
+fn profile(name: &str, collection: &str) -> ProfileConfig {
+	ProfileConfig {
+		name: name.into(),
+		collection: collection.into(),
+		documents_path: "/content".into(),
+		domain: "immigration to Canada".into(),
+		agency_name: "IRCC".into(),
+		site_base_url: "https://www.canada.ca".into(),
+		strip_suffix: None,
+		languages: vec!["English".into()]
+	}
+}
+
+#[test]
+fn example_configuration_shows_the_defaults() {
+	let example = Config::from_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml")).unwrap();
+
+	assert_eq!(example.to_redacted_toml().unwrap(), Config::default().to_redacted_toml().unwrap());
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+	let dir = TempDir::new("config-unknown-key");
+	let path = dir.join("config.toml");
+	std::fs::write(&path, "[server]\nprot = 3000\n").unwrap();
+	let error = Config::from_file(&path).unwrap_err().to_string();
+
+	assert!(error.starts_with(&format!("Failed to parse {}", path.display())), "{}", error);
+	assert!(error.contains("prot"), "{}", error);
+}
+
+// The only test reading the environment, as the variables are shared by every test of the binary
+#[test]
+fn environment_overrides_the_file_and_flags_override_both() {
+	let dir = TempDir::new("config-layers");
+	let path = dir.join("config.toml");
+	std::fs::write(&path, "[qdrant]\ncollection = \"File\"\n\n[server]\nhost = \"127.0.0.1\"\nport = 3500\n").unwrap();
+	let args = ConfigArgs {
+		config: Some(path.clone()),
+		port: Some(5000),
+		..ConfigArgs::default()
+	};
+
+	std::env::set_var("QDRANT_COLLECTION", "Env");
+	std::env::set_var("WEBSERVER_PORT", "4000");
+	// Empty variables count as unset
+	std::env::set_var("WEBSERVER_HOST", "");
+	std::env::set_var("API_KEYS", "first-key, ,second-key");
+	let config = Config::load(&args);
+	std::env::set_var("LLM_MAX_RETRIES", "many");
+	let invalid = Config::load(&args);
+	for name in ["QDRANT_COLLECTION", "WEBSERVER_PORT", "WEBSERVER_HOST", "API_KEYS", "LLM_MAX_RETRIES"] {
+		std::env::remove_var(name);
+	}
+
+	let config = config.unwrap();
+	assert_eq!(config.qdrant.collection, "Env");
+	assert_eq!(config.server.host, "127.0.0.1");
+	assert_eq!(config.server.port, 5000);
+	assert_eq!(config.auth.api_keys.iter().map(Secret::expose).collect::<Vec<_>>(), vec!["first-key", "second-key"]);
+	assert_eq!(invalid.unwrap_err().to_string(), "Invalid LLM_MAX_RETRIES: many");
+}
+
+#[test]
+fn secrets_are_redacted() {
+	let mut config = Config::default();
+	config.openai.api_key = Some(Secret::new("sk-openai"));
+	config.auth.api_keys = vec![Secret::new("client-key")];
+	config.bot.telegram_token = Some(Secret::new("telegram-token"));
+
+	let toml = config.to_redacted_toml().unwrap();
+	let debug = format!("{:?}", config);
+	for secret in ["sk-openai", "client-key", "telegram-token"] {
+		assert!(!toml.contains(secret), "{}", toml);
+		assert!(!debug.contains(secret), "{}", debug);
+	}
+}
+
+#[test]
+fn default_profile_is_described_by_the_top_level_settings() {
+	let mut config = Config::default();
+	config.qdrant.collection = "CRA".into();
+	config.documents.path = "/content/cra".into();
+	config.prompts.language = "French".into();
+
+	let profiles = config.profiles();
+	assert_eq!(profiles.len(), 1);
+	assert_eq!((profiles[0].name.as_str(), profiles[0].collection.as_str()), ("default", "CRA"));
+	assert_eq!(profiles[0].documents_path, PathBuf::from("/content/cra"));
+	assert_eq!(profiles[0].languages, vec!["French".to_string()]);
+
+	config.profiles = vec![profile("ircc", "IRCC")];
+	assert_eq!(config.profiles().iter().map(|profile| profile.name.as_str()).collect::<Vec<_>>(), vec!["ircc"]);
+}
+
+#[test]
+fn validation_reports_every_problem() {
+	let dir = TempDir::new("config-validation");
+	let mut config = Config::default();
+	config.model.path = dir.to_path_buf();
+	config.prompts.path = dir.to_path_buf();
+	config.profiles = vec![
+		ProfileConfig {
+			documents_path: dir.to_path_buf(),
+			..profile("ircc", "IRCC")
+		},
+		ProfileConfig {
+			documents_path: dir.to_path_buf(),
+			languages: Vec::new(),
+			..profile("ircc", "IRCC")
+		}
+	];
+	config.server.port = 0;
+	config.chat.answer.temperature = 3.0;
+
+	let errors = config.validate(Binary::Oracle).unwrap_err();
+	config.profiles.truncate(1);
+	config.server.port = 3000;
+	config.chat.answer.temperature = 0.7;
+	config.openai.api_key = Some(Secret::new("sk-openai"));
+	let fixed = config.validate(Binary::Oracle);
+
+	for expected in [
+		"profile name ircc is used more than once",
+		"profile ircc shares collection IRCC with another profile",
+		"profile ircc must list at least one language",
+		"server.port must not be 0",
+		"chat.answer.temperature must be between 0 and 2",
+		"openai.api_key (OPENAI_API_KEY) is required"
+	] {
+		assert!(errors.iter().any(|error| error == expected), "{} not in {:?}", expected, errors);
+	}
+	assert_eq!(fixed, Ok(()));
+}
+
+#[test]
+fn each_binary_requires_its_own_settings() {
+	let mut config = Config::default();
+	config.crawl.start_url = "www.canada.ca".into();
+
+	let crawl = config.validate(Binary::Crawl).unwrap_err();
+	assert_eq!(crawl, vec!["crawl.start_url must be an http(s) URL, got \"www.canada.ca\"".to_string()]);
+
+	let bot = config.validate(Binary::Bot).unwrap_err();
+	assert_eq!(bot, vec!["bot.telegram_token (TELOXIDE_TOKEN) is required".to_string()]);
+}
@@ -0,0 +1,153 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ircc_ai::config::CrawlConfig;
+use ircc_ai::constants::CRAWL_MAX_DELAY_SECS;
+use ircc_ai::crawl::robots::Robots;
+use ircc_ai::crawl::Crawler;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use self::common::TempDir;
+
+mod common;
+
+fn site_path() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/site")
+}
+
+// Serves the files under `root` over plain HTTP, one request per connection, as `python3 -m http.server` would
+async fn serve(root: PathBuf) -> SocketAddr {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let address = listener.local_addr().unwrap();
+	tokio::spawn(async move {
+		while let Ok((mut stream, _)) = listener.accept().await {
+			let root = root.clone();
+			tokio::spawn(async move {
+				let mut request = Vec::new();
+				let mut buffer = [0; 1024];
+				while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+					match stream.read(&mut buffer).await {
+						Ok(0) | Err(_) => return,
+						Ok(read) => request.extend_from_slice(&buffer[..read])
+					}
+				}
+
+				let request = String::from_utf8_lossy(&request);
+				let target = request.split_whitespace().nth(1).unwrap_or("/");
+				let path = target.split('?').next().unwrap_or_default();
+				let mut file = root.join(path.trim_start_matches('/'));
+				if path.ends_with('/') {
+					file.push("index.html");
+				}
+
+				let response = match fs::read(&file).await {
+					Ok(body) => {
+						let is_html = file.extension().is_some_and(|extension| extension == "html");
+						let content_type = if is_html { "text/html; charset=utf-8" } else { "text/plain" };
+						let head = format!(
+							"HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+							content_type,
+							body.len()
+						);
+						[head.into_bytes(), body].concat()
+					}
+					Err(_) => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+				};
+				let _ = stream.write_all(&response).await;
+			});
+		}
+	});
+	address
+}
+
+#[tokio::test]
+async fn crawl_mirrors_the_site_as_markdown() {
+	let address = serve(site_path()).await;
+	let output = TempDir::new("crawl");
+	let config = CrawlConfig {
+		start_url: format!("http://{}/en/index.html", address),
+		allowed_prefixes: vec![format!("http://{}/en/", address)],
+		delay_ms: 0,
+		..CrawlConfig::default()
+	};
+
+	let summary = Crawler::new(&config, &output).unwrap().run().await.unwrap();
+	let index = fs::read_to_string(output.join("en/index.md")).await;
+	let express_entry = fs::read_to_string(output.join("en/express-entry.md")).await;
+	let study = fs::read_to_string(output.join("en/study/index.md")).await;
+	let drafts_exists = output.join("en/private/drafts.md").exists();
+	let public_exists = output.join("en/private/public.md").exists();
+	let french_exists = output.join("fr/index.md").exists();
+
+	assert_eq!((summary.written, summary.skipped, summary.failed), (4, 1, 0));
+
+	// Front matter
+	let index = index.unwrap();
+	let front_matter = format!("---\ntitle: Immigrate to Canada\nsource_url: http://{}/en/index.html\nfetched_at: ", address);
+	assert!(index.starts_with(&front_matter), "{}", index);
+	assert!(index.contains("\n---\n\n"), "{}", index);
+	// The navigation outside `<main>` is left out
+	assert!(!index.contains("Français"), "{}", index);
+
+	// Crawled pages point to their documents, without the query but with the fragment
+	assert!(index.contains("(/en/express-entry.md#eligibility)"), "{}", index);
+	assert!(index.contains("(/en/study/index.md)"), "{}", index);
+	// Pages outside the crawl and files that are not pages become absolute URLs
+	assert!(index.contains(&format!("(http://{}/fr/index.html)", address)), "{}", index);
+	assert!(index.contains(&format!("(http://{}/en/guide.pdf)", address)), "{}", index);
+	assert!(index.contains("(https://www.example.com/settle)"), "{}", index);
+	assert!(express_entry.unwrap().contains("(/en/index.md#top)"));
+	assert!(study.unwrap().contains("(/en/express-entry.md)"));
+
+	// robots.txt disallows the private directory but for one page, and the French pages are not allowed
+	assert!(!drafts_exists);
+	assert!(public_exists);
+	assert!(!french_exists);
+}
+
+#[tokio::test]
+async fn crawl_ignores_robots_txt_when_told_to() {
+	let address = serve(site_path()).await;
+	let output = TempDir::new("crawl-ignoring-robots");
+	let config = CrawlConfig {
+		start_url: format!("http://{}/en/index.html", address),
+		allowed_prefixes: vec![format!("http://{}/en/", address)],
+		delay_ms: 0,
+		respect_robots_txt: false,
+		..CrawlConfig::default()
+	};
+
+	let summary = Crawler::new(&config, &output).unwrap().run().await.unwrap();
+	let drafts_exists = output.join("en/private/drafts.md").exists();
+
+	assert_eq!((summary.written, summary.skipped, summary.failed), (5, 0, 0));
+	assert!(drafts_exists);
+}
+
+#[test]
+fn robots_applies_the_group_of_the_crawler() {
+	let content = std::fs::read_to_string(site_path().join("robots.txt")).unwrap();
+
+	let robots = Robots::parse(&content, "ircc-ai-crawler/1.0");
+	assert!(robots.allows("/en/index.html"));
+	assert!(!robots.allows("/en/private/drafts.html"));
+	assert!(robots.allows("/en/private/public.html"));
+
+	let robots = Robots::parse(&content, "other-crawler");
+	assert!(!robots.allows("/en/index.html"));
+}
+
+#[test]
+fn robots_crawl_delay_is_capped() {
+	let crawl_delay = |value: &str| Robots::parse(&format!("User-agent: *\nCrawl-delay: {}\n", value), "ircc-ai-crawler").crawl_delay;
+
+	assert_eq!(crawl_delay("2.5"), Some(Duration::from_millis(2500)));
+	assert_eq!(crawl_delay("1e30"), Some(Duration::from_secs_f64(CRAWL_MAX_DELAY_SECS)));
+	assert_eq!(crawl_delay("inf"), Some(Duration::from_secs_f64(CRAWL_MAX_DELAY_SECS)));
+	assert_eq!(crawl_delay("-1"), None);
+	assert_eq!(crawl_delay("NaN"), None);
+	assert_eq!(crawl_delay("soon"), None);
+}
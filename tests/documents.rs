@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::StreamExt;
+use ircc_ai::config::DocumentsConfig;
+use ircc_ai::embeddings::TokenizerError;
+use ircc_ai::fs::checkpoint::Checkpoint;
+use ircc_ai::fs::chunker::chunk_markdown_with_capacity;
+use ircc_ai::fs::links::extract_links;
+use ircc_ai::fs::loader::html_to_markdown;
+use ircc_ai::fs::metadata::DocumentMetadata;
+use ircc_ai::fs::progress::IndexProgress;
+use ircc_ai::fs::report::{EmbedReport, FailureCategory, FailureThresholds, FileFailure, ProfileReport};
+use ircc_ai::fs::rules::{IndexRules, ListedFile};
+use ircc_ai::fs::SkipReason;
+
+use self::common::TempDir;
+
+mod common;
+
+fn write(path: &Path, content: &str) {
+	std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+	std::fs::write(path, content).unwrap();
+}
+
+fn sorted(paths: &HashSet<String>) -> Vec<String> {
+	let mut paths: Vec<String> = paths.iter().cloned().collect();
+	paths.sort();
+	paths
+}
+
+#[test]
+fn chunks_follow_the_headings() {
+	let content = "---\ntitle: Express Entry\n---\n\n# Express Entry\n\nIntro.\n\n## Who can apply\n\nSkilled workers.\n\n\
+	               ### Federal Skilled Worker\n\nDetails.\n\n## How to apply\n\n- Create a profile\n- Wait for an invitation\n";
+
+	let chunks: Vec<(Vec<String>, String)> = chunk_markdown_with_capacity(content, 200)
+		.into_iter()
+		.map(|chunk| (chunk.breadcrumb, chunk.text))
+		.collect();
+
+	let breadcrumb = |headings: &[&str]| headings.iter().map(|heading| heading.to_string()).collect::<Vec<_>>();
+	assert_eq!(
+		chunks,
+		vec![
+			(breadcrumb(&["Express Entry"]), "Intro.".to_string()),
+			(breadcrumb(&["Express Entry", "Who can apply"]), "Skilled workers.".to_string()),
+			(breadcrumb(&["Express Entry", "Who can apply", "Federal Skilled Worker"]), "Details.".to_string()),
+			(breadcrumb(&["Express Entry", "How to apply"]), "- Create a profile\n- Wait for an invitation".to_string())
+		]
+	);
+}
+
+#[test]
+fn chunks_split_tables_between_rows_with_the_header() {
+	let header = "| Program | Processing time |\n|---|---|";
+	let rows = ["| Express Entry | 6 months |", "| Family sponsorship | 12 months |", "| Study permit | 8 weeks |", "| Work permit | 10 weeks |"];
+	let content = format!("{}\n{}\n", header, rows.join("\n"));
+
+	let chunks = chunk_markdown_with_capacity(&content, 60);
+
+	assert_eq!(chunks.len(), rows.len());
+	for (chunk, row) in chunks.iter().zip(rows) {
+		assert_eq!(chunk.text, format!("{}\n{}", header, row));
+	}
+}
+
+#[test]
+fn chunk_sizes_are_counted_in_characters() {
+	// 25 characters, but 50 bytes
+	let paragraph = "é".repeat(25);
+	let content = format!("{}\n\n{}\n", paragraph, paragraph);
+
+	let chunks = chunk_markdown_with_capacity(&content, 60);
+
+	assert_eq!(chunks.len(), 1);
+	assert_eq!(chunks[0].text, format!("{}\n\n{}", paragraph, paragraph));
+}
+
+#[test]
+fn links_resolve_to_local_documents() {
+	let base = Path::new("/docs");
+	let content = "[Apply](apply.html#steps) [Study](../fr/etudier.md?x=1) [Site](https://www.canada.ca/en.html) [Logo](/images/logo.png) \
+	               [Outside](../../etc/passwd.md) [Itself](express-entry.md) [Again](apply.html)";
+
+	let links = extract_links(Path::new("/docs/en/express-entry.md"), base, content);
+	assert_eq!(links, vec!["/docs/en/apply.md".to_string(), "/docs/fr/etudier.md".to_string()]);
+
+	// Pages of a raw HTML mirror link to the pages themselves
+	let links = extract_links(Path::new("/docs/en/express-entry.html"), base, "[Apply](/en/apply.html)");
+	assert_eq!(links, vec!["/docs/en/apply.html".to_string()]);
+}
+
+#[test]
+fn metadata_comes_from_the_front_matter_and_the_path() {
+	let content = "---\ntitle: \"Express Entry\"\nlast_modified: 2023-10-02\n---\n\n# Ignored heading\n\nSee [apply](apply.md).\n";
+
+	let metadata = DocumentMetadata::extract(Path::new("/docs/en/immigration/express-entry.md"), Path::new("/docs"), content, None);
+
+	assert_eq!(metadata.title.as_deref(), Some("Express Entry"));
+	assert_eq!(metadata.language.as_deref(), Some("en"));
+	assert_eq!(metadata.section.as_deref(), Some("immigration"));
+	assert_eq!(metadata.last_modified.map(|date| date.to_rfc3339()).as_deref(), Some("2023-10-02T00:00:00+00:00"));
+	assert_eq!(metadata.links, vec!["/docs/en/immigration/apply.md".to_string()]);
+}
+
+#[test]
+fn html_keeps_the_main_content_and_the_title() {
+	let html = "<html><head><title>\n  Express\n  Entry </title><script>var tracking = 1;</script></head>\
+	            <body><nav>Menu</nav><main><h2>Who can apply</h2><p>Skilled workers.</p><footer>Footer</footer></main></body></html>";
+
+	let (title, markdown) = html_to_markdown(html);
+
+	assert_eq!(title.as_deref(), Some("Express Entry"));
+	assert!(markdown.contains("Who can apply"), "{}", markdown);
+	assert!(markdown.contains("Skilled workers."), "{}", markdown);
+	for left_out in ["tracking", "Menu", "Footer"] {
+		assert!(!markdown.contains(left_out), "{}", markdown);
+	}
+}
+
+#[test]
+fn progress_counts_the_files_of_each_stage() {
+	let progress = IndexProgress::default();
+	// Four files found, one skipped by its loader, one failing to embed and one waiting for its batch
+	for _ in 0..4 {
+		progress.found();
+		progress.read.enter();
+	}
+	progress.read.leave(4);
+	progress.skipped();
+	for _ in 0..3 {
+		progress.embed.enter();
+	}
+	progress.embed.leave(3);
+	progress.failed();
+	progress.upsert.enter();
+	progress.upsert.enter();
+	assert_eq!(progress.upsert.queued(), 2);
+	progress.upsert.leave(1);
+
+	assert_eq!(progress.indexed(), 1);
+	assert_eq!(progress.failed_count(), 1);
+	let report = progress.report();
+	assert!(report.starts_with("Indexed 1/4 files (1 skipped, 1 failed, still walking), "), "{}", report);
+	assert!(report.ends_with("queues: read 0, embed 0, upsert 1"), "{}", report);
+
+	progress.walk_finished();
+	let report = progress.report();
+	assert!(report.starts_with("Indexed 1/4 files (1 skipped, 1 failed), "), "{}", report);
+}
+
+#[tokio::test]
+async fn walk_lists_every_file_with_the_reason_it_is_skipped() {
+	let base = TempDir::new("walk");
+	write(&base.join("en/a.md"), "# A");
+	write(&base.join("en/b.html"), "<p>B</p>");
+	write(&base.join("en/.draft.md"), "Draft");
+	write(&base.join("en/image.png"), "PNG");
+	write(&base.join("en/big.md"), &"x".repeat(101));
+	write(&base.join("en/.embedignore"), "old/\n");
+	write(&base.join("en/old/c.md"), "# C");
+	write(&base.join("news/n.md"), "# N");
+	#[cfg(unix)]
+	std::os::unix::fs::symlink(base.join("missing.md"), base.join("dangling.md")).unwrap();
+
+	let config = DocumentsConfig {
+		exclude: vec!["news".into()],
+		max_file_size_bytes: 100,
+		..DocumentsConfig::default()
+	};
+	let rules = IndexRules::from_config(&config).unwrap();
+	let listed: Vec<_> = rules.walk(&base).collect().await;
+	let not_found = rules.walk(&base.join("missing")).collect::<Vec<_>>().await;
+	let ignored = rules.check(&base, &base.join("en/old/c.md")).await.unwrap();
+
+	let mut listed: Vec<(String, Option<SkipReason>)> = listed
+		.into_iter()
+		.map(|file| match file.unwrap() {
+			ListedFile::Included(path) => (path.strip_prefix(&base).unwrap().display().to_string(), None),
+			ListedFile::Skipped(path, reason) => (path.strip_prefix(&base).unwrap().display().to_string(), Some(reason))
+		})
+		.collect();
+	listed.sort_by(|a, b| a.0.cmp(&b.0));
+
+	// A dangling link is reported rather than ending the walk
+	#[cfg(unix)]
+	{
+		let (path, reason) = listed.remove(0);
+		assert_eq!(path, "dangling.md");
+		assert!(matches!(reason, Some(SkipReason::Unreadable(_))), "{:?}", reason);
+	}
+	let embedignore = base.join("en/.embedignore").display().to_string();
+	assert_eq!(
+		listed,
+		vec![
+			("en/.draft.md".to_string(), Some(SkipReason::Hidden)),
+			("en/.embedignore".to_string(), Some(SkipReason::Hidden)),
+			("en/a.md".to_string(), None),
+			("en/b.html".to_string(), None),
+			("en/big.md".to_string(), Some(SkipReason::TooLarge(101))),
+			("en/image.png".to_string(), Some(SkipReason::UnsupportedType("png".into()))),
+			("en/old".to_string(), Some(SkipReason::Ignored(format!("old/ in {}", embedignore)))),
+			("news".to_string(), Some(SkipReason::Excluded("news".into())))
+		]
+	);
+	// Only a documents directory that cannot be read at all fails the walk
+	assert_eq!(not_found.len(), 1);
+	assert!(not_found[0].is_err());
+	assert_eq!(ignored, Some(SkipReason::Ignored(format!("old/ in {}", embedignore))));
+}
+
+#[tokio::test]
+async fn checkpoint_resumes_an_interrupted_run() {
+	let dir = TempDir::new("checkpoint-resume");
+	let path = dir.join("default.jsonl");
+
+	let mut checkpoint = Checkpoint::create(&path, "docs").await.unwrap();
+	checkpoint.record(vec!["a.md".into(), "b.md".into()]).await.unwrap();
+	checkpoint.record(vec!["c.md".into()]).await.unwrap();
+	drop(checkpoint);
+	// As left by a run killed while writing a line
+	let mut content = std::fs::read_to_string(&path).unwrap();
+	content.push_str("{\"collection\":\"docs\",\"batch\":2,\"pa");
+	std::fs::write(&path, content).unwrap();
+
+	let mut resumed = Checkpoint::resume(&path, "docs").await.unwrap().unwrap();
+	let completed = sorted(resumed.completed());
+	let batches = resumed.batches();
+	resumed.record(vec!["d.md".into()]).await.unwrap();
+	drop(resumed);
+	let resumed_again = Checkpoint::resume(&path, "docs").await.unwrap().unwrap();
+	let other_collection = Checkpoint::resume(&path, "other").await;
+	let missing = Checkpoint::resume(&dir.join("missing.jsonl"), "docs").await.unwrap();
+
+	assert_eq!(completed, vec!["a.md", "b.md", "c.md"]);
+	assert_eq!(batches, 2);
+	// The batch cut short is written again after the line ends
+	assert_eq!(sorted(resumed_again.completed()), vec!["a.md", "b.md", "c.md", "d.md"]);
+	assert_eq!(resumed_again.batches(), 3);
+	assert!(other_collection.is_err());
+	assert!(missing.is_none());
+}
+
+#[tokio::test]
+async fn checkpoint_compacts_the_changes_of_a_watch() {
+	let dir = TempDir::new("checkpoint-compact");
+	let path = dir.join("default.jsonl");
+
+	let mut checkpoint = Checkpoint::create(&path, "docs").await.unwrap();
+	checkpoint.record(vec!["a.md".into(), "b.md".into()]).await.unwrap();
+	checkpoint.record_deleted(vec!["a.md".into()]).await.unwrap();
+	checkpoint.record(vec!["c.md".into()]).await.unwrap();
+	checkpoint.record(vec!["b.md".into()]).await.unwrap();
+	checkpoint.compact().await.unwrap();
+	let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+	let batches = checkpoint.batches();
+	checkpoint.record(vec!["d.md".into()]).await.unwrap();
+	drop(checkpoint);
+	let resumed = Checkpoint::resume(&path, "docs").await.unwrap().unwrap();
+
+	assert_eq!(lines, 1);
+	assert_eq!(batches, 1);
+	assert_eq!(sorted(resumed.completed()), vec!["b.md", "c.md", "d.md"]);
+	assert_eq!(resumed.batches(), 2);
+}
+
+#[tokio::test]
+async fn failures_are_reported_by_category_and_checked_against_the_thresholds() {
+	let timeout = tokio::time::timeout(Duration::from_millis(1), std::future::pending::<()>()).await.unwrap_err();
+	assert_eq!(FailureCategory::of_read(&timeout.into()), FailureCategory::ReadTimeout);
+	assert_eq!(FailureCategory::of_read(&std::io::Error::from(std::io::ErrorKind::PermissionDenied).into()), FailureCategory::Read);
+	assert_eq!(FailureCategory::of_read(&anyhow!("Invalid UTF-8")), FailureCategory::Decode);
+	assert_eq!(FailureCategory::of_embedding(&TokenizerError("Too long".into()).into()), FailureCategory::Tokenizer);
+	assert_eq!(FailureCategory::of_embedding(&anyhow!("Session failed")), FailureCategory::Onnx);
+
+	let failures = vec![
+		FileFailure::new(Path::new("/content/a.pdf"), FailureCategory::Decode, &anyhow!("Malformed PDF").context("Failed to load")),
+		FileFailure::new(Path::new("/content/b.md"), FailureCategory::ReadTimeout, &anyhow!("Timed out"))
+	];
+	let ircc = ProfileReport::new("ircc", "IRCC", 6, 3, failures);
+	assert_eq!(ircc.failed, 2);
+	// Skipped files are left out of the share
+	assert_eq!(ircc.failed_percent, 25.0);
+	assert_eq!(ircc.failures[0].error, "Failed to load: Malformed PDF");
+	let cra = ProfileReport::aborted("cra", "CRA", &anyhow!("Qdrant is unreachable"));
+	let empty = ProfileReport::new("esdc", "ESDC", 0, 0, Vec::new());
+	assert_eq!(empty.failed_percent, 0.0);
+
+	let thresholds = |max_failed_percent: f64, max_failed_files: Option<usize>| FailureThresholds {
+		max_failed_percent,
+		max_failed_files
+	};
+	let lenient = EmbedReport::new(vec![ircc.clone(), empty.clone()], &thresholds(100.0, None));
+	assert!(lenient.passed);
+	let strict = EmbedReport::new(vec![ircc, cra, empty], &thresholds(20.0, Some(1)));
+	assert!(!strict.passed);
+	assert_eq!(strict.violations, vec![
+		"profile ircc: 25.0% of the files failed, above the maximum of 20%",
+		"profile ircc: 2 files failed, above the maximum of 1",
+		"profile cra: Qdrant is unreachable"
+	]);
+
+	let dir = TempDir::new("embed-report");
+	let path = dir.join("reports/embed-report.json");
+	strict.write(&path).await.unwrap();
+	let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+	assert_eq!(written["passed"], false);
+	assert_eq!(written["profiles"][0]["failed_by_category"], serde_json::json!({ "read_timeout": 1, "decode": 1 }));
+	assert_eq!(written["profiles"][0]["failures"][1]["category"], "read_timeout");
+	assert_eq!(written["profiles"][1]["error"], "Qdrant is unreachable");
+}
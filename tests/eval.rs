@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ircc_ai::eval::answers::{AnswerMetrics, AnswerResult};
+use ircc_ai::eval::judge::{default_rubric, Judge, JudgeScores};
+use ircc_ai::eval::metrics::{Metrics, Scores};
+use ircc_ai::eval::{diff, load_configurations, load_golden_set, GoldenQuestion, NamedMetrics};
+use ircc_ai::llm::{ChatClient, ScriptedClient, ScriptedResponse};
+use ircc_ai::prelude::*;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, ChatCompletionResponse};
+use serde_json::json;
+
+use self::common::TempDir;
+
+mod common;
+
+fn paths(paths: &[&str]) -> Vec<String> {
+	paths.iter().map(|path| path.to_string()).collect()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+	assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+}
+
+#[test]
+fn scores_rank_the_expected_documents() {
+	let scores = Scores::new(&paths(&["a.md", "x.md", "b.md"]), &paths(&["a.md", "b.md", "c.md"]), &[1, 3]);
+
+	assert_close(scores.recall[&1], 1.0 / 3.0);
+	assert_close(scores.recall[&3], 2.0 / 3.0);
+	assert_close(scores.reciprocal_rank, 1.0);
+	assert_close(scores.ndcg[&1], 1.0);
+	// Found at ranks 1 and 3 out of an ideal of ranks 1, 2 and 3
+	assert_close(scores.ndcg[&3], (1.0 + 1.0 / 4f64.log2()) / (1.0 + 1.0 / 3f64.log2() + 1.0 / 4f64.log2()));
+
+	let late = Scores::new(&paths(&["x.md", "y.md", "a.md"]), &paths(&["a.md"]), &[1, 3]);
+	assert_close(late.recall[&1], 0.0);
+	assert_close(late.recall[&3], 1.0);
+	assert_close(late.reciprocal_rank, 1.0 / 3.0);
+	assert_close(late.ndcg[&3], 0.5);
+
+	let missed = Scores::new(&paths(&["x.md"]), &paths(&["a.md"]), &[3]);
+	assert_close(missed.reciprocal_rank, 0.0);
+	assert_close(missed.ndcg[&3], 0.0);
+	let nothing_expected = Scores::new(&paths(&["x.md"]), &[], &[3]);
+	assert_close(nothing_expected.recall[&3], 0.0);
+	assert_close(nothing_expected.ndcg[&3], 0.0);
+}
+
+#[test]
+fn metrics_are_the_means_of_the_scores() {
+	let scores = [
+		Scores::new(&paths(&["a.md"]), &paths(&["a.md"]), &[1, 3]),
+		Scores::new(&paths(&["x.md", "b.md"]), &paths(&["b.md"]), &[1, 3])
+	];
+
+	let metrics = Metrics::mean(&scores);
+	assert_eq!(metrics.questions, 2);
+	assert_close(metrics.recall[&1], 0.5);
+	assert_close(metrics.recall[&3], 1.0);
+	assert_close(metrics.mrr, 0.75);
+	assert_eq!(
+		metrics.named().into_iter().map(|(name, _)| name).collect::<Vec<_>>(),
+		vec!["recall@1", "recall@3", "mrr", "ndcg@1", "ndcg@3"]
+	);
+
+	let empty = Metrics::mean(std::iter::empty());
+	assert_eq!(empty.questions, 0);
+	assert_close(empty.mrr, 0.0);
+}
+
+#[test]
+fn diff_matches_the_metrics_of_both_runs() {
+	let metrics = |values: &[(&str, &str, f64)]| -> NamedMetrics {
+		values
+			.iter()
+			.map(|(configuration, metric, value)| ((configuration.to_string(), "search_documents".to_string(), metric.to_string()), *value))
+			.collect()
+	};
+	let baseline = metrics(&[("baseline", "mrr", 0.5), ("baseline", "recall@3", 0.6), ("gone", "mrr", 0.4)]);
+	let current = metrics(&[("baseline", "mrr", 0.75), ("baseline", "recall@3", 0.6), ("new", "mrr", 0.9)]);
+
+	let diffs: BTreeMap<(String, String), (Option<f64>, Option<f64>, Option<f64>)> = diff(&baseline, &current)
+		.into_iter()
+		.map(|diff| ((diff.configuration, diff.metric), (diff.baseline, diff.current, diff.delta)))
+		.collect();
+	let get = |configuration: &str, metric: &str| diffs[&(configuration.to_string(), metric.to_string())];
+
+	assert_eq!(diffs.len(), 4);
+	assert_eq!(get("baseline", "mrr"), (Some(0.5), Some(0.75), Some(0.25)));
+	assert_eq!(get("baseline", "recall@3"), (Some(0.6), Some(0.6), Some(0.0)));
+	assert_eq!(get("gone", "mrr"), (Some(0.4), None, None));
+	assert_eq!(get("new", "mrr"), (None, Some(0.9), None));
+}
+
+#[tokio::test]
+async fn golden_sets_and_configurations_are_loaded() {
+	let dir = TempDir::new("eval-files");
+	let golden = dir.join("golden.jsonl");
+	std::fs::write(
+		&golden,
+		"{\"id\": \"ee\", \"question\": \"How do I apply?\", \"expected_paths\": [\"en/apply.md\"], \"path_query\": \"apply\"}\n\n\
+		 {\"question\": \"What does it cost?\", \"expected_paths\": [\"en/fees.md\"]}\n"
+	)
+	.unwrap();
+	let invalid = dir.join("invalid.jsonl");
+	std::fs::write(&invalid, "{\"question\": \"How do I apply?\", \"expected_paths\": []}\n{\"question\": \"Typo\", \"expected\": []}\n").unwrap();
+	let configurations = dir.join("configurations.toml");
+	std::fs::write(
+		&configurations,
+		"[[configurations]]\nname = \"baseline\"\n\n[[configurations]]\nname = \"more-files\"\nrelevant_files_limit = 5\n"
+	)
+	.unwrap();
+	let unknown = dir.join("unknown.toml");
+	std::fs::write(&unknown, "[[configurations]]\nname = \"typo\"\nrelevant_file_limit = 5\n").unwrap();
+
+	let questions = load_golden_set(&golden).await.unwrap();
+	let invalid_error = load_golden_set(&invalid).await.unwrap_err().to_string();
+	let configurations = load_configurations(&configurations).await.unwrap();
+	let unknown_error = load_configurations(&unknown).await.unwrap_err().to_string();
+
+	// Questions without an id are named after their line
+	assert_eq!(questions.iter().map(|question| question.id.as_str()).collect::<Vec<_>>(), vec!["ee", "3"]);
+	assert_eq!(questions[0].path_query.as_deref(), Some("apply"));
+	assert_eq!(questions[1].expected_paths, vec!["en/fees.md".to_string()]);
+	assert!(invalid_error.starts_with(&format!("Invalid question at {}:2", invalid.display())), "{}", invalid_error);
+
+	assert_eq!(configurations.len(), 2);
+	assert_eq!(configurations[1].name, "more-files");
+	assert_eq!(configurations[1].relevant_files_limit, Some(5));
+	assert_eq!(configurations[0].relevant_files_limit, None);
+	assert!(unknown_error.contains("relevant_file_limit"), "{}", unknown_error);
+}
+
+fn answer(answer: Option<&str>, expected_retrieved: f64, grounding: Option<(f64, f64)>, judge: &[(&str, f64)]) -> AnswerResult {
+	AnswerResult {
+		id: "1".into(),
+		question: "How do I apply?".into(),
+		error: answer.is_none().then(|| "The script has no response left for this request".to_string()),
+		answer: answer.map(str::to_string),
+		trace: Vec::new(),
+		retrieved: Vec::new(),
+		citations: Vec::new(),
+		expected_retrieved,
+		groundedness: grounding.map(|(groundedness, _)| groundedness),
+		citation_validity: grounding.map(|(_, citation_validity)| citation_validity),
+		judge: (!judge.is_empty()).then(|| JudgeScores {
+			scores: judge.iter().map(|(criterion, score)| (criterion.to_string(), *score)).collect(),
+			rationale: String::new()
+		})
+	}
+}
+
+#[test]
+fn answer_metrics_average_over_the_answers_they_apply_to() {
+	let results = [
+		answer(Some("Apply online."), 1.0, Some((1.0, 1.0)), &[("correctness", 4.0)]),
+		answer(Some("Apply online or by mail."), 0.5, Some((0.5, 0.5)), &[("correctness", 2.0), ("clarity", 5.0)]),
+		answer(Some("Apply."), 0.0, None, &[]),
+		answer(None, 0.0, None, &[])
+	];
+
+	let metrics = AnswerMetrics::mean(&results);
+	assert_eq!(metrics.questions, 4);
+	assert_close(metrics.answered, 0.75);
+	assert_eq!(metrics.uncited, 1);
+	// Citations are averaged over the answers citing documents, the rest over the answered questions
+	assert_close(metrics.grounded, 0.5);
+	assert_close(metrics.groundedness, 0.75);
+	assert_close(metrics.citation_validity, 0.75);
+	assert_close(metrics.expected_retrieved, 0.5);
+	// Each criterion over the answers judged on it
+	assert_close(metrics.judge["correctness"], 3.0);
+	assert_close(metrics.judge["clarity"], 5.0);
+	assert_eq!(metrics.named().into_iter().map(|(name, _)| name).collect::<Vec<_>>(), vec![
+		"answered",
+		"grounded",
+		"groundedness",
+		"citation_validity",
+		"expected_retrieved",
+		"judge.clarity",
+		"judge.correctness"
+	]);
+}
+
+fn question() -> GoldenQuestion {
+	GoldenQuestion {
+		id: "ee".into(),
+		question: "How do I apply for Express Entry?".into(),
+		expected_paths: Vec::new(),
+		path_query: None,
+		responses: Vec::new(),
+		reference_answer: Some("Create a profile online.".into())
+	}
+}
+
+fn judge_replying(reply: &str) -> Judge {
+	let llm = ScriptedClient::new(vec![ScriptedResponse::Content { content: reply.into() }]);
+	Judge::new(Arc::new(llm), "gpt-4".into(), default_rubric())
+}
+
+// Replies without any choice, as the API sometimes does
+struct NoChoicesClient;
+
+#[async_trait]
+impl ChatClient for NoChoicesClient {
+	async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+		Ok(serde_json::from_value(json!({
+			"id": "no-choices",
+			"object": "chat.completion",
+			"created": 0,
+			"model": request.model,
+			"choices": [],
+			"usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }
+		}))?)
+	}
+}
+
+async fn judge_error(reply: &str) -> String {
+	judge_replying(reply).score(&question(), "Apply.").await.unwrap_err().to_string()
+}
+
+#[tokio::test]
+async fn judge_scores_every_criterion_of_the_rubric() {
+	let reply = "```json\n{\"scores\": {\"correctness\": 6, \"completeness\": 4, \"clarity\": 0.5, \"tone\": 3}, \"rationale\": \"Accurate.\"}\n```";
+	let scores = judge_replying(reply).score(&question(), "Create a profile online.").await.unwrap();
+
+	// Out of range scores are clamped and criteria outside the rubric dropped
+	assert_eq!(scores.scores, BTreeMap::from([("clarity".to_string(), 1.0), ("completeness".to_string(), 4.0), ("correctness".to_string(), 5.0)]));
+	assert_eq!(scores.rationale, "Accurate.");
+}
+
+#[tokio::test]
+async fn invalid_judge_replies_are_errors() {
+	let missing = judge_error("{\"scores\": {\"correctness\": 4, \"completeness\": 4}}").await;
+	assert!(missing.starts_with("The judge did not score clarity"), "{}", missing);
+	let not_json = judge_error("Looks good to me").await;
+	assert!(not_json.starts_with("The judge did not reply with JSON"), "{}", not_json);
+
+	let no_choices = Judge::new(Arc::new(NoChoicesClient), "gpt-4".into(), default_rubric());
+	let error = no_choices.score(&question(), "Apply.").await.unwrap_err().to_string();
+	assert_eq!(error, "The judge replied with no choices");
+}
@@ -0,0 +1,55 @@
+use actix_web::{test, App};
+use ircc_ai::db::SearchFilter;
+use ircc_ai::routes::events::{AnswerPayload, ErrorPayload, QueryEvent, SearchDocumentsPayload, SearchPathPayload};
+use ircc_ai::routes::openapi::{event_catalogue, openapi_spec};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn catalogue_lists_every_event_with_its_schema() {
+	let app = test::init_service(App::new().service(event_catalogue)).await;
+	let events: Vec<Value> = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/events").to_request()).await;
+
+	let names: Vec<&str> = events.iter().filter_map(|event| event["event"].as_str()).collect();
+	assert_eq!(
+		names,
+		vec!["PROCESS_QUERY", "SEARCH_DOCUMENTS", "SEARCH_FILE", "SEARCH_PATH", "GET_LINKS", "GENERATE_RESPONSE", "DONE", "ERROR"]
+	);
+
+	// Every payload schema is registered with the OpenAPI document, only `GENERATE_RESPONSE` carries no data
+	for event in &events {
+		let has_payload = !event["payload"].is_null();
+		assert_eq!(has_payload, event["event"] != "GENERATE_RESPONSE", "{}", event);
+	}
+	let search_documents = events.iter().find(|event| event["event"] == "SEARCH_DOCUMENTS").unwrap();
+	let properties = &search_documents["payload"]["properties"];
+	assert!(properties.get("query").is_some() && properties.get("filter").is_some(), "{}", search_documents);
+}
+
+#[actix_web::test]
+async fn openapi_document_describes_the_routes() {
+	let app = test::init_service(App::new().service(openapi_spec)).await;
+	let document: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/openapi.json").to_request()).await;
+
+	for path in ["/query", "/events", "/openapi.json"] {
+		assert!(document["paths"].get(path).is_some(), "{} is missing", path);
+	}
+	assert!(document["components"]["securitySchemes"].get("api_key").is_some());
+}
+
+#[test]
+fn events_serialise_as_their_payload() {
+	let search = QueryEvent::SearchDocuments(SearchDocumentsPayload {
+		query: "express entry".into(),
+		filter: Some(SearchFilter::default())
+	});
+	assert_eq!(search.name(), "SEARCH_DOCUMENTS");
+	assert_eq!(search.into_data().unwrap()["query"], "express entry");
+
+	let search_path = QueryEvent::SearchPath(SearchPathPayload { path: "en/index.md".into() });
+	assert_eq!(search_path.into_data(), Some(json!({ "path": "en/index.md" })));
+
+	// Answers and errors are JSON strings, events without a payload have no data
+	assert_eq!(QueryEvent::Done(AnswerPayload("An answer".into())).into_data(), Some(json!("An answer")));
+	assert_eq!(QueryEvent::Error(ErrorPayload("Failed".into())).into_data(), Some(json!("Failed")));
+	assert_eq!(QueryEvent::GenerateResponse.into_data(), None);
+}
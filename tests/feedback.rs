@@ -0,0 +1,78 @@
+use chrono::Utc;
+use ircc_ai::constants::FEEDBACK_COMMENT_MAX_LENGTH;
+use ircc_ai::feedback::{AnswerRecord, Feedback, FeedbackStore, Rating};
+
+use self::common::TempDir;
+
+mod common;
+
+fn answer(answer_id: &str) -> AnswerRecord {
+	AnswerRecord {
+		answer_id: answer_id.to_string(),
+		query: "How do I apply for Express Entry?".to_string(),
+		sanitized_query: "apply Express Entry".to_string(),
+		retrieved_paths: vec!["en/express-entry.md".to_string()],
+		answer: "Create an Express Entry profile online.".to_string(),
+		prompt_version: "v1".to_string(),
+		profile: "ircc".to_string(),
+		created_at: Utc::now()
+	}
+}
+
+fn feedback(answer_id: &str, rating: Rating, comment: Option<&str>) -> Feedback {
+	Feedback {
+		answer_id: answer_id.to_string(),
+		rating,
+		comment: comment.map(str::to_string)
+	}
+}
+
+#[tokio::test]
+async fn feedback_is_joined_with_its_answer() {
+	let dir = TempDir::new("feedback");
+	let store = FeedbackStore::open(&dir).await.unwrap();
+	store.record_answer(&answer("rated")).await.unwrap();
+	store.record_answer(&answer("unrated")).await.unwrap();
+
+	assert!(store.record_feedback(feedback("rated", Rating::Up, None)).await.unwrap());
+	assert!(store.record_feedback(feedback("rated", Rating::Down, Some("Out of date"))).await.unwrap());
+	// Feedback on answers the store never saw is dropped
+	assert!(!store.record_feedback(feedback("unknown", Rating::Up, None)).await.unwrap());
+
+	let all = store.export(false).await.unwrap();
+	let rated = store.export(true).await.unwrap();
+	let feedback_file = std::fs::read_to_string(dir.join("feedback.jsonl")).unwrap();
+
+	assert_eq!(all.iter().map(|item| item.answer.answer_id.as_str()).collect::<Vec<_>>(), vec!["rated", "unrated"]);
+	assert_eq!(rated.len(), 1);
+	let ratings: Vec<(Rating, Option<String>)> = rated[0].feedback.iter().map(|record| (record.feedback.rating, record.feedback.comment.clone())).collect();
+	assert_eq!(ratings, vec![(Rating::Up, None), (Rating::Down, Some("Out of date".to_string()))]);
+	assert!(!feedback_file.contains("unknown"));
+}
+
+#[tokio::test]
+async fn answers_are_known_after_reopening() {
+	let dir = TempDir::new("feedback-reopened");
+	FeedbackStore::open(&dir).await.unwrap().record_answer(&answer("earlier")).await.unwrap();
+	// A line cut short by a crash is skipped
+	std::fs::write(dir.join("answers.jsonl"), format!("{}{{\"answer_id\": \"trunc", std::fs::read_to_string(dir.join("answers.jsonl")).unwrap())).unwrap();
+
+	let store = FeedbackStore::open(&dir).await.unwrap();
+	let recorded = store.record_feedback(feedback("earlier", Rating::Up, None)).await.unwrap();
+	let items = store.export(true).await.unwrap();
+
+	assert!(recorded);
+	assert_eq!(items.len(), 1);
+	assert_eq!(items[0].answer.profile, "ircc");
+}
+
+#[test]
+fn long_comments_are_rejected() {
+	let comment = "a".repeat(FEEDBACK_COMMENT_MAX_LENGTH);
+	assert!(feedback("id", Rating::Down, Some(&comment)).validate().is_ok());
+	// Counted in characters, not bytes
+	let comment = "é".repeat(FEEDBACK_COMMENT_MAX_LENGTH);
+	assert!(feedback("id", Rating::Down, Some(&comment)).validate().is_ok());
+	let comment = "a".repeat(FEEDBACK_COMMENT_MAX_LENGTH + 1);
+	assert!(feedback("id", Rating::Down, Some(&comment)).validate().is_err());
+}
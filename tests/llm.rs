@@ -0,0 +1,228 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ircc_ai::config::{OpenAIConfig, Secret};
+use ircc_ai::constants::LLM_BREAKER_FAILURE_THRESHOLD;
+use ircc_ai::llm::breaker::CircuitBreaker;
+use ircc_ai::llm::{BreakerState, ChatClient, LlmError, OpenAIClient};
+use ircc_ai::prelude::*;
+use openai_api_rs::v1::chat_completion::{ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, MessageRole};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+// Answers each request with the next status of the script, the last one repeating, and counts the requests
+struct StubApi {
+	address: SocketAddr,
+	requests: Arc<AtomicUsize>
+}
+
+async fn stub_api(statuses: Vec<u16>, delay: Duration) -> StubApi {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let address = listener.local_addr().unwrap();
+	let requests = Arc::new(AtomicUsize::new(0));
+	let statuses = Arc::new(Mutex::new(statuses));
+
+	let counter = requests.clone();
+	tokio::spawn(async move {
+		while let Ok((mut stream, _)) = listener.accept().await {
+			let (counter, statuses) = (counter.clone(), statuses.clone());
+			tokio::spawn(async move {
+				// The headers, then as much of the body as announced
+				let mut request = Vec::new();
+				let mut buffer = [0; 4096];
+				loop {
+					match stream.read(&mut buffer).await {
+						Ok(0) | Err(_) => return,
+						Ok(read) => request.extend_from_slice(&buffer[..read])
+					}
+					let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") else {
+						continue;
+					};
+					let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+					let length = head
+						.lines()
+						.find_map(|line| line.strip_prefix("content-length:"))
+						.and_then(|length| length.trim().parse::<usize>().ok())
+						.unwrap_or_default();
+					if request.len() >= end + 4 + length {
+						break;
+					}
+				}
+
+				counter.fetch_add(1, Ordering::SeqCst);
+				let status = {
+					let mut statuses = statuses.lock().unwrap();
+					if statuses.len() > 1 {
+						statuses.remove(0)
+					} else {
+						statuses[0]
+					}
+				};
+				tokio::time::sleep(delay).await;
+
+				let body = if status == 200 { completion().to_string() } else { json!({ "error": { "message": "stub" } }).to_string() };
+				let response = format!(
+					"HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+					status,
+					body.len(),
+					body
+				);
+				let _ = stream.write_all(response.as_bytes()).await;
+			});
+		}
+	});
+
+	StubApi { address, requests }
+}
+
+fn completion() -> serde_json::Value {
+	json!({
+		"id": "chatcmpl-stub",
+		"object": "chat.completion",
+		"created": 1700000000,
+		"model": "gpt-3.5-turbo",
+		"choices": [{ "index": 0, "message": { "role": "assistant", "content": "Hello" }, "finish_reason": "stop" }],
+		"usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 }
+	})
+}
+
+fn client(api: &StubApi, max_retries: u32, request_timeout_secs: u32) -> OpenAIClient {
+	OpenAIClient::new(&OpenAIConfig {
+		api_key: Some(Secret::new("test-key")),
+		api_base: format!("http://{}/v1/", api.address),
+		request_timeout_secs,
+		max_retries,
+		..OpenAIConfig::default()
+	})
+	.unwrap()
+}
+
+fn request() -> ChatCompletionRequest {
+	ChatCompletionRequest {
+		model: "gpt-3.5-turbo".into(),
+		messages: vec![ChatCompletionMessage {
+			name: None,
+			function_call: None,
+			role: MessageRole::user,
+			content: "Hi".into()
+		}],
+		functions: None,
+		function_call: None,
+		temperature: None,
+		top_p: None,
+		n: None,
+		stream: None,
+		stop: None,
+		max_tokens: None,
+		presence_penalty: None,
+		frequency_penalty: None,
+		logit_bias: None,
+		user: None
+	}
+}
+
+fn llm_error(result: Result<ChatCompletionResponse>) -> LlmError {
+	match result {
+		Ok(_) => panic!("The request succeeded"),
+		Err(e) => e.downcast::<LlmError>().unwrap()
+	}
+}
+
+#[tokio::test]
+async fn server_errors_and_rate_limits_are_retried() {
+	let api = stub_api(vec![500, 429, 200], Duration::ZERO).await;
+	let client = client(&api, 3, 5);
+
+	let response = client.chat_completion(request()).await.unwrap();
+	assert_eq!(response.choices[0].message.content.as_deref(), Some("Hello"));
+	assert_eq!(api.requests.load(Ordering::SeqCst), 3);
+	assert_eq!(client.state(), BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+	let api = stub_api(vec![400], Duration::ZERO).await;
+	let client = client(&api, 3, 5);
+
+	assert!(matches!(llm_error(client.chat_completion(request()).await), LlmError::Status(400, _)));
+	assert_eq!(api.requests.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn slow_responses_time_out() {
+	let api = stub_api(vec![200], Duration::from_secs(3)).await;
+	let client = client(&api, 0, 1);
+
+	assert!(matches!(llm_error(client.chat_completion(request()).await), LlmError::Timeout));
+}
+
+#[tokio::test]
+async fn repeated_failures_open_the_breaker() {
+	let api = stub_api(vec![503], Duration::ZERO).await;
+	let client = client(&api, 0, 5);
+
+	for _ in 0..LLM_BREAKER_FAILURE_THRESHOLD {
+		assert!(matches!(llm_error(client.chat_completion(request()).await), LlmError::Status(503, _)));
+	}
+	assert_eq!(client.state(), BreakerState::Open);
+
+	// Fails fast without reaching the API
+	assert!(matches!(llm_error(client.chat_completion(request()).await), LlmError::Unavailable));
+	assert_eq!(api.requests.load(Ordering::SeqCst), LLM_BREAKER_FAILURE_THRESHOLD as usize);
+}
+
+// Sends a request through the breaker that fails
+fn fail(breaker: &CircuitBreaker) {
+	breaker.allow().unwrap().record_failure();
+}
+
+#[test]
+fn breaker_lets_a_single_trial_through_after_the_reset_timeout() {
+	let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+
+	fail(&breaker);
+	assert_eq!(breaker.state(), BreakerState::Closed);
+	fail(&breaker);
+	assert_eq!(breaker.state(), BreakerState::Open);
+	assert!(breaker.allow().is_none());
+
+	std::thread::sleep(Duration::from_millis(100));
+	assert_eq!(breaker.state(), BreakerState::HalfOpen);
+	let trial = breaker.allow().unwrap();
+	assert!(breaker.allow().is_none());
+
+	// A failed trial opens the breaker for another reset timeout
+	trial.record_failure();
+	assert_eq!(breaker.state(), BreakerState::Open);
+
+	std::thread::sleep(Duration::from_millis(100));
+	breaker.allow().unwrap().record_success();
+	assert_eq!(breaker.state(), BreakerState::Closed);
+	assert!(breaker.allow().is_some());
+}
+
+#[test]
+fn abandoned_trials_free_the_breaker() {
+	let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+	fail(&breaker);
+	std::thread::sleep(Duration::from_millis(100));
+
+	// As when the conversation waiting for the trial is cancelled
+	drop(breaker.allow().unwrap());
+
+	assert_eq!(breaker.state(), BreakerState::HalfOpen);
+	assert!(breaker.allow().is_some());
+}
+
+#[test]
+fn success_resets_the_failure_count() {
+	let breaker = CircuitBreaker::new(2, Duration::from_secs(30));
+
+	fail(&breaker);
+	breaker.allow().unwrap().record_success();
+	fail(&breaker);
+	assert_eq!(breaker.state(), BreakerState::Closed);
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use ircc_ai::config::{Config, ProfileConfig};
+use ircc_ai::convrsation::profiles::Profiles;
+
+fn profile(name: &str, collection: &str, domain: &str, agency_name: &str, languages: &[&str]) -> ProfileConfig {
+	ProfileConfig {
+		name: name.into(),
+		collection: collection.into(),
+		documents_path: format!("/content/{}", name).into(),
+		domain: domain.into(),
+		agency_name: agency_name.into(),
+		site_base_url: "https://www.canada.ca".into(),
+		strip_suffix: Some(".md".into()),
+		languages: languages.iter().map(|language| language.to_string()).collect()
+	}
+}
+
+// No request reaches Qdrant, its client connects on first use
+fn config(profiles: Vec<ProfileConfig>) -> Config {
+	let mut config = Config::default();
+	config.qdrant.url = "http://localhost:6334".into();
+	config.prompts.path = Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts");
+	config.profiles = profiles;
+	config
+}
+
+#[tokio::test]
+async fn queries_are_answered_from_the_profile_they_name() {
+	let profiles = Profiles::from_config(&config(vec![
+		profile("ircc", "IRCC", "immigration to Canada", "Immigration, Refugees and Citizenship Canada", &["English", "French"]),
+		profile("cra", "CRA", "taxes and benefits in Canada", "Canada Revenue Agency", &["English"])
+	]))
+	.unwrap();
+
+	let default = profiles.get(None).unwrap();
+	assert_eq!(default.name, "ircc");
+	assert_eq!(default.db.collection(), "IRCC");
+
+	let cra = profiles.get(Some("cra")).unwrap();
+	assert_eq!(cra.db.collection(), "CRA");
+	assert_eq!(cra.languages, vec!["English".to_string()]);
+	// Each profile has prompts and citation links of its own
+	let system_message = cra.prompts.pick().system_message();
+	assert!(system_message.contains("taxes and benefits in Canada"), "{}", system_message);
+	assert!(system_message.contains("Canada Revenue Agency"), "{}", system_message);
+	assert!(!system_message.contains("Immigration"), "{}", system_message);
+	assert_eq!(cra.search.url_for("/content/cra/en/services/taxes.md"), "https://www.canada.ca/en/services/taxes");
+
+	let unknown = profiles.get(Some("esdc")).err().unwrap().to_string();
+	assert_eq!(unknown, "Unknown profile esdc, use one of: ircc, cra");
+	assert_eq!(profiles.iter().count(), 2);
+}
+
+#[tokio::test]
+async fn top_level_settings_make_the_default_profile() {
+	let mut config = config(Vec::new());
+	config.qdrant.collection = "Documents".into();
+	let profiles = Profiles::from_config(&config).unwrap();
+
+	let default = profiles.get(Some("default")).unwrap();
+	assert_eq!(default.db.collection(), "Documents");
+	assert_eq!(default.search.documents_path, config.documents.path);
+}
+
+#[tokio::test]
+async fn profiles_need_valid_prompts() {
+	let mut config = config(vec![profile("ircc", "IRCC", "immigration to Canada", "IRCC", &["English"])]);
+	config.prompts.versions = vec!["missing".into()];
+
+	assert!(Profiles::from_config(&config).is_err());
+}
@@ -0,0 +1,78 @@
+use ircc_ai::config::{ChatConfig, ChatModelConfig};
+use ircc_ai::constants::CHAT_COMPLETION_MAX_TOKENS_LIMIT;
+use ircc_ai::convrsation::settings::{ChatSettings, ModelOverrides, ModelSettings};
+
+fn chat_settings() -> ChatSettings {
+	ChatSettings::from_config(&ChatConfig {
+		planning: ChatModelConfig {
+			model: "gpt-3.5-turbo".into(),
+			temperature: 0.0,
+			max_tokens: None
+		},
+		answer: ChatModelConfig {
+			model: "gpt-4".into(),
+			temperature: 0.7,
+			max_tokens: Some(800)
+		},
+		allowed_models: vec!["gpt-4-turbo".into()]
+	})
+}
+
+fn overrides(model: Option<&str>, temperature: Option<f64>, max_tokens: Option<u32>) -> ModelOverrides {
+	ModelOverrides {
+		model: model.map(str::to_string),
+		temperature,
+		max_tokens
+	}
+}
+
+#[test]
+fn deployment_settings_apply_without_overrides() {
+	let settings = chat_settings().resolve(None, Some(&ModelOverrides::default())).unwrap();
+
+	assert_eq!(settings.planning, ModelSettings {
+		model: "gpt-3.5-turbo".into(),
+		temperature: 0.0,
+		max_tokens: None
+	});
+	assert_eq!(settings.answer, ModelSettings {
+		model: "gpt-4".into(),
+		temperature: 0.7,
+		max_tokens: Some(800)
+	});
+}
+
+#[test]
+fn overrides_replace_only_what_they_set() {
+	let planning = overrides(None, Some(0.2), None);
+	let answer = overrides(Some("gpt-4-turbo"), None, Some(1000));
+	let settings = chat_settings().resolve(Some(&planning), Some(&answer)).unwrap();
+
+	assert_eq!(settings.planning, ModelSettings {
+		model: "gpt-3.5-turbo".into(),
+		temperature: 0.2,
+		max_tokens: None
+	});
+	assert_eq!(settings.answer, ModelSettings {
+		model: "gpt-4-turbo".into(),
+		temperature: 0.7,
+		max_tokens: Some(1000)
+	});
+
+	// The planning and answer models are allowed in either role
+	let planning = overrides(Some("gpt-4"), None, None);
+	assert_eq!(chat_settings().resolve(Some(&planning), None).unwrap().planning.model, "gpt-4");
+}
+
+#[test]
+fn overrides_out_of_bounds_are_rejected() {
+	let settings = chat_settings();
+	let rejected = |overrides: ModelOverrides| settings.resolve(None, Some(&overrides)).unwrap_err().to_string();
+
+	assert!(rejected(overrides(Some("gpt-5"), None, None)).starts_with("Model gpt-5 is not allowed"));
+	assert!(rejected(overrides(None, Some(2.5), None)).starts_with("Temperature must be between 0 and 2"));
+	assert!(rejected(overrides(None, Some(-0.1), None)).starts_with("Temperature must be between 0 and 2"));
+	assert!(rejected(overrides(None, None, Some(0))).starts_with("Max tokens must be between 1"));
+	assert!(rejected(overrides(None, None, Some(CHAT_COMPLETION_MAX_TOKENS_LIMIT + 1))).starts_with("Max tokens must be between 1"));
+	assert!(settings.resolve(None, Some(&overrides(None, Some(2.0), Some(CHAT_COMPLETION_MAX_TOKENS_LIMIT)))).is_ok());
+}
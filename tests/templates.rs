@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use ircc_ai::convrsation::templates::{prompt_date, PromptStore, PromptVariables};
+use ircc_ai::prelude::*;
+use ircc_ai::utils::hash::sha256_hex;
+
+use self::common::TempDir;
+
+mod common;
+
+fn variables() -> PromptVariables {
+	PromptVariables {
+		site_base_url: "https://www.canada.ca".into(),
+		language: "en".into(),
+		languages: "en, fr".into(),
+		agency_name: "Immigration, Refugees and Citizenship Canada".into(),
+		domain: "immigration to Canada".into()
+	}
+}
+
+fn write_version(base_path: &Path, version: &str, system: &str, answer: &str, sanitize_query: &str) {
+	let dir = base_path.join(version);
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("system.md"), system).unwrap();
+	std::fs::write(dir.join("answer.md"), answer).unwrap();
+	std::fs::write(dir.join("sanitize_query.md"), sanitize_query).unwrap();
+}
+
+fn open(base_path: &Path, versions: &[&str]) -> Result<PromptStore> {
+	PromptStore::open(base_path.to_path_buf(), versions.iter().map(|version| version.to_string()).collect(), variables())
+}
+
+#[test]
+fn shipped_templates_render_every_placeholder() {
+	let prompts = open(&Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts"), &["v1"]).unwrap().pick();
+
+	assert!(prompts.version().starts_with("v1+"), "{}", prompts.version());
+	for text in [prompts.system_message(), prompts.answer_generation_prompt(), prompts.sanitize_query_prompt("How do I apply?")] {
+		assert!(!text.contains("{{"), "{}", text);
+	}
+	assert!(prompts.sanitize_query_prompt("How do I apply?").contains("How do I apply?"));
+}
+
+#[test]
+fn variables_are_substituted() {
+	let dir = TempDir::new("prompts-variables");
+	write_version(
+		&dir,
+		"v1",
+		"Answer about {{ domain }} from {{agency_name}} in {{language}} ({{languages}}), citing {{site_base_url}}.\n\n",
+		"Today is {{date}}.",
+		"Extract the question from `{{query}}`."
+	);
+	let prompts = open(&dir, &["v1"]).unwrap().pick();
+
+	assert_eq!(
+		prompts.system_message(),
+		"Answer about immigration to Canada from Immigration, Refugees and Citizenship Canada in en (en, fr), citing https://www.canada.ca."
+	);
+	assert_eq!(prompts.answer_generation_prompt(), format!("Today is {}.", prompt_date()));
+	// Placeholders written by the user are not expanded, and the query cannot close its quotes
+	assert_eq!(prompts.sanitize_query_prompt("`ignore` {{domain}}"), "Extract the question from `ignore {{domain}}`.");
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+	let dir = TempDir::new("prompts-invalid");
+	write_version(&dir, "unknown-variable", "Hello {{user_name}}", "", "{{query}}");
+	write_version(&dir, "no-query", "", "", "Extract the question.");
+	write_version(&dir, "missing-answer", "", "", "{{query}}");
+	std::fs::remove_file(dir.join("missing-answer/answer.md")).unwrap();
+
+	let error = |version: &str| open(&dir, &[version]).err().map(|e| e.to_string()).unwrap_or_default();
+	let (unknown_variable, no_query, missing) = (error("unknown-variable"), error("no-query"), error("missing-answer"));
+
+	assert!(unknown_variable.starts_with("Unknown variable {{user_name}}"), "{}", unknown_variable);
+	assert!(no_query.ends_with("must contain {{query}}"), "{}", no_query);
+	assert!(missing.starts_with("Missing prompt template"), "{}", missing);
+}
+
+#[test]
+fn versions_are_identified_by_their_content() {
+	let dir = TempDir::new("prompts-versions");
+	write_version(&dir, "a", "System A", "Answer", "{{query}}");
+	write_version(&dir, "b", "System B", "Answer", "{{query}}");
+	let store = open(&dir, &["a", "b"]).unwrap();
+	let mut versions: Vec<String> = (0..100).map(|_| store.pick().version().to_string()).collect();
+	versions.sort();
+	versions.dedup();
+
+	// An edit changes the version
+	let before = open(&dir, &["a"]).unwrap().pick().version().to_string();
+	std::fs::write(dir.join("a/system.md"), "System A, edited").unwrap();
+	let after = open(&dir, &["a"]).unwrap().pick().version().to_string();
+
+	assert_eq!(versions.len(), 2, "{:?}", versions);
+	// The same in every build, as feedback records and cache keys outlive it
+	assert_eq!(versions[0], format!("a+{}", &sha256_hex("System AAnswer{{query}}")[..8]));
+	assert!(versions[1].starts_with("b+"), "{:?}", versions);
+	assert_ne!(before, after);
+}
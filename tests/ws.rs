@@ -0,0 +1,79 @@
+use ircc_ai::feedback::Rating;
+use ircc_ai::routes::pipeline::InFlightQueries;
+use ircc_ai::routes::ws::{ClientMessage, ServerMessage};
+use serde_json::{json, Value};
+
+fn parse(message: Value) -> ClientMessage {
+	serde_json::from_value(message).unwrap()
+}
+
+fn serialize(message: ServerMessage) -> Value {
+	serde_json::to_value(message).unwrap()
+}
+
+#[test]
+fn client_messages_are_tagged_by_type() {
+	let ClientMessage::Question(query) = parse(json!({ "type": "question", "query": "And for my spouse?", "profile": "ircc" })) else {
+		panic!("Not a question");
+	};
+	assert_eq!(query.query, "And for my spouse?");
+	assert_eq!(query.profile.as_deref(), Some("ircc"));
+	assert!(!query.has_overrides());
+
+	assert!(matches!(parse(json!({ "type": "cancel" })), ClientMessage::Cancel));
+
+	let ClientMessage::Feedback(feedback) = parse(json!({ "type": "feedback", "answer_id": "answer-1", "rating": "down", "comment": "Wrong" })) else {
+		panic!("Not feedback");
+	};
+	assert_eq!((feedback.answer_id.as_str(), feedback.rating, feedback.comment.as_deref()), ("answer-1", Rating::Down, Some("Wrong")));
+
+	for invalid in [json!({ "type": "shutdown" }), json!({ "query": "No type" }), json!({ "type": "question" })] {
+		assert!(serde_json::from_value::<ClientMessage>(invalid.clone()).is_err(), "{}", invalid);
+	}
+}
+
+#[test]
+fn server_messages_are_tagged_by_type() {
+	assert_eq!(serialize(ServerMessage::Session { session_id: "session-1".into() }), json!({ "type": "session", "session_id": "session-1" }));
+	assert_eq!(
+		serialize(ServerMessage::Event {
+			event: "DONE",
+			data: Some(json!("An answer"))
+		}),
+		json!({ "type": "event", "event": "DONE", "data": "An answer" })
+	);
+	assert_eq!(
+		serialize(ServerMessage::Event {
+			event: "GENERATE_RESPONSE",
+			data: None
+		}),
+		json!({ "type": "event", "event": "GENERATE_RESPONSE", "data": null })
+	);
+	assert_eq!(serialize(ServerMessage::Cancelled { answer_id: "answer-1".into() }), json!({ "type": "cancelled", "answer_id": "answer-1" }));
+	assert_eq!(
+		serialize(ServerMessage::FeedbackRecorded { answer_id: "answer-1".into() }),
+		json!({ "type": "feedback_recorded", "answer_id": "answer-1" })
+	);
+	assert_eq!(
+		serialize(ServerMessage::Error {
+			error: "busy".into(),
+			message: "An answer is already in progress".into()
+		}),
+		json!({ "type": "error", "error": "busy", "message": "An answer is already in progress" })
+	);
+}
+
+#[test]
+fn answers_can_be_cancelled_once_registered() {
+	let in_flight = InFlightQueries::default();
+	assert!(!in_flight.cancel("answer-1"));
+
+	// A session registers the answer before it starts, the pipeline registers it again and must get the same token
+	let registered = in_flight.register("answer-1");
+	assert!(in_flight.cancel("answer-1"));
+	let started = in_flight.register("answer-1");
+	assert!(registered.is_cancelled());
+	assert!(started.is_cancelled());
+
+	assert!(!in_flight.register("answer-2").is_cancelled());
+}